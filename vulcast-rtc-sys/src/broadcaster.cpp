@@ -1,5 +1,6 @@
 #include "broadcaster.hpp"
 
+#include <algorithm>
 #include <chrono>
 #include <cstdlib>
 #include <ctime>
@@ -11,6 +12,8 @@
 #include <json.hpp>
 #include <mediasoupclient.hpp>
 
+#include "codec.hpp"
+#include "error.hpp"
 #include "media_stream_track_factory.hpp"
 
 using json = nlohmann::json;
@@ -26,65 +29,145 @@ void Broadcaster::Start() {
   auto factory = GetPeerConnectionFactory();
   mediasoupclient::PeerConnection::Options options;
   options.factory = factory.get();
-  this->device_.Load(routerRtpCapabilities, &options);
+  try {
+    this->device_.Load(routerRtpCapabilities, &options);
+  } catch (const std::exception &e) {
+    throw FfiException(ERR_DEVICE_LOAD, e.what());
+  }
 
   auto rtp_capabilities = device_.GetRtpCapabilities();
   signaller_.OnRtpCapabilities(rtp_capabilities);
 
-  this->CreateSendTransport();
-  this->CreateRecvTransport();
+  send_transport_ = this->CreateSendTransport().release();
+  recv_transport_ = this->CreateRecvTransport().release();
 }
 
-mediasoupclient::DataProducer *Broadcaster::ProduceData() {
-  LOG(INFO) << "Broadcaster::ProduceData()";
-  // unreliable, unordered transport (plumb the parameters up if you need
-  // something else)
-  return send_transport_->ProduceData(this, "", "", false, 0, 0);
+mediasoupclient::DataProducer *
+Broadcaster::ProduceData(bool ordered, uint16_t max_packet_life_time,
+                         uint16_t max_retransmits, const std::string &label,
+                         const std::string &protocol,
+                         const nlohmann::json &appdata) {
+  LOG(INFO) << "Broadcaster::ProduceData(" << label << ")";
+  return OpenSendTransport()->ProduceData(this, label, protocol, ordered,
+                                          max_retransmits, max_packet_life_time,
+                                          appdata);
 }
 mediasoupclient::DataConsumer *
 Broadcaster::ConsumeData(const std::string &data_consumer_id,
                          const std::string &data_producer_id,
-                         const nlohmann::json &sctp_stream_parameters) {
+                         const nlohmann::json &sctp_stream_parameters,
+                         const std::string &label,
+                         const std::string &protocol) {
   LOG(INFO) << "Broadcaster::CreateDataConsumer(" << data_producer_id << ")";
-  return recv_transport_->ConsumeData(
+  auto data_consumer = OpenRecvTransport()->ConsumeData(
       this, data_consumer_id, data_producer_id,
-      sctp_stream_parameters["streamId"].get<uint16_t>(), "");
+      sctp_stream_parameters["streamId"].get<uint16_t>(), label, protocol);
+  data_consumers_.erase(
+      std::remove_if(data_consumers_.begin(), data_consumers_.end(),
+                     [](auto *dc) { return dc->IsClosed(); }),
+      data_consumers_.end());
+  data_consumers_.push_back(data_consumer);
+  return data_consumer;
+}
+
+mediasoupclient::Consumer *
+Broadcaster::Consume(const std::string &consumer_id,
+                     const std::string &producer_id, const std::string &kind,
+                     nlohmann::json rtp_parameters) {
+  LOG(INFO) << "Broadcaster::Consume(" << consumer_id << "," << producer_id
+            << "," << kind << ")";
+  auto consumer = OpenRecvTransport()->Consume(this, consumer_id, producer_id,
+                                               kind, &rtp_parameters);
+  consumers_.erase(std::remove_if(consumers_.begin(), consumers_.end(),
+                                  [](auto *c) { return c->IsClosed(); }),
+                   consumers_.end());
+  consumers_.push_back(consumer);
+  return consumer;
 }
 
 mediasoupclient::Producer *Broadcaster::Produce(
     webrtc::MediaStreamTrackInterface *track,
     const std::vector<webrtc::RtpEncodingParameters> *encodings,
-    const nlohmann::json &codec_options, const nlohmann::json &appdata) {
+    const nlohmann::json &codec_options, const std::string &codec_mime_type,
+    const nlohmann::json &appdata) {
   LOG(INFO) << "Broadcaster::Produce(" << std::hex << track << "," << std::hex
-            << encodings << "," << codec_options << "," << appdata << ")";
-  return send_transport_->Produce(this, track, encodings, &codec_options,
-                                  nullptr, appdata);
+            << encodings << "," << codec_options << "," << codec_mime_type
+            << "," << appdata << ")";
+  if (!device_.CanProduce(track->kind())) {
+    throw FfiException(ERR_CANNOT_PRODUCE, track->kind());
+  }
+  auto *send_transport = OpenSendTransport();
+  if (codec_mime_type.empty()) {
+    return send_transport->Produce(this, track, encodings, &codec_options,
+                                   nullptr, appdata);
+  }
+  json codec = FindCodec(device_.GetRtpCapabilities(), codec_mime_type);
+  return send_transport->Produce(this, track, encodings, &codec_options,
+                                 &codec, appdata);
+}
+
+json Broadcaster::GetStats() {
+  LOG(INFO) << "Broadcaster::GetStats()";
+  return {{"send", OpenSendTransport()->GetStats()},
+          {"recv", OpenRecvTransport()->GetStats()}};
+}
+
+std::string Broadcaster::GetSendTransportId() const {
+  if (send_transport_ == nullptr) {
+    throw FfiException(ERR_TRANSPORT_CLOSED, "no send transport");
+  }
+  return send_transport_->GetId();
+}
+
+std::string Broadcaster::GetRecvTransportId() const {
+  if (recv_transport_ == nullptr) {
+    throw FfiException(ERR_TRANSPORT_CLOSED, "no recv transport");
+  }
+  return recv_transport_->GetId();
+}
+
+mediasoupclient::SendTransport *Broadcaster::OpenSendTransport() {
+  if (send_transport_ == nullptr || send_transport_->IsClosed()) {
+    throw FfiException(ERR_TRANSPORT_CLOSED, "send transport closed");
+  }
+  return send_transport_;
+}
+
+mediasoupclient::RecvTransport *Broadcaster::OpenRecvTransport() {
+  if (recv_transport_ == nullptr || recv_transport_->IsClosed()) {
+    throw FfiException(ERR_TRANSPORT_CLOSED, "recv transport closed");
+  }
+  return recv_transport_;
 }
 
-void Broadcaster::CreateSendTransport() {
+std::unique_ptr<mediasoupclient::SendTransport>
+Broadcaster::CreateSendTransport() {
   LOG(INFO) << "Broadcaster::CreateSendTransport()";
   auto response = signaller_.CreateWebrtcTransport();
 
   auto factory = GetPeerConnectionFactory();
   mediasoupclient::PeerConnection::Options options;
   options.factory = factory.get();
-  this->send_transport_ = device_.CreateSendTransport(
-      this, response["id"], response["iceParameters"],
-      response["iceCandidates"], response["dtlsParameters"],
-      response["sctpParameters"], &options);
+  return std::unique_ptr<mediasoupclient::SendTransport>(
+      device_.CreateSendTransport(
+          this, response["id"], response["iceParameters"],
+          response["iceCandidates"], response["dtlsParameters"],
+          response["sctpParameters"], &options));
 }
 
-void Broadcaster::CreateRecvTransport() {
+std::unique_ptr<mediasoupclient::RecvTransport>
+Broadcaster::CreateRecvTransport() {
   LOG(INFO) << "Broadcaster::CreateRecvTransport()";
   auto response = signaller_.CreateWebrtcTransport();
 
   auto factory = GetPeerConnectionFactory();
   mediasoupclient::PeerConnection::Options options;
   options.factory = factory.get();
-  this->recv_transport_ = device_.CreateRecvTransport(
-      this, response["id"], response["iceParameters"],
-      response["iceCandidates"], response["dtlsParameters"],
-      response["sctpParameters"], &options);
+  return std::unique_ptr<mediasoupclient::RecvTransport>(
+      device_.CreateRecvTransport(
+          this, response["id"], response["iceParameters"],
+          response["iceCandidates"], response["dtlsParameters"],
+          response["sctpParameters"], &options));
 }
 
 void Broadcaster::Stop() {
@@ -97,6 +180,58 @@ void Broadcaster::Stop() {
   }
 }
 
+void Broadcaster::RestartIce(const std::string &transport_id,
+                             const nlohmann::json &ice_parameters) {
+  LOG(INFO) << "Broadcaster::RestartIce(" << transport_id << ","
+            << ice_parameters << ")";
+  mediasoupclient::Transport *transport = nullptr;
+  if (send_transport_ && send_transport_->GetId() == transport_id) {
+    transport = send_transport_;
+  } else if (recv_transport_ && recv_transport_->GetId() == transport_id) {
+    transport = recv_transport_;
+  }
+  if (transport == nullptr || transport->IsClosed()) {
+    throw FfiException(ERR_TRANSPORT_CLOSED,
+                       "transport " + transport_id + " closed");
+  }
+  transport->RestartIce(ice_parameters);
+}
+
+void Broadcaster::RecreateTransports() {
+  LOG(INFO) << "Broadcaster::RecreateTransports()";
+  // create the new transports first, so that the current ones are kept if
+  // signalling fails
+  auto send_transport = this->CreateSendTransport();
+  auto recv_transport = this->CreateRecvTransport();
+  // close the consumers while their transport is still open, so that they
+  // no longer refer to it once it is deleted
+  for (auto *consumer : consumers_) {
+    if (!consumer->IsClosed()) {
+      consumer->Close();
+      signaller_.OnConsumerTransportClose(consumer->GetId());
+    }
+  }
+  consumers_.clear();
+  for (auto *data_consumer : data_consumers_) {
+    if (!data_consumer->IsClosed()) {
+      data_consumer->Close();
+      signaller_.OnDataConsumerStateChanged(data_consumer->GetId(), "closed");
+    }
+  }
+  data_consumers_.clear();
+  this->Stop();
+  closed_send_transports_.emplace_back(send_transport_);
+  closed_recv_transports_.emplace_back(recv_transport_);
+  send_transport_ = send_transport.release();
+  recv_transport_ = recv_transport.release();
+}
+
+void Broadcaster::DeleteClosedTransports() {
+  LOG(INFO) << "Broadcaster::DeleteClosedTransports()";
+  closed_send_transports_.clear();
+  closed_recv_transports_.clear();
+}
+
 /* SendTransport::Listener */
 std::future<void> Broadcaster::OnConnect(mediasoupclient::Transport *transport,
                                          const json &dtlsParameters) {
@@ -105,8 +240,12 @@ std::future<void> Broadcaster::OnConnect(mediasoupclient::Transport *transport,
   LOG(INFO) << "Broadcaster::OnConnect(" << transport->GetId() << ","
             << dtlsParameters << ")";
   std::promise<void> promise;
-  signaller_.OnConnectWebrtcTransport(transport->GetId(), dtlsParameters);
-  promise.set_value();
+  try {
+    signaller_.OnConnectWebrtcTransport(transport->GetId(), dtlsParameters);
+    promise.set_value();
+  } catch (...) {
+    promise.set_exception(std::current_exception());
+  }
   return promise.get_future();
 }
 
@@ -114,7 +253,9 @@ void Broadcaster::OnConnectionStateChange(mediasoupclient::Transport *transport,
                                           const std::string &connectionState) {
   LOG(INFO) << "Broadcaster::OnConnectionStateChange(" << transport->GetId()
             << "," << connectionState << ")";
-  signaller_.OnConnectionStateChanged(transport->GetId(), connectionState);
+  std::string direction = transport == send_transport_ ? "send" : "recv";
+  signaller_.OnConnectionStateChanged(transport->GetId(), direction,
+                                      connectionState);
 }
 
 std::future<std::string>
@@ -126,8 +267,12 @@ Broadcaster::OnProduce(mediasoupclient::SendTransport *transport,
   LOG(INFO) << "Broadcaster::OnProduce(" << transport->GetId() << "," << kind
             << "," << rtpParameters << "," << appData << ")";
   std::promise<std::string> promise;
-  promise.set_value(
-      signaller_.OnProduce(transport->GetId(), kind, rtpParameters));
+  try {
+    promise.set_value(
+        signaller_.OnProduce(transport->GetId(), kind, rtpParameters));
+  } catch (...) {
+    promise.set_exception(std::current_exception());
+  }
   return promise.get_future();
 }
 
@@ -142,8 +287,12 @@ Broadcaster::OnProduceData(mediasoupclient::SendTransport *transport,
             << sctpStreamParameters << "," << label << "," << protocol << ","
             << appData << ")";
   std::promise<std::string> promise;
-  promise.set_value(
-      signaller_.OnProduceData(transport->GetId(), sctpStreamParameters));
+  try {
+    promise.set_value(signaller_.OnProduceData(
+        transport->GetId(), sctpStreamParameters, label, protocol, appData));
+  } catch (...) {
+    promise.set_exception(std::current_exception());
+  }
   return promise.get_future();
 }
 
@@ -183,11 +332,21 @@ void Broadcaster::OnTransportClose(
     mediasoupclient::DataConsumer *data_consumer) {
   LOG(INFO) << "Broadcaster::OnTransportClose(" << data_consumer->GetId()
             << ")";
+  signaller_.OnDataConsumerStateChanged(
+      data_consumer->GetId(), webrtc::DataChannelInterface::DataStateString(
+                                  data_consumer->GetReadyState()));
 }
 
 /* Producer::Listener */
 void Broadcaster::OnTransportClose(mediasoupclient::Producer *producer) {
   LOG(INFO) << "Broadcaster::OnTransportClose(" << producer->GetId() << ")";
+  signaller_.OnProducerTransportClose(producer->GetId());
+}
+
+/* Consumer::Listener */
+void Broadcaster::OnTransportClose(mediasoupclient::Consumer *consumer) {
+  LOG(INFO) << "Broadcaster::OnTransportClose(" << consumer->GetId() << ")";
+  signaller_.OnConsumerTransportClose(consumer->GetId());
 }
 
 /* DataProducer::Listener */
@@ -202,7 +361,10 @@ void Broadcaster::OnClose(mediasoupclient::DataProducer *data_producer) {
                                   data_producer->GetReadyState()));
 }
 void Broadcaster::OnBufferedAmountChange(
-    mediasoupclient::DataProducer *data_producer, uint64_t sent_data_size) {}
+    mediasoupclient::DataProducer *data_producer, uint64_t sent_data_size) {
+  signaller_.OnDataProducerBufferedAmountChange(
+      data_producer->GetId(), data_producer->GetBufferedAmount());
+}
 void Broadcaster::OnTransportClose(
     mediasoupclient::DataProducer *data_producer) {
   signaller_.OnDataProducerStateChanged(
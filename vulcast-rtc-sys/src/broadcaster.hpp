@@ -3,8 +3,10 @@
 #include <chrono>
 #include <condition_variable>
 #include <future>
+#include <memory>
 #include <mutex>
 #include <string>
+#include <vector>
 
 #include <json.hpp>
 #include <mediasoupclient.hpp>
@@ -14,6 +16,7 @@
 class Broadcaster : public mediasoupclient::SendTransport::Listener,
                     public mediasoupclient::RecvTransport::Listener,
                     mediasoupclient::Producer::Listener,
+                    mediasoupclient::Consumer::Listener,
                     mediasoupclient::DataProducer::Listener,
                     mediasoupclient::DataConsumer::Listener {
 public:
@@ -37,6 +40,10 @@ public:
 public:
   void OnTransportClose(mediasoupclient::Producer *producer) override;
 
+  /* Consumer::Listener */
+public:
+  void OnTransportClose(mediasoupclient::Consumer *consumer) override;
+
   /* DataProducer::Listener */
 public:
   void OnOpen(mediasoupclient::DataProducer *dataProducer) override;
@@ -59,26 +66,55 @@ public:
   void Start();
   void Stop();
 
+  // Restart ICE on the transport with the given id using the new ICE
+  // parameters obtained from the server.
+  void RestartIce(const std::string &transport_id,
+                  const nlohmann::json &ice_parameters);
+  // Close both transports and create new ones. Producers of the old
+  // transports are closed and must be produced again by the caller. Consumers
+  // and data consumers are closed and reported as closed with their transport.
+  // The old transports are kept until DeleteClosedTransports().
+  void RecreateTransports();
+  // Delete the transports closed by RecreateTransports(), once the producers
+  // of the old send transport have been produced again.
+  void DeleteClosedTransports();
+  // Stats of both transports, as an object with "send" and "recv" arrays of
+  // RTCStats.
+  nlohmann::json GetStats();
+
   Broadcaster(Signaller signaller);
   virtual ~Broadcaster();
 
-  mediasoupclient::DataProducer *ProduceData();
+  mediasoupclient::DataProducer *
+  ProduceData(bool ordered = false, uint16_t max_packet_life_time = 0,
+              uint16_t max_retransmits = 0, const std::string &label = "",
+              const std::string &protocol = "",
+              const nlohmann::json &appdata = nlohmann::json::object());
   mediasoupclient::DataConsumer *
   ConsumeData(const std::string &data_consumer_id,
               const std::string &data_producer_id,
-              const nlohmann::json &sctp_stream_parameters);
+              const nlohmann::json &sctp_stream_parameters,
+              const std::string &label = "",
+              const std::string &protocol = "");
+
+  mediasoupclient::Consumer *Consume(const std::string &consumer_id,
+                                     const std::string &producer_id,
+                                     const std::string &kind,
+                                     nlohmann::json rtp_parameters);
 
   mediasoupclient::Producer *
   Produce(webrtc::MediaStreamTrackInterface *track,
           const std::vector<webrtc::RtpEncodingParameters> *encodings = nullptr,
           const nlohmann::json &codec_options = nlohmann::json::object(),
+          const std::string &codec_mime_type = "",
           const nlohmann::json &appdata = nlohmann::json::object());
 
   bool CanProduceAudio() { return device_.CanProduce("audio"); }
   bool CanProduceVideo() { return device_.CanProduce("video"); }
 
-  std::string GetSendTransportId() const { return send_transport_->GetId(); }
-  std::string GetRecvTransportId() const { return recv_transport_->GetId(); }
+  // Ids of the current transports, throwing if they were not created.
+  std::string GetSendTransportId() const;
+  std::string GetRecvTransportId() const;
 
 private:
   Signaller signaller_;
@@ -86,9 +122,23 @@ private:
   mediasoupclient::Device device_;
   mediasoupclient::SendTransport *send_transport_{nullptr};
   mediasoupclient::RecvTransport *recv_transport_{nullptr};
+  // closed by RecreateTransports(), producers of the old send transport may
+  // still refer to them
+  std::vector<std::unique_ptr<mediasoupclient::SendTransport>>
+      closed_send_transports_;
+  std::vector<std::unique_ptr<mediasoupclient::RecvTransport>>
+      closed_recv_transports_;
+  // consumers of the receive transport, closed when it is recreated
+  std::vector<mediasoupclient::Consumer *> consumers_;
+  std::vector<mediasoupclient::DataConsumer *> data_consumers_;
 
   std::string id = std::to_string(rtc::CreateRandomId());
 
-  void CreateSendTransport();
-  void CreateRecvTransport();
+  // Create a transport on the server and locally, without replacing the
+  // current one.
+  std::unique_ptr<mediasoupclient::SendTransport> CreateSendTransport();
+  std::unique_ptr<mediasoupclient::RecvTransport> CreateRecvTransport();
+  // The current transports, throwing if they were not created or are closed.
+  mediasoupclient::SendTransport *OpenSendTransport();
+  mediasoupclient::RecvTransport *OpenRecvTransport();
 };
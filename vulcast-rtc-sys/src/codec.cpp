@@ -0,0 +1,25 @@
+#include "codec.hpp"
+
+#include <algorithm>
+#include <cctype>
+
+#include "error.hpp"
+
+using json = nlohmann::json;
+
+namespace {
+std::string lower(std::string s) {
+  std::transform(s.begin(), s.end(), s.begin(),
+                 [](unsigned char c) { return std::tolower(c); });
+  return s;
+}
+} // namespace
+
+json FindCodec(const json &rtp_capabilities, const std::string &mime_type) {
+  for (const auto &codec : rtp_capabilities["codecs"]) {
+    if (lower(codec["mimeType"].get<std::string>()) == lower(mime_type)) {
+      return codec;
+    }
+  }
+  throw FfiException(ERR_CODEC_NOT_SUPPORTED, mime_type);
+}
@@ -0,0 +1,10 @@
+#pragma once
+
+#include <string>
+
+#include <json.hpp>
+
+// Find the codec with the given MIME type in the given RTP capabilities,
+// ignoring case. Throws ERR_CODEC_NOT_SUPPORTED if there is none.
+nlohmann::json FindCodec(const nlohmann::json &rtp_capabilities,
+                         const std::string &mime_type);
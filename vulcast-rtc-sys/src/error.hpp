@@ -0,0 +1,20 @@
+#pragma once
+
+#include <stdexcept>
+#include <string>
+
+#include "wrapper.hpp"
+
+// Exception carrying an ErrorKind. Thrown internally and converted into an
+// Error by the wrapper functions, so that it never unwinds across the FFI
+// boundary.
+class FfiException : public std::runtime_error {
+public:
+  FfiException(ErrorKind kind, const std::string &what)
+      : std::runtime_error(what), kind_(kind) {}
+
+  ErrorKind kind() const { return kind_; }
+
+private:
+  ErrorKind kind_;
+};
@@ -0,0 +1,61 @@
+#include "foreign_audio_source.hpp"
+
+#include <algorithm>
+
+#include <api/task_queue/default_task_queue_factory.h>
+
+#include "glog/logging.h"
+
+ForeignAudioSource::ForeignAudioSource(uint32_t sample_rate, size_t channels,
+                                       const void *ctx,
+                                       audio_source_callback_t callback)
+    : sample_rate_(sample_rate), channels_(channels),
+      frames_(sample_rate / 100), ctx_(ctx), callback_(callback),
+      task_queue_factory_(webrtc::CreateDefaultTaskQueueFactory()),
+      task_queue_(task_queue_factory_->CreateTaskQueue(
+          "ForeignAudioSource", webrtc::TaskQueueFactory::Priority::HIGH)),
+      buffer_(frames_ * channels_) {
+  pull_task_ = webrtc::RepeatingTaskHandle::Start(task_queue_.get(),
+                                                  [this]() { return Pull(); });
+}
+
+ForeignAudioSource::~ForeignAudioSource() { Stop(); }
+
+void ForeignAudioSource::Stop() {
+  {
+    webrtc::MutexLock lock(&lock_);
+    if (stopped_) {
+      return;
+    }
+    stopped_ = true;
+  }
+  // deleting the task queue waits for a running pull to finish and drops the
+  // pending one
+  task_queue_.reset();
+}
+
+webrtc::MediaSourceInterface::SourceState ForeignAudioSource::state() const {
+  webrtc::MutexLock lock(&lock_);
+  return stopped_ ? kEnded : kLive;
+}
+
+void ForeignAudioSource::AddSink(webrtc::AudioTrackSinkInterface *sink) {
+  webrtc::MutexLock lock(&lock_);
+  if (std::find(sinks_.begin(), sinks_.end(), sink) == sinks_.end()) {
+    sinks_.push_back(sink);
+  }
+}
+
+void ForeignAudioSource::RemoveSink(webrtc::AudioTrackSinkInterface *sink) {
+  webrtc::MutexLock lock(&lock_);
+  sinks_.erase(std::remove(sinks_.begin(), sinks_.end(), sink), sinks_.end());
+}
+
+webrtc::TimeDelta ForeignAudioSource::Pull() {
+  callback_(ctx_, sample_rate_, channels_, frames_, buffer_.data());
+  webrtc::MutexLock lock(&lock_);
+  for (auto sink : sinks_) {
+    sink->OnData(buffer_.data(), 16, sample_rate_, channels_, frames_);
+  }
+  return webrtc::TimeDelta::Millis(10);
+}
@@ -0,0 +1,53 @@
+#pragma once
+
+#include <memory>
+#include <vector>
+
+#include <api/audio_options.h>
+#include <api/media_stream_interface.h>
+#include <api/notifier.h>
+#include <api/task_queue/task_queue_base.h>
+#include <api/task_queue/task_queue_factory.h>
+#include <rtc_base/synchronization/mutex.h>
+#include <rtc_base/task_utils/repeating_task.h>
+
+#include "wrapper.hpp"
+
+// Audio source pulling 10ms of PCM samples from a foreign callback on its own
+// task queue, and passing them on to the sinks of its tracks.
+class ForeignAudioSource
+    : public webrtc::Notifier<webrtc::AudioSourceInterface> {
+public:
+  ForeignAudioSource(uint32_t sample_rate, size_t channels, const void *ctx,
+                     audio_source_callback_t callback);
+  ~ForeignAudioSource() override;
+
+  // Stop pulling samples. The callback is not invoked after this returns.
+  void Stop();
+
+  SourceState state() const override;
+  bool remote() const override { return false; }
+
+  void AddSink(webrtc::AudioTrackSinkInterface *sink) override;
+  void RemoveSink(webrtc::AudioTrackSinkInterface *sink) override;
+
+private:
+  webrtc::TimeDelta Pull();
+
+  const uint32_t sample_rate_;
+  const size_t channels_;
+  const size_t frames_;
+  const void *const ctx_;
+  const audio_source_callback_t callback_;
+
+  std::unique_ptr<webrtc::TaskQueueFactory> task_queue_factory_;
+  std::unique_ptr<webrtc::TaskQueueBase, webrtc::TaskQueueDeleter> task_queue_;
+  webrtc::RepeatingTaskHandle pull_task_;
+
+  // only touched from the task queue
+  std::vector<int16_t> buffer_;
+
+  mutable webrtc::Mutex lock_;
+  std::vector<webrtc::AudioTrackSinkInterface *> sinks_ RTC_GUARDED_BY(lock_);
+  bool stopped_ RTC_GUARDED_BY(lock_) = false;
+};
@@ -0,0 +1,57 @@
+#include "foreign_sink.hpp"
+
+#include "third_party/libyuv/include/libyuv/convert.h"
+#include "third_party/libyuv/include/libyuv/convert_argb.h"
+
+#include "glog/logging.h"
+
+ForeignVideoSink::ForeignVideoSink(VideoFrameFormat format, const void *ctx,
+                                   video_sink_callback_t callback)
+    : format_(format), ctx_(ctx), callback_(callback) {}
+
+void ForeignVideoSink::OnFrame(const webrtc::VideoFrame &frame) {
+  auto i420 = frame.video_frame_buffer()->ToI420();
+  const int width = i420->width();
+  const int height = i420->height();
+  switch (format_) {
+  case FRAME_I420: {
+    // pack the planes without padding
+    const int chroma_width = (width + 1) / 2;
+    const int chroma_height = (height + 1) / 2;
+    buffer_.resize(width * height + 2 * chroma_width * chroma_height);
+    uint8_t *y = buffer_.data();
+    uint8_t *u = y + width * height;
+    uint8_t *v = u + chroma_width * chroma_height;
+    libyuv::I420Copy(i420->DataY(), i420->StrideY(), i420->DataU(),
+                     i420->StrideU(), i420->DataV(), i420->StrideV(), y,
+                     width, u, chroma_width, v, chroma_width, width, height);
+    break;
+  }
+  case FRAME_RGBA:
+    // same pixel layout as the frames produced by ForeignFrameGenerator
+    buffer_.resize(width * height * 4);
+    libyuv::I420ToARGB(i420->DataY(), i420->StrideY(), i420->DataU(),
+                       i420->StrideU(), i420->DataV(), i420->StrideV(),
+                       buffer_.data(), width * 4, width, height);
+    break;
+  }
+  callback_(ctx_, width, height, frame.timestamp_us(), buffer_.data(),
+            buffer_.size());
+}
+
+ForeignAudioSink::ForeignAudioSink(const void *ctx,
+                                   audio_sink_callback_t callback)
+    : ctx_(ctx), callback_(callback) {}
+
+void ForeignAudioSink::OnData(const void *audio_data, int bits_per_sample,
+                              int sample_rate, size_t number_of_channels,
+                              size_t number_of_frames) {
+  if (bits_per_sample != 16) {
+    LOG_EVERY_N(WARNING, 100)
+        << "ForeignAudioSink::OnData(): unsupported bits_per_sample="
+        << bits_per_sample;
+    return;
+  }
+  callback_(ctx_, static_cast<const int16_t *>(audio_data), sample_rate,
+            number_of_channels, number_of_frames);
+}
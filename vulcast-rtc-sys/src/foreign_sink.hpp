@@ -0,0 +1,39 @@
+#pragma once
+
+#include <vector>
+
+#include <api/media_stream_interface.h>
+#include <api/video/video_frame.h>
+#include <api/video/video_sink_interface.h>
+
+#include "wrapper.hpp"
+
+// Forwards decoded frames of a remote video track to a foreign callback.
+class ForeignVideoSink : public rtc::VideoSinkInterface<webrtc::VideoFrame> {
+public:
+  ForeignVideoSink(VideoFrameFormat format, const void *ctx,
+                   video_sink_callback_t callback);
+
+  void OnFrame(const webrtc::VideoFrame &frame) override;
+
+private:
+  const VideoFrameFormat format_;
+  const void *const ctx_;
+  const video_sink_callback_t callback_;
+
+  // only touched from the decoding thread
+  std::vector<uint8_t> buffer_;
+};
+
+// Forwards PCM samples of a remote audio track to a foreign callback.
+class ForeignAudioSink : public webrtc::AudioTrackSinkInterface {
+public:
+  ForeignAudioSink(const void *ctx, audio_sink_callback_t callback);
+
+  void OnData(const void *audio_data, int bits_per_sample, int sample_rate,
+              size_t number_of_channels, size_t number_of_frames) override;
+
+private:
+  const void *const ctx_;
+  const audio_sink_callback_t callback_;
+};
@@ -0,0 +1,11 @@
+#include "frame_monitor.hpp"
+
+FrameMonitor::FrameMonitor(
+    rtc::scoped_refptr<webrtc::VideoTrackInterface> track)
+    : track_(track) {
+  track_->AddOrUpdateSink(this, rtc::VideoSinkWants());
+}
+
+FrameMonitor::~FrameMonitor() { track_->RemoveSink(this); }
+
+void FrameMonitor::OnFrame(const webrtc::VideoFrame &) { frame_count_++; }
@@ -0,0 +1,24 @@
+#pragma once
+
+#include <atomic>
+
+#include <api/media_stream_interface.h>
+#include <api/scoped_refptr.h>
+#include <api/video/video_frame.h>
+#include <api/video/video_sink_interface.h>
+
+// Counts the frames delivered by a local video track, keeping a reference to
+// the track until it is destroyed.
+class FrameMonitor : public rtc::VideoSinkInterface<webrtc::VideoFrame> {
+public:
+  explicit FrameMonitor(rtc::scoped_refptr<webrtc::VideoTrackInterface> track);
+  ~FrameMonitor() override;
+
+  void OnFrame(const webrtc::VideoFrame &frame) override;
+
+  uint64_t frame_count() const { return frame_count_; }
+
+private:
+  const rtc::scoped_refptr<webrtc::VideoTrackInterface> track_;
+  std::atomic<uint64_t> frame_count_{0};
+};
@@ -0,0 +1,70 @@
+#include "gated_audio_device_module.hpp"
+
+GatedAudioDeviceModule::GatedAudioDeviceModule(
+    rtc::scoped_refptr<webrtc::AudioDeviceModule> impl)
+    : impl_(impl) {
+  impl_->RegisterAudioCallback(this);
+}
+
+int32_t GatedAudioDeviceModule::RecordedDataIsAvailable(
+    const void *audioSamples, const size_t nSamples,
+    const size_t nBytesPerSample, const size_t nChannels,
+    const uint32_t samplesPerSec, const uint32_t totalDelayMS,
+    const int32_t clockDrift, const uint32_t currentMicLevel,
+    const bool keyPressed, uint32_t &newMicLevel) {
+  newMicLevel = currentMicLevel;
+  if (device_sources_ == 0) {
+    return 0;
+  }
+  webrtc::MutexLock lock(&lock_);
+  if (!transport_) {
+    return 0;
+  }
+  return transport_->RecordedDataIsAvailable(
+      audioSamples, nSamples, nBytesPerSample, nChannels, samplesPerSec,
+      totalDelayMS, clockDrift, currentMicLevel, keyPressed, newMicLevel);
+}
+
+int32_t GatedAudioDeviceModule::NeedMorePlayData(
+    const size_t nSamples, const size_t nBytesPerSample, const size_t nChannels,
+    const uint32_t samplesPerSec, void *audioSamples, size_t &nSamplesOut,
+    int64_t *elapsed_time_ms, int64_t *ntp_time_ms) {
+  webrtc::MutexLock lock(&lock_);
+  if (!transport_) {
+    nSamplesOut = 0;
+    *elapsed_time_ms = -1;
+    *ntp_time_ms = -1;
+    return 0;
+  }
+  return transport_->NeedMorePlayData(nSamples, nBytesPerSample, nChannels,
+                                      samplesPerSec, audioSamples, nSamplesOut,
+                                      elapsed_time_ms, ntp_time_ms);
+}
+
+void GatedAudioDeviceModule::PullRenderData(
+    int bits_per_sample, int sample_rate, size_t number_of_channels,
+    size_t number_of_frames, void *audio_data, int64_t *elapsed_time_ms,
+    int64_t *ntp_time_ms) {
+  webrtc::MutexLock lock(&lock_);
+  if (transport_) {
+    transport_->PullRenderData(bits_per_sample, sample_rate,
+                               number_of_channels, number_of_frames,
+                               audio_data, elapsed_time_ms, ntp_time_ms);
+  }
+}
+
+int32_t GatedAudioDeviceModule::RegisterAudioCallback(
+    webrtc::AudioTransport *audioCallback) {
+  webrtc::MutexLock lock(&lock_);
+  transport_ = audioCallback;
+  return 0;
+}
+
+DeviceAudioSource::DeviceAudioSource(
+    rtc::scoped_refptr<GatedAudioDeviceModule> adm,
+    const cricket::AudioOptions &options)
+    : adm_(adm), options_(options) {
+  adm_->AddDeviceSource();
+}
+
+DeviceAudioSource::~DeviceAudioSource() { adm_->RemoveDeviceSource(); }
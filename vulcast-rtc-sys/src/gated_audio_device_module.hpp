@@ -0,0 +1,219 @@
+#pragma once
+
+#include <atomic>
+
+#include <api/audio_options.h>
+#include <api/media_stream_interface.h>
+#include <api/notifier.h>
+#include <api/scoped_refptr.h>
+#include <modules/audio_device/include/audio_device.h>
+#include <rtc_base/synchronization/mutex.h>
+
+// Forwards to the platform audio device module, but only delivers recorded
+// audio while a DeviceAudioSource is alive. The recorded audio is sent to
+// every audio stream, including those fed by a ForeignAudioSource, so the
+// Rust API refuses to produce both kinds of tracks at the same time. There is
+// a single instance, owned by the process-wide peer connection factory.
+class GatedAudioDeviceModule : public webrtc::AudioDeviceModule,
+                               public webrtc::AudioTransport {
+public:
+  explicit GatedAudioDeviceModule(
+      rtc::scoped_refptr<webrtc::AudioDeviceModule> impl);
+
+  void AddDeviceSource() { device_sources_++; }
+  void RemoveDeviceSource() { device_sources_--; }
+
+  // webrtc::AudioTransport
+  int32_t RecordedDataIsAvailable(const void *audioSamples,
+                                  const size_t nSamples,
+                                  const size_t nBytesPerSample,
+                                  const size_t nChannels,
+                                  const uint32_t samplesPerSec,
+                                  const uint32_t totalDelayMS,
+                                  const int32_t clockDrift,
+                                  const uint32_t currentMicLevel,
+                                  const bool keyPressed,
+                                  uint32_t &newMicLevel) override;
+  int32_t NeedMorePlayData(const size_t nSamples, const size_t nBytesPerSample,
+                           const size_t nChannels, const uint32_t samplesPerSec,
+                           void *audioSamples, size_t &nSamplesOut,
+                           int64_t *elapsed_time_ms,
+                           int64_t *ntp_time_ms) override;
+  void PullRenderData(int bits_per_sample, int sample_rate,
+                      size_t number_of_channels, size_t number_of_frames,
+                      void *audio_data, int64_t *elapsed_time_ms,
+                      int64_t *ntp_time_ms) override;
+
+  // webrtc::AudioDeviceModule
+  int32_t RegisterAudioCallback(webrtc::AudioTransport *audioCallback) override;
+  int32_t ActiveAudioLayer(AudioLayer *audioLayer) const override {
+    return impl_->ActiveAudioLayer(audioLayer);
+  }
+  int32_t Init() override { return impl_->Init(); }
+  int32_t Terminate() override { return impl_->Terminate(); }
+  bool Initialized() const override { return impl_->Initialized(); }
+  int16_t PlayoutDevices() override { return impl_->PlayoutDevices(); }
+  int16_t RecordingDevices() override { return impl_->RecordingDevices(); }
+  int32_t PlayoutDeviceName(
+      uint16_t index, char name[webrtc::kAdmMaxDeviceNameSize],
+      char guid[webrtc::kAdmMaxGuidSize]) override {
+    return impl_->PlayoutDeviceName(index, name, guid);
+  }
+  int32_t RecordingDeviceName(
+      uint16_t index, char name[webrtc::kAdmMaxDeviceNameSize],
+      char guid[webrtc::kAdmMaxGuidSize]) override {
+    return impl_->RecordingDeviceName(index, name, guid);
+  }
+  int32_t SetPlayoutDevice(uint16_t index) override {
+    return impl_->SetPlayoutDevice(index);
+  }
+  int32_t SetPlayoutDevice(WindowsDeviceType device) override {
+    return impl_->SetPlayoutDevice(device);
+  }
+  int32_t SetRecordingDevice(uint16_t index) override {
+    return impl_->SetRecordingDevice(index);
+  }
+  int32_t SetRecordingDevice(WindowsDeviceType device) override {
+    return impl_->SetRecordingDevice(device);
+  }
+  int32_t PlayoutIsAvailable(bool *available) override {
+    return impl_->PlayoutIsAvailable(available);
+  }
+  int32_t InitPlayout() override { return impl_->InitPlayout(); }
+  bool PlayoutIsInitialized() const override {
+    return impl_->PlayoutIsInitialized();
+  }
+  int32_t RecordingIsAvailable(bool *available) override {
+    return impl_->RecordingIsAvailable(available);
+  }
+  int32_t InitRecording() override { return impl_->InitRecording(); }
+  bool RecordingIsInitialized() const override {
+    return impl_->RecordingIsInitialized();
+  }
+  int32_t StartPlayout() override { return impl_->StartPlayout(); }
+  int32_t StopPlayout() override { return impl_->StopPlayout(); }
+  bool Playing() const override { return impl_->Playing(); }
+  int32_t StartRecording() override { return impl_->StartRecording(); }
+  int32_t StopRecording() override { return impl_->StopRecording(); }
+  bool Recording() const override { return impl_->Recording(); }
+  int32_t InitSpeaker() override { return impl_->InitSpeaker(); }
+  bool SpeakerIsInitialized() const override {
+    return impl_->SpeakerIsInitialized();
+  }
+  int32_t InitMicrophone() override { return impl_->InitMicrophone(); }
+  bool MicrophoneIsInitialized() const override {
+    return impl_->MicrophoneIsInitialized();
+  }
+  int32_t SpeakerVolumeIsAvailable(bool *available) override {
+    return impl_->SpeakerVolumeIsAvailable(available);
+  }
+  int32_t SetSpeakerVolume(uint32_t volume) override {
+    return impl_->SetSpeakerVolume(volume);
+  }
+  int32_t SpeakerVolume(uint32_t *volume) const override {
+    return impl_->SpeakerVolume(volume);
+  }
+  int32_t MaxSpeakerVolume(uint32_t *maxVolume) const override {
+    return impl_->MaxSpeakerVolume(maxVolume);
+  }
+  int32_t MinSpeakerVolume(uint32_t *minVolume) const override {
+    return impl_->MinSpeakerVolume(minVolume);
+  }
+  int32_t MicrophoneVolumeIsAvailable(bool *available) override {
+    return impl_->MicrophoneVolumeIsAvailable(available);
+  }
+  int32_t SetMicrophoneVolume(uint32_t volume) override {
+    return impl_->SetMicrophoneVolume(volume);
+  }
+  int32_t MicrophoneVolume(uint32_t *volume) const override {
+    return impl_->MicrophoneVolume(volume);
+  }
+  int32_t MaxMicrophoneVolume(uint32_t *maxVolume) const override {
+    return impl_->MaxMicrophoneVolume(maxVolume);
+  }
+  int32_t MinMicrophoneVolume(uint32_t *minVolume) const override {
+    return impl_->MinMicrophoneVolume(minVolume);
+  }
+  int32_t SpeakerMuteIsAvailable(bool *available) override {
+    return impl_->SpeakerMuteIsAvailable(available);
+  }
+  int32_t SetSpeakerMute(bool enable) override {
+    return impl_->SetSpeakerMute(enable);
+  }
+  int32_t SpeakerMute(bool *enabled) const override {
+    return impl_->SpeakerMute(enabled);
+  }
+  int32_t MicrophoneMuteIsAvailable(bool *available) override {
+    return impl_->MicrophoneMuteIsAvailable(available);
+  }
+  int32_t SetMicrophoneMute(bool enable) override {
+    return impl_->SetMicrophoneMute(enable);
+  }
+  int32_t MicrophoneMute(bool *enabled) const override {
+    return impl_->MicrophoneMute(enabled);
+  }
+  int32_t StereoPlayoutIsAvailable(bool *available) const override {
+    return impl_->StereoPlayoutIsAvailable(available);
+  }
+  int32_t SetStereoPlayout(bool enable) override {
+    return impl_->SetStereoPlayout(enable);
+  }
+  int32_t StereoPlayout(bool *enabled) const override {
+    return impl_->StereoPlayout(enabled);
+  }
+  int32_t StereoRecordingIsAvailable(bool *available) const override {
+    return impl_->StereoRecordingIsAvailable(available);
+  }
+  int32_t SetStereoRecording(bool enable) override {
+    return impl_->SetStereoRecording(enable);
+  }
+  int32_t StereoRecording(bool *enabled) const override {
+    return impl_->StereoRecording(enabled);
+  }
+  int32_t PlayoutDelay(uint16_t *delayMS) const override {
+    return impl_->PlayoutDelay(delayMS);
+  }
+  bool BuiltInAECIsAvailable() const override {
+    return impl_->BuiltInAECIsAvailable();
+  }
+  bool BuiltInAGCIsAvailable() const override {
+    return impl_->BuiltInAGCIsAvailable();
+  }
+  bool BuiltInNSIsAvailable() const override {
+    return impl_->BuiltInNSIsAvailable();
+  }
+  int32_t EnableBuiltInAEC(bool enable) override {
+    return impl_->EnableBuiltInAEC(enable);
+  }
+  int32_t EnableBuiltInAGC(bool enable) override {
+    return impl_->EnableBuiltInAGC(enable);
+  }
+  int32_t EnableBuiltInNS(bool enable) override {
+    return impl_->EnableBuiltInNS(enable);
+  }
+
+private:
+  const rtc::scoped_refptr<webrtc::AudioDeviceModule> impl_;
+  std::atomic<int> device_sources_{0};
+
+  webrtc::Mutex lock_;
+  webrtc::AudioTransport *transport_ RTC_GUARDED_BY(lock_) = nullptr;
+};
+
+// Audio source of tracks captured from the audio device module. Recorded audio
+// is delivered while any of these is alive.
+class DeviceAudioSource
+    : public webrtc::Notifier<webrtc::AudioSourceInterface> {
+public:
+  DeviceAudioSource(rtc::scoped_refptr<GatedAudioDeviceModule> adm,
+                    const cricket::AudioOptions &options);
+  ~DeviceAudioSource() override;
+
+  SourceState state() const override { return kLive; }
+  bool remote() const override { return false; }
+  const cricket::AudioOptions options() const override { return options_; }
+
+private:
+  const rtc::scoped_refptr<GatedAudioDeviceModule> adm_;
+  const cricket::AudioOptions options_;
+};
@@ -1,6 +1,8 @@
 #include <api/scoped_refptr.h>
 #include <api/task_queue/default_task_queue_factory.h>
+#include <cstring>
 #include <iostream>
+#include <vector>
 
 #include "api/audio_codecs/builtin_audio_decoder_factory.h"
 #include "api/audio_codecs/builtin_audio_encoder_factory.h"
@@ -18,12 +20,18 @@
 
 #include <glog/logging.h>
 
+#include "error.hpp"
+#include "gated_audio_device_module.hpp"
+
 namespace {
 
+static rtc::Thread *worker_thread;
+static rtc::scoped_refptr<GatedAudioDeviceModule> audio_device_module;
+
 static rtc::scoped_refptr<webrtc::PeerConnectionFactoryInterface>
 CreatePeerConnectionFactory() {
   auto network_thread = rtc::Thread::CreateWithSocketServer().release();
-  auto worker_thread = rtc::Thread::Create().release();
+  worker_thread = rtc::Thread::Create().release();
   auto signalling_thread = rtc::Thread::Create().release();
 
   network_thread->SetName("network_thread", nullptr);
@@ -35,15 +43,62 @@ CreatePeerConnectionFactory() {
     LOG(FATAL) << "could not start webrtc threads";
   }
 
+  // the audio device module must be created on the worker thread
+  static auto adm_task_queue_factory = webrtc::CreateDefaultTaskQueueFactory();
+  audio_device_module = worker_thread->Invoke<
+      rtc::scoped_refptr<GatedAudioDeviceModule>>(RTC_FROM_HERE, [&]() {
+    auto adm = webrtc::AudioDeviceModule::Create(
+        webrtc::AudioDeviceModule::kPlatformDefaultAudio,
+        adm_task_queue_factory.get());
+    CHECK(adm) << "could not create audio device module";
+    return new rtc::RefCountedObject<GatedAudioDeviceModule>(adm);
+  });
+
   return webrtc::CreatePeerConnectionFactory(
       network_thread /*network_thread*/, worker_thread /*worker_thread*/,
-      signalling_thread /*signalling_thread*/, nullptr /*default_adm*/,
+      signalling_thread /*signalling_thread*/, audio_device_module,
       webrtc::CreateBuiltinAudioEncoderFactory(),
       webrtc::CreateBuiltinAudioDecoderFactory(),
       webrtc::CreateBuiltinVideoEncoderFactory(),
       webrtc::CreateBuiltinVideoDecoderFactory(), nullptr /*audio_mixer*/,
       nullptr /*audio_processing*/);
 }
+
+// Select the recording device with the given name (or GUID) or index,
+// restarting recording if it was active. Must be called on the worker thread.
+static bool SelectRecordingDevice(webrtc::AudioDeviceModule *adm,
+                                  const char *device_name, int device_idx) {
+  const int16_t num_devices = adm->RecordingDevices();
+  if (device_name != nullptr) {
+    device_idx = -1;
+    for (int16_t i = 0; i < num_devices; ++i) {
+      char name[webrtc::kAdmMaxDeviceNameSize] = {0};
+      char guid[webrtc::kAdmMaxGuidSize] = {0};
+      if (adm->RecordingDeviceName(i, name, guid) == 0 &&
+          (std::strcmp(device_name, name) == 0 ||
+           std::strcmp(device_name, guid) == 0)) {
+        device_idx = i;
+        break;
+      }
+    }
+  }
+  if (device_idx < 0 || device_idx >= num_devices) {
+    return false;
+  }
+
+  const bool recording = adm->Recording();
+  if (recording) {
+    adm->StopRecording();
+  }
+  if (adm->SetRecordingDevice(device_idx) != 0) {
+    return false;
+  }
+  if (recording &&
+      (adm->InitRecording() != 0 || adm->StartRecording() != 0)) {
+    LOG(ERROR) << "could not restart recording on device " << device_idx;
+  }
+  return true;
+}
 } // namespace
 
 rtc::scoped_refptr<webrtc::PeerConnectionFactoryInterface>
@@ -55,14 +110,35 @@ GetPeerConnectionFactory() {
 }
 
 // Audio track creation.
-rtc::scoped_refptr<webrtc::AudioTrackInterface> CreateAudioTrack() {
+rtc::scoped_refptr<webrtc::AudioTrackInterface>
+CreateAudioTrack(const char *device_name, int device_idx,
+                 const cricket::AudioOptions &options) {
   auto factory = GetPeerConnectionFactory();
 
-  cricket::AudioOptions options;
-  options.highpass_filter = false;
+  if (device_name != nullptr || device_idx >= 0) {
+    bool selected = worker_thread->Invoke<bool>(RTC_FROM_HERE, [&]() {
+      return SelectRecordingDevice(audio_device_module, device_name,
+                                   device_idx);
+    });
+    if (!selected) {
+      throw FfiException(ERR_CAPTURE_DEVICE_NOT_FOUND,
+                         device_name != nullptr
+                             ? std::string(device_name)
+                             : "recording device " +
+                                   std::to_string(device_idx));
+    }
+  }
 
   rtc::scoped_refptr<webrtc::AudioSourceInterface> source =
-      factory->CreateAudioSource(options);
+      new rtc::RefCountedObject<DeviceAudioSource>(audio_device_module,
+                                                   options);
+
+  return factory->CreateAudioTrack(rtc::CreateRandomUuid(), source);
+}
+
+rtc::scoped_refptr<webrtc::AudioTrackInterface>
+CreateForeignAudioTrack(ForeignAudioSource *source) {
+  auto factory = GetPeerConnectionFactory();
 
   return factory->CreateAudioTrack(rtc::CreateRandomUuid(), source);
 }
@@ -94,32 +170,65 @@ rtc::scoped_refptr<webrtc::VideoTrackInterface> CreateSquaresVideoTrack() {
 
 class CapturerTrackSource : public webrtc::VideoTrackSource {
 public:
+  // Open the device with the given name or unique id, or index if device_name
+  // is nullptr. If neither is given, the first device which can be opened is
+  // used. If best_match is set, the capability is replaced with the closest
+  // one supported by the device. The unique id of the opened device is
+  // written to unique_id.
   static rtc::scoped_refptr<CapturerTrackSource>
-  Create(int device_idx, size_t width, size_t height, size_t fps,
-         webrtc::VideoType video_type) {
-    std::unique_ptr<webrtc::test::VcmCapturer> capturer;
+  Create(const char *device_name, int device_idx,
+         webrtc::VideoCaptureCapability &capability, bool best_match,
+         std::string &unique_id) {
     std::unique_ptr<webrtc::VideoCaptureModule::DeviceInfo> info(
         webrtc::VideoCaptureFactory::CreateDeviceInfo());
     if (!info) {
       return nullptr;
     }
-    int num_devices = info->NumberOfDevices();
-    if (device_idx > 0) {
-      CHECK(device_idx < num_devices);
-      capturer = absl::WrapUnique(webrtc::test::VcmCapturer::Create(
-          width, height, fps, device_idx, video_type));
-      if (capturer) {
-        return new rtc::RefCountedObject<CapturerTrackSource>(
-            std::move(capturer));
+    const int num_devices = info->NumberOfDevices();
+    std::vector<int> candidates;
+    if (device_name != nullptr) {
+      for (int i = 0; i < num_devices; ++i) {
+        char name[256] = {0};
+        char unique_name[256] = {0};
+        if (info->GetDeviceName(i, name, sizeof(name), unique_name,
+                                sizeof(unique_name)) == 0 &&
+            (std::strcmp(device_name, unique_name) == 0 ||
+             std::strcmp(device_name, name) == 0)) {
+          candidates.push_back(i);
+          break;
+        }
+      }
+    } else if (device_idx >= 0) {
+      if (device_idx < num_devices) {
+        candidates.push_back(device_idx);
       }
     } else {
       for (int i = 0; i < num_devices; ++i) {
-        capturer = absl::WrapUnique(webrtc::test::VcmCapturer::Create(
-            width, height, fps, i, video_type));
-        if (capturer) {
-          return new rtc::RefCountedObject<CapturerTrackSource>(
-              std::move(capturer));
-        }
+        candidates.push_back(i);
+      }
+    }
+
+    for (int i : candidates) {
+      char name[256] = {0};
+      char unique_name[256] = {0};
+      if (info->GetDeviceName(i, name, sizeof(name), unique_name,
+                              sizeof(unique_name)) != 0) {
+        continue;
+      }
+      webrtc::VideoCaptureCapability resulting = capability;
+      if (best_match && info->GetBestMatchedCapability(unique_name, capability,
+                                                       resulting) < 0) {
+        continue;
+      }
+      std::unique_ptr<webrtc::test::VcmCapturer> capturer =
+          absl::WrapUnique(webrtc::test::VcmCapturer::Create(
+              resulting.width, resulting.height, resulting.maxFPS, i,
+              resulting.videoType));
+      if (capturer) {
+        capability = resulting;
+        unique_id = unique_name;
+        return new rtc::RefCountedObject<CapturerTrackSource>(
+            std::move(capturer));
       }
     }
 
@@ -139,13 +248,30 @@ private:
 };
 
 rtc::scoped_refptr<webrtc::VideoTrackInterface>
-CreateVcmCapturerVideoTrack(int device_idx, size_t width, size_t height,
-                            size_t fps, webrtc::VideoType video_type) {
+CreateVcmCapturerVideoTrack(const char *device_name, int device_idx,
+                            webrtc::VideoCaptureCapability &capability,
+                            bool best_match, std::string &unique_id) {
   auto factory = GetPeerConnectionFactory();
 
   rtc::scoped_refptr<CapturerTrackSource> video_device =
-      CapturerTrackSource::Create(device_idx, width, height, fps, video_type);
-  CHECK(video_device);
+      CapturerTrackSource::Create(device_name, device_idx, capability,
+                                  best_match, unique_id);
+  if (!video_device) {
+    const std::string mode = std::to_string(capability.width) + "x" +
+                             std::to_string(capability.height) + "@" +
+                             std::to_string(capability.maxFPS);
+    if (device_name != nullptr) {
+      throw FfiException(ERR_CAPTURE_DEVICE_NOT_FOUND,
+                         std::string(device_name) +
+                             " could not be opened at " + mode);
+    } else if (device_idx >= 0) {
+      throw FfiException(ERR_CAPTURE_DEVICE_NOT_FOUND,
+                         "device " + std::to_string(device_idx) +
+                             " could not be opened at " + mode);
+    }
+    throw FfiException(ERR_CAPTURE_DEVICE_NOT_FOUND,
+                       "no capture device supports " + mode);
+  }
   return factory->CreateVideoTrack(rtc::CreateRandomUuid(), video_device);
 }
 
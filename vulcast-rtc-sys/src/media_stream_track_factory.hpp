@@ -3,18 +3,31 @@
 #include <api/media_stream_interface.h>
 #include <api/peer_connection_interface.h>
 #include <common_video/libyuv/include/webrtc_libyuv.h>
+#include <modules/video_capture/video_capture_defines.h>
 
+#include "foreign_audio_source.hpp"
 #include "foreign_frame_generator.hpp"
 
 rtc::scoped_refptr<webrtc::PeerConnectionFactoryInterface>
 GetPeerConnectionFactory();
 
-rtc::scoped_refptr<webrtc::AudioTrackInterface> CreateAudioTrack();
+// Capture from the recording device with the given name or index, or the
+// current one if device_name is nullptr and device_idx is negative.
+rtc::scoped_refptr<webrtc::AudioTrackInterface>
+CreateAudioTrack(const char *device_name, int device_idx,
+                 const cricket::AudioOptions &options);
+rtc::scoped_refptr<webrtc::AudioTrackInterface>
+CreateForeignAudioTrack(ForeignAudioSource *source);
 rtc::scoped_refptr<webrtc::VideoTrackInterface> CreateVideoTrack();
 rtc::scoped_refptr<webrtc::VideoTrackInterface> CreateSquaresVideoTrack();
+// Capture from the device with the given name or unique id, or index if
+// device_name is nullptr. If best_match is set, the closest supported
+// capability is used. The capability actually used and the unique id of the
+// device are written back.
 rtc::scoped_refptr<webrtc::VideoTrackInterface>
-CreateVcmCapturerVideoTrack(int device_idx, size_t width, size_t height,
-                            size_t fps, webrtc::VideoType video_type);
+CreateVcmCapturerVideoTrack(const char *device_name, int device_idx,
+                            webrtc::VideoCaptureCapability &capability,
+                            bool best_match, std::string &unique_id);
 rtc::scoped_refptr<webrtc::VideoTrackInterface>
 CreateForeignVideoTrack(size_t width, size_t height, size_t fps, void *ctx,
                         frame_callback_t callback);
@@ -0,0 +1,532 @@
+#include "plain_transport.hpp"
+
+#include <algorithm>
+#include <cstring>
+
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <poll.h>
+#include <unistd.h>
+
+#include <api/audio_codecs/opus/audio_encoder_opus.h>
+#include <api/video/video_bitrate_allocation.h>
+#include <glog/logging.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/common_header.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/fir.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/pli.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/psfb.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/sdes.h>
+#include <modules/rtp_rtcp/source/rtcp_packet/sender_report.h>
+#include <modules/rtp_rtcp/source/rtp_format.h>
+#include <modules/rtp_rtcp/source/rtp_video_header.h>
+#include <modules/video_coding/codecs/vp8/include/vp8.h>
+#include <rtc_base/helpers.h>
+#include <rtc_base/ssl_stream_adapter.h>
+#include <rtc_base/third_party/base64/base64.h>
+#include <rtc_base/time_utils.h>
+#include <system_wrappers/include/clock.h>
+
+#include "codec.hpp"
+#include "error.hpp"
+
+using json = nlohmann::json;
+
+namespace {
+// RTP packets are kept below the MTU, leaving room for SRTP
+constexpr size_t kMaxPacketSize = 1200;
+constexpr size_t kRtpHeaderSize = 12;
+constexpr size_t kSrtpMaxOverhead = 64;
+constexpr size_t kReceiveBufferSize = 1500;
+constexpr int64_t kReportIntervalMs = 1000;
+constexpr int kPollTimeoutMs = 100;
+constexpr uint32_t kVideoClockRate = 90000;
+constexpr int kOpusFrameSizeMs = 20;
+
+// Address of a TransportTuple of the server transport.
+sockaddr_storage tuple_addr(const json &tuple) {
+  if (tuple.value("protocol", "udp") != "udp") {
+    throw FfiException(ERR_UNKNOWN, "plain transport must use UDP");
+  }
+  // localIp was called localAddress before mediasoup 3.10
+  auto ip = tuple.contains("localIp")
+                ? tuple["localIp"].get<std::string>()
+                : tuple["localAddress"].get<std::string>();
+  auto port = htons(tuple["localPort"].get<uint16_t>());
+  sockaddr_storage addr{};
+  auto addr4 = reinterpret_cast<sockaddr_in *>(&addr);
+  auto addr6 = reinterpret_cast<sockaddr_in6 *>(&addr);
+  if (inet_pton(AF_INET, ip.c_str(), &addr4->sin_addr) == 1) {
+    addr4->sin_family = AF_INET;
+    addr4->sin_port = port;
+  } else if (inet_pton(AF_INET6, ip.c_str(), &addr6->sin6_addr) == 1) {
+    addr6->sin6_family = AF_INET6;
+    addr6->sin6_port = port;
+  } else {
+    throw FfiException(ERR_UNKNOWN, "invalid transport address " + ip);
+  }
+  return addr;
+}
+
+socklen_t addr_len(const sockaddr_storage &addr) {
+  return addr.ss_family == AF_INET ? sizeof(sockaddr_in) : sizeof(sockaddr_in6);
+}
+} // namespace
+
+PlainProducer::PlainProducer(PlainTransport *transport, const json &codec)
+    : codec_(codec),
+      payload_type_(codec["preferredPayloadType"].get<uint8_t>()),
+      clock_rate_(codec["clockRate"].get<uint32_t>()), transport_(transport),
+      ssrc_(rtc::CreateRandomNonZeroId()),
+      cname_(rtc::CreateRandomString(16)),
+      sequence_number_(rtc::CreateRandomId() & 0xFFFF) {}
+
+json PlainProducer::GetRtpParameters() const {
+  json codec = {{"mimeType", codec_["mimeType"]},
+                {"payloadType", payload_type_},
+                {"clockRate", clock_rate_},
+                {"parameters", codec_.value("parameters", json::object())},
+                {"rtcpFeedback", json::array()}};
+  if (codec_.contains("channels")) {
+    codec["channels"] = codec_["channels"];
+  }
+  if (codec_["kind"] == "video") {
+    // key frames are sent on request, but lost packets are not retransmitted
+    codec["rtcpFeedback"] = {{{"type", "nack"}, {"parameter", "pli"}},
+                             {{"type", "ccm"}, {"parameter", "fir"}}};
+  }
+  return {{"codecs", {codec}},
+          {"encodings", {{{"ssrc", ssrc_}}}},
+          {"rtcp", {{"cname", cname_}, {"reducedSize", true}}}};
+}
+
+void PlainProducer::SendPacket(webrtc::RtpPacketToSend *packet) {
+  packet->SetPayloadType(payload_type_);
+  packet->SetSsrc(ssrc_);
+  {
+    std::lock_guard<std::mutex> lock(lock_);
+    packet->SetSequenceNumber(sequence_number_++);
+    packet_count_++;
+    octet_count_ += packet->payload_size();
+    last_rtp_timestamp_ = packet->Timestamp();
+    last_rtp_time_ms_ = rtc::TimeMillis();
+  }
+  transport_->SendRtp(packet->data(), packet->size());
+}
+
+void PlainProducer::AppendReport(std::vector<uint8_t> *compound) {
+  webrtc::rtcp::SenderReport report;
+  report.SetSenderSsrc(ssrc_);
+  report.SetNtp(webrtc::Clock::GetRealTimeClock()->CurrentNtpTime());
+  {
+    std::lock_guard<std::mutex> lock(lock_);
+    if (last_rtp_time_ms_ < 0) {
+      // nothing sent yet
+      return;
+    }
+    // extrapolate the RTP timestamp of the last packet to now
+    auto elapsed_ms = rtc::TimeMillis() - last_rtp_time_ms_;
+    report.SetRtpTimestamp(last_rtp_timestamp_ +
+                           static_cast<uint32_t>(elapsed_ms * clock_rate_ /
+                                                 1000));
+    report.SetPacketCount(packet_count_);
+    report.SetOctetCount(octet_count_);
+  }
+  webrtc::rtcp::Sdes sdes;
+  sdes.AddCName(ssrc_, cname_);
+  for (const auto &buffer : {report.Build(), sdes.Build()}) {
+    compound->insert(compound->end(), buffer.begin(), buffer.end());
+  }
+}
+
+void PlainProducer::RemoveFromTransport() {
+  transport_->RemoveProducer(this);
+}
+
+PlainVideoProducer::PlainVideoProducer(
+    PlainTransport *transport, const json &codec,
+    rtc::scoped_refptr<webrtc::VideoTrackInterface> track,
+    uint32_t max_bitrate, uint32_t max_framerate)
+    : PlainProducer(transport, codec), track_(track),
+      max_bitrate_(max_bitrate), max_framerate_(max_framerate),
+      timestamp_offset_(rtc::CreateRandomId()),
+      picture_id_(rtc::CreateRandomId() & 0x7FFF) {
+  track_->AddOrUpdateSink(this, rtc::VideoSinkWants());
+}
+
+PlainVideoProducer::~PlainVideoProducer() {
+  // no frame is being delivered once the sink is removed
+  track_->RemoveSink(this);
+  if (encoder_) {
+    encoder_->Release();
+  }
+}
+
+void PlainVideoProducer::InitEncoder(int width, int height) {
+  LOG(INFO) << "PlainVideoProducer::InitEncoder(" << width << "," << height
+            << ")";
+  if (encoder_) {
+    encoder_->Release();
+  }
+  webrtc::VideoCodec codec;
+  codec.codecType = webrtc::kVideoCodecVP8;
+  codec.width = width;
+  codec.height = height;
+  codec.startBitrate = max_bitrate_ / 1000;
+  codec.maxBitrate = max_bitrate_ / 1000;
+  codec.minBitrate = std::min<uint32_t>(30, max_bitrate_ / 1000);
+  codec.maxFramerate = max_framerate_;
+  codec.qpMax = 56;
+  codec.active = true;
+  codec.mode = webrtc::VideoCodecMode::kRealtimeVideo;
+  *codec.VP8() = webrtc::VideoEncoder::GetDefaultVp8Settings();
+  codec.VP8()->numberOfTemporalLayers = 1;
+
+  encoder_ = webrtc::VP8Encoder::Create();
+  webrtc::VideoEncoder::Settings settings(
+      webrtc::VideoEncoder::Capabilities(false),
+      std::max(1u, std::thread::hardware_concurrency()),
+      kMaxPacketSize - kRtpHeaderSize);
+  if (encoder_->InitEncode(&codec, settings) != WEBRTC_VIDEO_CODEC_OK) {
+    LOG(ERROR) << "failed to initialize VP8 encoder";
+    encoder_ = nullptr;
+    return;
+  }
+  encoder_->RegisterEncodeCompleteCallback(this);
+  webrtc::VideoBitrateAllocation allocation;
+  allocation.SetBitrate(0, 0, max_bitrate_);
+  encoder_->SetRates(webrtc::VideoEncoder::RateControlParameters(
+      allocation, static_cast<double>(max_framerate_)));
+  width_ = width;
+  height_ = height;
+  key_frame_requested_ = true;
+}
+
+void PlainVideoProducer::OnFrame(const webrtc::VideoFrame &frame) {
+  if (IsPaused()) {
+    return;
+  }
+  if (!encoder_ || frame.width() != width_ || frame.height() != height_) {
+    InitEncoder(frame.width(), frame.height());
+    if (!encoder_) {
+      return;
+    }
+  }
+  auto timestamp_us =
+      frame.timestamp_us() != 0 ? frame.timestamp_us() : rtc::TimeMicros();
+  webrtc::VideoFrame input(frame);
+  input.set_timestamp(timestamp_offset_ +
+                      static_cast<uint32_t>(timestamp_us * kVideoClockRate /
+                                            rtc::kNumMicrosecsPerSec));
+  std::vector<webrtc::VideoFrameType> frame_types{
+      key_frame_requested_.exchange(false)
+          ? webrtc::VideoFrameType::kVideoFrameKey
+          : webrtc::VideoFrameType::kVideoFrameDelta};
+  encoder_->Encode(input, &frame_types);
+}
+
+webrtc::EncodedImageCallback::Result
+PlainVideoProducer::OnEncodedImage(const webrtc::EncodedImage &image,
+                                   const webrtc::CodecSpecificInfo *info) {
+  webrtc::RTPVideoHeader header;
+  header.frame_type = image._frameType;
+  header.width = image._encodedWidth;
+  header.height = image._encodedHeight;
+  header.codec = webrtc::kVideoCodecVP8;
+  auto &vp8 = header.video_type_header.emplace<webrtc::RTPVideoHeaderVP8>();
+  vp8.InitRTPVideoHeaderVP8();
+  vp8.pictureId = picture_id_;
+  picture_id_ = (picture_id_ + 1) & 0x7FFF;
+  if (info) {
+    vp8.nonReference = info->codecSpecific.VP8.nonReference;
+  }
+
+  webrtc::RtpPacketizer::PayloadSizeLimits limits;
+  limits.max_payload_len = kMaxPacketSize - kRtpHeaderSize;
+  auto packetizer = webrtc::RtpPacketizer::Create(
+      webrtc::kVideoCodecVP8, rtc::MakeArrayView(image.data(), image.size()),
+      limits, header);
+  for (auto remaining = packetizer->NumPackets(); remaining > 0; remaining--) {
+    webrtc::RtpPacketToSend packet(nullptr);
+    packet.SetTimestamp(image.Timestamp());
+    if (!packetizer->NextPacket(&packet)) {
+      break;
+    }
+    SendPacket(&packet);
+  }
+  return Result(Result::OK);
+}
+
+PlainAudioProducer::PlainAudioProducer(
+    PlainTransport *transport, const json &codec,
+    rtc::scoped_refptr<webrtc::AudioTrackInterface> track,
+    uint32_t max_bitrate)
+    : PlainProducer(transport, codec), track_(track),
+      max_bitrate_(max_bitrate), rtp_timestamp_(rtc::CreateRandomId()) {
+  track_->AddSink(this);
+}
+
+PlainAudioProducer::~PlainAudioProducer() {
+  // no audio is being delivered once the sink is removed
+  track_->RemoveSink(this);
+}
+
+void PlainAudioProducer::OnData(const void *audio_data, int bits_per_sample,
+                                int sample_rate, size_t number_of_channels,
+                                size_t number_of_frames) {
+  if (bits_per_sample != 16 || number_of_channels == 0 ||
+      number_of_channels > 2) {
+    return;
+  }
+  if (!encoder_ || encoder_->NumChannels() != number_of_channels) {
+    webrtc::AudioEncoderOpusConfig config;
+    config.frame_size_ms = kOpusFrameSizeMs;
+    config.num_channels = number_of_channels;
+    config.application =
+        webrtc::AudioEncoderOpusConfig::ApplicationMode::kAudio;
+    if (max_bitrate_ != 0) {
+      config.bitrate_bps = max_bitrate_;
+    }
+    encoder_ =
+        webrtc::AudioEncoderOpus::MakeAudioEncoder(config, payload_type_);
+    if (!encoder_) {
+      LOG(ERROR) << "failed to create Opus encoder";
+      return;
+    }
+  }
+  // the encoder keeps consuming audio while paused, so that timestamps stay
+  // continuous
+  auto frames = static_cast<size_t>(encoder_->SampleRateHz() / 100);
+  resampled_.resize(frames * number_of_channels);
+  resampler_.InitializeIfNeeded(sample_rate, encoder_->SampleRateHz(),
+                                number_of_channels);
+  if (resampler_.Resample(static_cast<const int16_t *>(audio_data),
+                          number_of_frames * number_of_channels,
+                          resampled_.data(), resampled_.size()) < 0) {
+    return;
+  }
+  encoded_.Clear();
+  auto info = encoder_->Encode(rtp_timestamp_, resampled_, &encoded_);
+  rtp_timestamp_ += frames * encoder_->RtpTimestampRateHz() /
+                    encoder_->SampleRateHz();
+  if (info.encoded_bytes == 0 || IsPaused()) {
+    return;
+  }
+  webrtc::RtpPacketToSend packet(nullptr);
+  packet.SetTimestamp(info.encoded_timestamp);
+  auto payload = packet.SetPayloadSize(info.encoded_bytes);
+  std::memcpy(payload, encoded_.data(), info.encoded_bytes);
+  SendPacket(&packet);
+}
+
+PlainTransport::PlainTransport(const json &options,
+                               const json &router_rtp_capabilities)
+    : id_(options["id"].get<std::string>()),
+      router_rtp_capabilities_(router_rtp_capabilities) {
+  LOG(INFO) << "PlainTransport::PlainTransport(" << options.dump() << ")";
+  rtp_addr_ = tuple_addr(options["tuple"]);
+  // without rtcpTuple, RTCP is multiplexed with RTP
+  rtcp_addr_ = options.contains("rtcpTuple") && !options["rtcpTuple"].is_null()
+                   ? tuple_addr(options["rtcpTuple"])
+                   : rtp_addr_;
+
+  if (options.contains("srtpParameters") &&
+      !options["srtpParameters"].is_null()) {
+    const auto &remote = options["srtpParameters"];
+    auto crypto_suite = remote["cryptoSuite"].get<std::string>();
+    int suite = rtc::SrtpCryptoSuiteFromName(crypto_suite);
+    int key_length, salt_length;
+    if (suite == rtc::kSrtpInvalidCryptoSuite ||
+        !rtc::GetSrtpKeyAndSaltLengths(suite, &key_length, &salt_length)) {
+      throw FfiException(ERR_UNKNOWN,
+                         "unsupported SRTP crypto suite " + crypto_suite);
+    }
+    std::string remote_key;
+    if (!rtc::Base64::Decode(remote["keyBase64"].get<std::string>(),
+                             rtc::Base64::DO_STRICT, &remote_key, nullptr) ||
+        remote_key.size() != static_cast<size_t>(key_length + salt_length)) {
+      throw FfiException(ERR_UNKNOWN, "invalid SRTP key");
+    }
+    // the server decrypts what is sent with a key of our own
+    std::string local_key;
+    rtc::CreateRandomData(key_length + salt_length, &local_key);
+    send_session_ = std::make_unique<cricket::SrtpSession>();
+    recv_session_ = std::make_unique<cricket::SrtpSession>();
+    if (!send_session_->SetSend(
+            suite, reinterpret_cast<const uint8_t *>(local_key.data()),
+            local_key.size(), {}) ||
+        !recv_session_->SetRecv(
+            suite, reinterpret_cast<const uint8_t *>(remote_key.data()),
+            remote_key.size(), {})) {
+      throw FfiException(ERR_UNKNOWN, "failed to create SRTP sessions");
+    }
+    srtp_parameters_ = {{"cryptoSuite", crypto_suite},
+                        {"keyBase64", rtc::Base64::Encode(local_key)}};
+  }
+
+  socket_ = socket(rtp_addr_.ss_family, SOCK_DGRAM, 0);
+  if (socket_ < 0) {
+    throw FfiException(ERR_UNKNOWN, "failed to create socket: " +
+                                        std::string(std::strerror(errno)));
+  }
+  // bind to an ephemeral port now rather than on the first send, so that the
+  // socket can be polled right away
+  sockaddr_storage local_addr{};
+  local_addr.ss_family = rtp_addr_.ss_family;
+  if (bind(socket_, reinterpret_cast<sockaddr *>(&local_addr),
+           addr_len(local_addr)) < 0) {
+    auto message = std::string(std::strerror(errno));
+    close(socket_);
+    throw FfiException(ERR_UNKNOWN, "failed to bind socket: " + message);
+  }
+  thread_ = std::thread(&PlainTransport::Run, this);
+}
+
+PlainTransport::~PlainTransport() {
+  LOG(INFO) << "PlainTransport::~PlainTransport()";
+  stopped_ = true;
+  thread_.join();
+  close(socket_);
+}
+
+std::unique_ptr<PlainProducer>
+PlainTransport::Produce(webrtc::MediaStreamTrackInterface *track,
+                        uint32_t max_bitrate, uint32_t max_framerate) {
+  LOG(INFO) << "PlainTransport::Produce(" << track->kind() << ","
+            << max_bitrate << "," << max_framerate << ")";
+  std::unique_ptr<PlainProducer> producer;
+  if (track->kind() == webrtc::MediaStreamTrackInterface::kVideoKind) {
+    if (max_bitrate == 0 || max_framerate == 0) {
+      throw std::invalid_argument(
+          "video bitrate and frame rate must not be zero");
+    }
+    producer = std::make_unique<PlainVideoProducer>(
+        this, FindCodec(router_rtp_capabilities_, "video/VP8"),
+        static_cast<webrtc::VideoTrackInterface *>(track), max_bitrate,
+        max_framerate);
+  } else {
+    producer = std::make_unique<PlainAudioProducer>(
+        this, FindCodec(router_rtp_capabilities_, "audio/opus"),
+        static_cast<webrtc::AudioTrackInterface *>(track), max_bitrate);
+  }
+  std::lock_guard<std::mutex> lock(producers_lock_);
+  producers_.push_back(producer.get());
+  return producer;
+}
+
+void PlainTransport::RemoveProducer(PlainProducer *producer) {
+  std::lock_guard<std::mutex> lock(producers_lock_);
+  producers_.erase(std::remove(producers_.begin(), producers_.end(), producer),
+                   producers_.end());
+}
+
+void PlainTransport::SendRtp(const uint8_t *data, size_t size) {
+  Send(rtp_addr_, data, size, false);
+}
+
+void PlainTransport::SendRtcp(const uint8_t *data, size_t size) {
+  Send(rtcp_addr_, data, size, true);
+}
+
+void PlainTransport::Send(const sockaddr_storage &addr, const uint8_t *data,
+                          size_t size, bool rtcp) {
+  std::vector<uint8_t> buffer(data, data + size);
+  std::lock_guard<std::mutex> lock(send_lock_);
+  if (send_session_) {
+    buffer.resize(size + kSrtpMaxOverhead);
+    int protected_size = 0;
+    bool ok = rtcp ? send_session_->ProtectRtcp(buffer.data(), size,
+                                                buffer.size(), &protected_size)
+                   : send_session_->ProtectRtp(buffer.data(), size,
+                                               buffer.size(), &protected_size);
+    if (!ok) {
+      LOG(WARNING) << "failed to protect " << (rtcp ? "RTCP" : "RTP");
+      return;
+    }
+    buffer.resize(protected_size);
+  }
+  if (sendto(socket_, buffer.data(), buffer.size(), 0,
+             reinterpret_cast<const sockaddr *>(&addr), addr_len(addr)) < 0) {
+    LOG(WARNING) << "failed to send " << (rtcp ? "RTCP" : "RTP") << ": "
+                 << std::strerror(errno);
+  }
+}
+
+void PlainTransport::Run() {
+  std::vector<uint8_t> buffer(kReceiveBufferSize);
+  auto next_report_ms = rtc::TimeMillis() + kReportIntervalMs;
+  while (!stopped_) {
+    pollfd fd{socket_, POLLIN, 0};
+    if (poll(&fd, 1, kPollTimeoutMs) > 0 && (fd.revents & POLLIN)) {
+      auto size = recv(socket_, buffer.data(), buffer.size(), 0);
+      if (size > 0) {
+        OnRtcp(buffer.data(), size);
+      }
+    }
+    if (rtc::TimeMillis() >= next_report_ms) {
+      SendReports();
+      next_report_ms += kReportIntervalMs;
+    }
+  }
+}
+
+void PlainTransport::OnRtcp(uint8_t *data, size_t size) {
+  // RTCP packet types are 192 to 223 (RFC 5761), the server sends no RTP
+  if (size < 2 || data[1] < 192 || data[1] > 223) {
+    return;
+  }
+  if (recv_session_) {
+    int unprotected_size = 0;
+    if (!recv_session_->UnprotectRtcp(data, size, &unprotected_size)) {
+      LOG(WARNING) << "failed to unprotect RTCP";
+      return;
+    }
+    size = unprotected_size;
+  }
+  std::vector<uint32_t> key_frame_ssrcs;
+  webrtc::rtcp::CommonHeader header;
+  for (const uint8_t *next = data; next < data + size;
+       next = header.NextPacket()) {
+    if (!header.Parse(next, data + size - next)) {
+      break;
+    }
+    if (header.type() != webrtc::rtcp::Psfb::kPacketType) {
+      continue;
+    }
+    if (header.fmt() == webrtc::rtcp::Pli::kFeedbackMessageType) {
+      webrtc::rtcp::Pli pli;
+      if (pli.Parse(header)) {
+        key_frame_ssrcs.push_back(pli.media_ssrc());
+      }
+    } else if (header.fmt() == webrtc::rtcp::Fir::kFeedbackMessageType) {
+      webrtc::rtcp::Fir fir;
+      if (fir.Parse(header)) {
+        for (const auto &request : fir.requests()) {
+          key_frame_ssrcs.push_back(request.ssrc);
+        }
+      }
+    }
+  }
+  if (key_frame_ssrcs.empty()) {
+    return;
+  }
+  std::lock_guard<std::mutex> lock(producers_lock_);
+  for (auto producer : producers_) {
+    if (std::find(key_frame_ssrcs.begin(), key_frame_ssrcs.end(),
+                  producer->GetSsrc()) != key_frame_ssrcs.end()) {
+      producer->OnKeyFrameRequest();
+    }
+  }
+}
+
+void PlainTransport::SendReports() {
+  std::vector<uint8_t> compound;
+  {
+    std::lock_guard<std::mutex> lock(producers_lock_);
+    for (auto producer : producers_) {
+      producer->AppendReport(&compound);
+    }
+  }
+  if (!compound.empty()) {
+    SendRtcp(compound.data(), compound.size());
+  }
+}
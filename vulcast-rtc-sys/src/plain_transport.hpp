@@ -0,0 +1,194 @@
+#pragma once
+
+#include <atomic>
+#include <cstdint>
+#include <memory>
+#include <mutex>
+#include <string>
+#include <thread>
+#include <vector>
+
+#include <sys/socket.h>
+
+#include <api/audio_codecs/audio_encoder.h>
+#include <api/media_stream_interface.h>
+#include <api/scoped_refptr.h>
+#include <api/video/video_frame.h>
+#include <api/video/video_sink_interface.h>
+#include <api/video_codecs/video_encoder.h>
+#include <common_audio/resampler/include/push_resampler.h>
+#include <json.hpp>
+#include <modules/rtp_rtcp/source/rtp_packet_to_send.h>
+#include <pc/srtp_session.h>
+#include <rtc_base/buffer.h>
+
+class PlainTransport;
+
+// A single RTP stream encoded from a local track and sent on a plain
+// transport, without libmediasoupclient.
+class PlainProducer {
+public:
+  virtual ~PlainProducer() = default;
+
+  // RtpParameters the producer must be produced with on the server.
+  nlohmann::json GetRtpParameters() const;
+  uint32_t GetSsrc() const { return ssrc_; }
+
+  void Pause() { paused_ = true; }
+  void Resume() { paused_ = false; }
+  bool IsPaused() const { return paused_; }
+
+  // Called from the RTCP thread of the transport when the server requests a
+  // key frame with a PLI or FIR.
+  virtual void OnKeyFrameRequest() {}
+  // Append a sender report and the CNAME of the stream, called from the RTCP
+  // thread of the transport.
+  void AppendReport(std::vector<uint8_t> *compound);
+  // Stop receiving RTCP from the transport, which must be done before the
+  // producer is deleted.
+  void RemoveFromTransport();
+
+protected:
+  PlainProducer(PlainTransport *transport, const nlohmann::json &codec);
+
+  // Fill in the payload type, sequence number and SSRC of the packet and send
+  // it on the transport.
+  void SendPacket(webrtc::RtpPacketToSend *packet);
+
+  const nlohmann::json codec_;
+  const uint8_t payload_type_;
+  const uint32_t clock_rate_;
+
+private:
+  PlainTransport *const transport_;
+  const uint32_t ssrc_;
+  const std::string cname_;
+  // nothing is sent until the producer is produced on the server
+  std::atomic<bool> paused_{true};
+
+  // stream state for sender reports, guarded by lock_
+  std::mutex lock_;
+  uint16_t sequence_number_;
+  uint32_t packet_count_{0};
+  uint32_t octet_count_{0};
+  uint32_t last_rtp_timestamp_{0};
+  int64_t last_rtp_time_ms_{-1};
+};
+
+// Encodes a local video track with VP8 at a fixed bitrate.
+class PlainVideoProducer : public PlainProducer,
+                           public rtc::VideoSinkInterface<webrtc::VideoFrame>,
+                           public webrtc::EncodedImageCallback {
+public:
+  PlainVideoProducer(PlainTransport *transport, const nlohmann::json &codec,
+                     rtc::scoped_refptr<webrtc::VideoTrackInterface> track,
+                     uint32_t max_bitrate, uint32_t max_framerate);
+  ~PlainVideoProducer() override;
+
+  void OnKeyFrameRequest() override { key_frame_requested_ = true; }
+
+  /* VideoSinkInterface */
+  void OnFrame(const webrtc::VideoFrame &frame) override;
+
+  /* EncodedImageCallback */
+  Result OnEncodedImage(const webrtc::EncodedImage &image,
+                        const webrtc::CodecSpecificInfo *info) override;
+
+private:
+  // (Re)create the encoder for frames of the given size.
+  void InitEncoder(int width, int height);
+
+  const rtc::scoped_refptr<webrtc::VideoTrackInterface> track_;
+  const uint32_t max_bitrate_;
+  const uint32_t max_framerate_;
+  const uint32_t timestamp_offset_;
+  std::atomic<bool> key_frame_requested_{true};
+
+  // only used from the thread delivering frames
+  std::unique_ptr<webrtc::VideoEncoder> encoder_;
+  int width_{0};
+  int height_{0};
+  uint16_t picture_id_;
+};
+
+// Encodes a local audio track with Opus, resampled to 48kHz.
+class PlainAudioProducer : public PlainProducer,
+                           public webrtc::AudioTrackSinkInterface {
+public:
+  PlainAudioProducer(PlainTransport *transport, const nlohmann::json &codec,
+                     rtc::scoped_refptr<webrtc::AudioTrackInterface> track,
+                     uint32_t max_bitrate);
+  ~PlainAudioProducer() override;
+
+  /* AudioTrackSinkInterface */
+  void OnData(const void *audio_data, int bits_per_sample, int sample_rate,
+              size_t number_of_channels, size_t number_of_frames) override;
+
+private:
+  const rtc::scoped_refptr<webrtc::AudioTrackInterface> track_;
+  const uint32_t max_bitrate_;
+
+  // only used from the thread delivering audio
+  std::unique_ptr<webrtc::AudioEncoder> encoder_;
+  webrtc::PushResampler<int16_t> resampler_;
+  std::vector<int16_t> resampled_;
+  rtc::Buffer encoded_;
+  uint32_t rtp_timestamp_;
+};
+
+// Client side of a mediasoup PlainTransport with comedia enabled. RTP and
+// RTCP are sent from a single UDP socket, SRTP protected if the server
+// transport has srtpParameters. A thread receives RTCP from the server to
+// handle key frame requests and sends sender reports every second.
+class PlainTransport {
+public:
+  // options are the id, tuple, rtcpTuple and srtpParameters of the server
+  // transport. Codecs are picked from router_rtp_capabilities.
+  PlainTransport(const nlohmann::json &options,
+                 const nlohmann::json &router_rtp_capabilities);
+  // All producers must be deleted first.
+  ~PlainTransport();
+
+  const std::string &GetId() const { return id_; }
+  // SrtpParameters the server transport must be connected with, or null if
+  // SRTP is not enabled.
+  const nlohmann::json &GetSrtpParameters() const { return srtp_parameters_; }
+
+  // Send track, encoded with VP8 or Opus depending on its kind.
+  std::unique_ptr<PlainProducer>
+  Produce(webrtc::MediaStreamTrackInterface *track, uint32_t max_bitrate,
+          uint32_t max_framerate);
+  // Stop delivering RTCP to a producer before it is deleted.
+  void RemoveProducer(PlainProducer *producer);
+
+  void SendRtp(const uint8_t *data, size_t size);
+
+private:
+  void SendRtcp(const uint8_t *data, size_t size);
+  void Send(const sockaddr_storage &addr, const uint8_t *data, size_t size,
+            bool rtcp);
+  // Receive RTCP until the transport is deleted.
+  void Run();
+  void OnRtcp(uint8_t *data, size_t size);
+  void SendReports();
+
+  std::string id_;
+  nlohmann::json router_rtp_capabilities_;
+  nlohmann::json srtp_parameters_;
+
+  int socket_{-1};
+  sockaddr_storage rtp_addr_{};
+  sockaddr_storage rtcp_addr_{};
+
+  // libsrtp sessions are not thread safe, guarded by send_lock_
+  std::mutex send_lock_;
+  std::unique_ptr<cricket::SrtpSession> send_session_;
+  // only used from the RTCP thread
+  std::unique_ptr<cricket::SrtpSession> recv_session_;
+
+  std::mutex producers_lock_;
+  std::vector<PlainProducer *> producers_;
+
+  std::atomic<bool> stopped_{false};
+  std::thread thread_;
+};
@@ -2,60 +2,98 @@
 
 #include <glog/logging.h>
 
+#include "error.hpp"
 #include "ffi.hpp"
 
+namespace {
+[[noreturn]] void ThrowSignallingError(const std::string &step, Error error) {
+  if (error.message == nullptr) {
+    throw FfiException(ERR_SIGNALLING, step + " failed");
+  }
+  std::string message(error.message);
+  rust_unmarshal_str(error.message);
+  throw FfiException(error.kind, message);
+}
+} // namespace
+
 Signaller::Signaller(const void *ctx, SignalHandler handler)
     : ctx_(ctx), handler_(handler) {}
 
 nlohmann::json Signaller::GetServerRtpCapabilities() const {
-  char *rtp_capabilities_cstr = handler_.server_rtp_capabilities(ctx_);
-  DCHECK(rtp_capabilities_cstr != nullptr);
+  Error error{ERR_NONE, nullptr};
+  char *rtp_capabilities_cstr = handler_.server_rtp_capabilities(ctx_, &error);
+  if (rtp_capabilities_cstr == nullptr) {
+    ThrowSignallingError("server_rtp_capabilities", error);
+  }
   auto rtp_capabilities = nlohmann::json::parse(rtp_capabilities_cstr);
-  cpp_unmarshal_str(rtp_capabilities_cstr);
+  rust_unmarshal_str(rtp_capabilities_cstr);
   return rtp_capabilities;
 }
 
 nlohmann::json Signaller::CreateWebrtcTransport() const {
-  char *webrtc_transport_options_cstr = handler_.create_webrtc_transport(ctx_);
-  DCHECK(webrtc_transport_options_cstr != nullptr);
+  Error error{ERR_NONE, nullptr};
+  char *webrtc_transport_options_cstr =
+      handler_.create_webrtc_transport(ctx_, &error);
+  if (webrtc_transport_options_cstr == nullptr) {
+    ThrowSignallingError("create_webrtc_transport", error);
+  }
   auto webrtc_transport_options =
       nlohmann::json::parse(webrtc_transport_options_cstr);
-  cpp_unmarshal_str(webrtc_transport_options_cstr);
+  rust_unmarshal_str(webrtc_transport_options_cstr);
   return webrtc_transport_options;
 }
 
 void Signaller::OnRtpCapabilities(const nlohmann::json &rtp_caps) const {
   auto rtp_caps_str = rtp_caps.dump();
-  handler_.on_rtp_capabilities(ctx_, rtp_caps_str.c_str());
+  Error error{ERR_NONE, nullptr};
+  if (!handler_.on_rtp_capabilities(ctx_, rtp_caps_str.c_str(), &error)) {
+    ThrowSignallingError("on_rtp_capabilities", error);
+  }
 }
 
 void Signaller::OnConnectWebrtcTransport(
     const std::string &transport_id,
     const nlohmann::json &dtls_parameters) const {
   auto dtls_params_str = dtls_parameters.dump();
-  handler_.on_connect_webrtc_transport(ctx_, transport_id.c_str(),
-                                       dtls_params_str.c_str());
+  Error error{ERR_NONE, nullptr};
+  if (!handler_.on_connect_webrtc_transport(ctx_, transport_id.c_str(),
+                                            dtls_params_str.c_str(), &error)) {
+    ThrowSignallingError("on_connect_webrtc_transport", error);
+  }
 }
 
 std::string Signaller::OnProduce(const std::string &transport_id,
                                  const std::string &kind,
                                  const nlohmann::json &rtp_parameters) const {
   auto rtp_parameters_str = rtp_parameters.dump();
-  char *producer_id_cstr = handler_.on_produce(
-      ctx_, transport_id.c_str(), kind.c_str(), rtp_parameters_str.c_str());
-  DCHECK(producer_id_cstr != nullptr);
+  Error error{ERR_NONE, nullptr};
+  char *producer_id_cstr =
+      handler_.on_produce(ctx_, transport_id.c_str(), kind.c_str(),
+                          rtp_parameters_str.c_str(), &error);
+  if (producer_id_cstr == nullptr) {
+    ThrowSignallingError("on_produce", error);
+  }
   std::string producer_id(producer_id_cstr);
+  rust_unmarshal_str(producer_id_cstr);
   return producer_id;
 }
 
 std::string
 Signaller::OnProduceData(const std::string &transport_id,
-                         const nlohmann::json &sctp_stream_parameters) const {
+                         const nlohmann::json &sctp_stream_parameters,
+                         const std::string &label, const std::string &protocol,
+                         const nlohmann::json &app_data) const {
   auto sctp_stream_parameters_str = sctp_stream_parameters.dump();
+  auto app_data_str = app_data.dump();
+  Error error{ERR_NONE, nullptr};
   char *data_producer_id_cstr = handler_.on_produce_data(
-      ctx_, transport_id.c_str(), sctp_stream_parameters_str.c_str());
-  DCHECK(data_producer_id_cstr != nullptr);
+      ctx_, transport_id.c_str(), sctp_stream_parameters_str.c_str(),
+      label.c_str(), protocol.c_str(), app_data_str.c_str(), &error);
+  if (data_producer_id_cstr == nullptr) {
+    ThrowSignallingError("on_produce_data", error);
+  }
   std::string data_producer_id(data_producer_id_cstr);
+  rust_unmarshal_str(data_producer_id_cstr);
   return data_producer_id;
 }
 
@@ -76,8 +114,23 @@ void Signaller::OnDataProducerStateChanged(const std::string &data_producer_id,
                                           state.c_str());
 }
 
+void Signaller::OnDataProducerBufferedAmountChange(
+    const std::string &data_producer_id, uint64_t buffered_amount) const {
+  handler_.on_data_producer_buffered_amount_change(
+      ctx_, data_producer_id.c_str(), buffered_amount);
+}
+
 void Signaller::OnConnectionStateChanged(const std::string &transport_id,
+                                         const std::string &direction,
                                          const std::string &state) const {
   handler_.on_connection_state_changed(ctx_, transport_id.c_str(),
-                                       state.c_str());
+                                       direction.c_str(), state.c_str());
+}
+
+void Signaller::OnProducerTransportClose(const std::string &producer_id) const {
+  handler_.on_producer_transport_close(ctx_, producer_id.c_str());
+}
+
+void Signaller::OnConsumerTransportClose(const std::string &consumer_id) const {
+  handler_.on_consumer_transport_close(ctx_, consumer_id.c_str());
 }
\ No newline at end of file
@@ -20,7 +20,10 @@ struct Signaller {
                         const nlohmann::json &rtp_parameters) const;
 
   std::string OnProduceData(const std::string &transport_id,
-                            const nlohmann::json &sctp_stream_parameters) const;
+                            const nlohmann::json &sctp_stream_parameters,
+                            const std::string &label,
+                            const std::string &protocol,
+                            const nlohmann::json &app_data) const;
 
   void OnDataConsumerMessage(const std::string &data_consumer_id,
                              const char *data, std::size_t len) const;
@@ -30,8 +33,14 @@ struct Signaller {
   void OnDataProducerStateChanged(const std::string &data_producer_id,
                                   const std::string &state) const;
 
+  void OnDataProducerBufferedAmountChange(const std::string &data_producer_id,
+                                          uint64_t buffered_amount) const;
+
   void OnConnectionStateChanged(const std::string &transport_id,
+                                const std::string &direction,
                                 const std::string &state) const;
+  void OnProducerTransportClose(const std::string &producer_id) const;
+  void OnConsumerTransportClose(const std::string &consumer_id) const;
 
 private:
   const void *ctx_;
@@ -2,6 +2,7 @@
 
 #include <memory>
 #include <unordered_map>
+#include <vector>
 
 #include <common_video/libyuv/include/webrtc_libyuv.h>
 #include <glog/logging.h>
@@ -10,7 +11,12 @@
 #include <modules/video_capture/video_capture_factory.h>
 
 #include "broadcaster.hpp"
+#include "error.hpp"
+#include "foreign_audio_source.hpp"
+#include "foreign_sink.hpp"
+#include "frame_monitor.hpp"
 #include "media_stream_track_factory.hpp"
+#include "plain_transport.hpp"
 
 namespace {
 [[nodiscard]] char *cpp_marshal_str(const std::string &str) {
@@ -18,6 +24,55 @@ namespace {
   std::strcpy(buf, str.c_str());
   return buf;
 }
+
+void set_error(Error *error, ErrorKind kind, const std::string &message) {
+  LOG(ERROR) << "error " << kind << ": " << message;
+  error->kind = kind;
+  error->message = cpp_marshal_str(message);
+}
+
+// Invoke f, reporting any exception it throws through error rather than
+// letting it unwind across the FFI boundary. Returns nullptr or false on
+// failure.
+template <typename F> auto catch_error(Error *error, F &&f) -> decltype(f()) {
+  error->kind = ERR_NONE;
+  error->message = nullptr;
+  try {
+    return f();
+  } catch (const FfiException &e) {
+    set_error(error, e.kind(), e.what());
+  } catch (const std::exception &e) {
+    set_error(error, ERR_UNKNOWN, e.what());
+  }
+  return decltype(f()){};
+}
+
+// Parse a JSON array of RtpEncodingParameters, as used by mediasoup-client.
+std::vector<webrtc::RtpEncodingParameters>
+ParseEncodings(const nlohmann::json &json) {
+  std::vector<webrtc::RtpEncodingParameters> encodings;
+  for (const auto &item : json) {
+    webrtc::RtpEncodingParameters encoding;
+    if (item.contains("rid")) {
+      encoding.rid = item["rid"].get<std::string>();
+    }
+    if (item.contains("maxBitrate")) {
+      encoding.max_bitrate_bps = item["maxBitrate"].get<int>();
+    }
+    if (item.contains("scaleResolutionDownBy")) {
+      encoding.scale_resolution_down_by =
+          item["scaleResolutionDownBy"].get<double>();
+    }
+    if (item.contains("maxFramerate")) {
+      encoding.max_framerate = item["maxFramerate"].get<double>();
+    }
+    if (item.contains("scalabilityMode")) {
+      encoding.scalability_mode = item["scalabilityMode"].get<std::string>();
+    }
+    encodings.push_back(encoding);
+  }
+  return encodings;
+}
 } // namespace
 
 void init(const char *argv0) {
@@ -28,85 +83,417 @@ void init(const char *argv0) {
   mediasoupclient::Logger::SetDefaultHandler();
 }
 
-Broadcaster *broadcaster_new(const void *ctx, SignalHandler signal_handler) {
+Broadcaster *broadcaster_new(const void *ctx, SignalHandler signal_handler,
+                             Error *error) {
   LOG(INFO) << "broadcaster_new(" << std::hex << ctx << ")";
-  auto broadcaster = new Broadcaster(Signaller(ctx, signal_handler));
-  broadcaster->Start();
-  return broadcaster;
+  return catch_error(error, [&]() {
+    auto broadcaster =
+        std::make_unique<Broadcaster>(Signaller(ctx, signal_handler));
+    broadcaster->Start();
+    return broadcaster.release();
+  });
 }
 void broadcaster_delete(Broadcaster *broadcaster) {
   LOG(INFO) << "broadcaster_delete(" << std::hex << broadcaster << ")";
   delete broadcaster;
 }
-char *broadcaster_marshal_recv_transport_id(Broadcaster *b) {
-  return cpp_marshal_str(b->GetRecvTransportId());
+char *broadcaster_marshal_recv_transport_id(Broadcaster *b, Error *error) {
+  return catch_error(
+      error, [&]() { return cpp_marshal_str(b->GetRecvTransportId()); });
+}
+bool broadcaster_restart_ice(Broadcaster *b, const char *transport_id,
+                             const char *ice_parameters, Error *error) {
+  LOG(INFO) << "broadcaster_restart_ice(" << std::hex << b << ","
+            << transport_id << "," << ice_parameters << ")";
+  return catch_error(error, [&]() {
+    b->RestartIce(transport_id, nlohmann::json::parse(ice_parameters));
+    return true;
+  });
+}
+bool broadcaster_recreate_transports(Broadcaster *b, Error *error) {
+  LOG(INFO) << "broadcaster_recreate_transports(" << std::hex << b << ")";
+  return catch_error(error, [&]() {
+    b->RecreateTransports();
+    return true;
+  });
+}
+void broadcaster_delete_closed_transports(Broadcaster *b) {
+  LOG(INFO) << "broadcaster_delete_closed_transports(" << std::hex << b << ")";
+  b->DeleteClosedTransports();
+}
+char *broadcaster_marshal_stats(Broadcaster *b, Error *error) {
+  LOG(INFO) << "broadcaster_marshal_stats(" << std::hex << b << ")";
+  return catch_error(error,
+                     [&]() { return cpp_marshal_str(b->GetStats().dump()); });
 }
 
 mediasoupclient::DataConsumer *
 data_consumer_new(Broadcaster *b, const char *data_consumer_id,
                   const char *data_producer_id,
-                  const char *sctp_stream_parameters) {
+                  const char *sctp_stream_parameters, const char *label,
+                  const char *protocol, Error *error) {
   LOG(INFO) << "data_consumer_new(" << std::hex << b << "," << data_consumer_id
-            << "," << data_producer_id << "," << sctp_stream_parameters << ")";
-  return b->ConsumeData(data_consumer_id, data_producer_id,
-                        nlohmann::json::parse(sctp_stream_parameters));
+            << "," << data_producer_id << "," << sctp_stream_parameters << ","
+            << label << "," << protocol << ")";
+  return catch_error(error, [&]() {
+    return b->ConsumeData(data_consumer_id, data_producer_id,
+                          nlohmann::json::parse(sctp_stream_parameters), label,
+                          protocol);
+  });
 }
 void data_consumer_delete(mediasoupclient::DataConsumer *consumer) {
   LOG(INFO) << "data_consumer_delete(" << consumer->GetId() << ")";
   consumer->Close();
 }
 
-mediasoupclient::Producer *producer_new_from_default_audio(Broadcaster *b) {
-  LOG(INFO) << "producer_new_from_default_audio(" << std::hex << b << ")";
-  CHECK(b->CanProduceAudio());
-  auto audio_track = CreateAudioTrack();
-  nlohmann::json codec_options = {{"opusStereo", true}, {"opusDtx", true}};
-  auto producer = b->Produce(audio_track, nullptr, codec_options);
-  CHECK(producer != nullptr);
-  return producer;
+mediasoupclient::Consumer *consumer_new(Broadcaster *b, const char *consumer_id,
+                                        const char *producer_id,
+                                        const char *kind,
+                                        const char *rtp_parameters,
+                                        Error *error) {
+  LOG(INFO) << "consumer_new(" << std::hex << b << "," << consumer_id << ","
+            << producer_id << "," << kind << "," << rtp_parameters << ")";
+  return catch_error(error, [&]() {
+    return b->Consume(consumer_id, producer_id, kind,
+                      nlohmann::json::parse(rtp_parameters));
+  });
 }
-mediasoupclient::Producer *producer_new_from_fake_video(Broadcaster *b) {
-  LOG(INFO) << "producer_new_from_fake_video(" << std::hex << b << ")";
-  CHECK(b->CanProduceVideo());
-  auto video_track = CreateSquaresVideoTrack();
-  auto producer = b->Produce(video_track);
-  CHECK(producer != nullptr);
-  return producer;
-}
-mediasoupclient::Producer *
-producer_new_from_vcm_capturer(Broadcaster *b, int device_idx, uint32_t width,
-                               uint32_t height, uint32_t fps, int video_type) {
-  LOG(INFO) << "producer_new_from_vcm_capturer(" << std::hex << b << ")";
-  auto video_track =
-      CreateVcmCapturerVideoTrack(device_idx, width, height, fps,
-                                  static_cast<webrtc::VideoType>(video_type));
-  auto producer = b->Produce(video_track);
-  CHECK(producer != nullptr);
-  return producer;
-}
-mediasoupclient::Producer *
-producer_new_from_foreign(Broadcaster *b, uint32_t width, uint32_t height,
-                          uint32_t fps, void *ctx, frame_callback_t callback) {
-  LOG(INFO) << "producer_new_from_foreign(" << std::hex << b << ")";
-  auto video_track = CreateForeignVideoTrack(width, height, fps, ctx, callback);
-  auto producer = b->Produce(video_track);
-  CHECK(producer != nullptr);
-  return producer;
+void consumer_delete(mediasoupclient::Consumer *consumer) {
+  LOG(INFO) << "consumer_delete(" << consumer->GetId() << ")";
+  consumer->Close();
+}
+ForeignVideoSink *video_sink_new(mediasoupclient::Consumer *consumer,
+                                 VideoFrameFormat format, const void *ctx,
+                                 video_sink_callback_t callback) {
+  LOG(INFO) << "video_sink_new(" << consumer->GetId() << "," << format << ")";
+  auto track =
+      static_cast<webrtc::VideoTrackInterface *>(consumer->GetTrack());
+  auto sink = new ForeignVideoSink(format, ctx, callback);
+  track->AddOrUpdateSink(sink, rtc::VideoSinkWants());
+  return sink;
+}
+void video_sink_delete(mediasoupclient::Consumer *consumer,
+                       ForeignVideoSink *sink) {
+  LOG(INFO) << "video_sink_delete(" << consumer->GetId() << ")";
+  auto track =
+      static_cast<webrtc::VideoTrackInterface *>(consumer->GetTrack());
+  track->RemoveSink(sink);
+  delete sink;
+}
+ForeignAudioSink *audio_sink_new(mediasoupclient::Consumer *consumer,
+                                 const void *ctx,
+                                 audio_sink_callback_t callback) {
+  LOG(INFO) << "audio_sink_new(" << consumer->GetId() << ")";
+  auto track =
+      static_cast<webrtc::AudioTrackInterface *>(consumer->GetTrack());
+  auto sink = new ForeignAudioSink(ctx, callback);
+  track->AddSink(sink);
+  return sink;
+}
+void audio_sink_delete(mediasoupclient::Consumer *consumer,
+                       ForeignAudioSink *sink) {
+  LOG(INFO) << "audio_sink_delete(" << consumer->GetId() << ")";
+  auto track =
+      static_cast<webrtc::AudioTrackInterface *>(consumer->GetTrack());
+  track->RemoveSink(sink);
+  delete sink;
+}
+
+webrtc::MediaStreamTrackInterface *
+track_new_from_alsa(const char *device_name, int device_idx,
+                    AudioProcessingOptions options, Error *error) {
+  LOG(INFO) << "track_new_from_alsa("
+            << (device_name != nullptr ? device_name : "") << ","
+            << device_idx << ")";
+  return catch_error(error, [&]() -> webrtc::MediaStreamTrackInterface * {
+    cricket::AudioOptions audio_options;
+    audio_options.echo_cancellation = options.echo_cancellation;
+    audio_options.noise_suppression = options.noise_suppression;
+    audio_options.auto_gain_control = options.auto_gain_control;
+    audio_options.highpass_filter = options.highpass_filter;
+    return CreateAudioTrack(device_name, device_idx, audio_options).release();
+  });
+}
+webrtc::MediaStreamTrackInterface *track_new_from_fake_video(Error *error) {
+  LOG(INFO) << "track_new_from_fake_video()";
+  return catch_error(error, [&]() -> webrtc::MediaStreamTrackInterface * {
+    return CreateSquaresVideoTrack().release();
+  });
+}
+webrtc::MediaStreamTrackInterface *
+track_new_from_vcm_capturer(const char *device_name, int device_idx,
+                            VideoCapability *capability, bool best_match,
+                            char **device_unique_id, Error *error) {
+  LOG(INFO) << "track_new_from_vcm_capturer("
+            << (device_name != nullptr ? device_name : "") << ","
+            << device_idx << "," << capability->width << ","
+            << capability->height << "," << capability->max_fps << ","
+            << capability->video_type << "," << best_match << ")";
+  return catch_error(error, [&]() -> webrtc::MediaStreamTrackInterface * {
+    webrtc::VideoCaptureCapability video_caps;
+    video_caps.width = capability->width;
+    video_caps.height = capability->height;
+    video_caps.maxFPS = capability->max_fps;
+    video_caps.videoType =
+        static_cast<webrtc::VideoType>(capability->video_type);
+    video_caps.interlaced = capability->interlaced;
+    std::string unique_id;
+    auto track = CreateVcmCapturerVideoTrack(device_name, device_idx,
+                                             video_caps, best_match, unique_id);
+    capability->width = video_caps.width;
+    capability->height = video_caps.height;
+    capability->max_fps = video_caps.maxFPS;
+    capability->video_type = static_cast<int>(video_caps.videoType);
+    capability->interlaced = video_caps.interlaced;
+    *device_unique_id = cpp_marshal_str(unique_id);
+    return track.release();
+  });
+}
+webrtc::MediaStreamTrackInterface *
+track_new_from_foreign(uint32_t width, uint32_t height, uint32_t fps, void *ctx,
+                       frame_callback_t callback, Error *error) {
+  LOG(INFO) << "track_new_from_foreign(" << width << "," << height << ","
+            << fps << "," << std::hex << ctx << ")";
+  return catch_error(error, [&]() -> webrtc::MediaStreamTrackInterface * {
+    return CreateForeignVideoTrack(width, height, fps, ctx, callback)
+        .release();
+  });
+}
+webrtc::MediaStreamTrackInterface *
+track_new_from_audio_source(ForeignAudioSource *source, Error *error) {
+  LOG(INFO) << "track_new_from_audio_source(" << std::hex << source << ")";
+  return catch_error(error, [&]() -> webrtc::MediaStreamTrackInterface * {
+    return CreateForeignAudioTrack(source).release();
+  });
+}
+void track_set_content_hint(webrtc::MediaStreamTrackInterface *track,
+                            ContentHint hint) {
+  LOG(INFO) << "track_set_content_hint(" << std::hex << track << ","
+            << std::dec << hint << ")";
+  if (track->kind() != webrtc::MediaStreamTrackInterface::kVideoKind) {
+    return;
+  }
+  auto video_track = static_cast<webrtc::VideoTrackInterface *>(track);
+  switch (hint) {
+  case CONTENT_HINT_NONE:
+    video_track->set_content_hint(
+        webrtc::VideoTrackInterface::ContentHint::kNone);
+    break;
+  case CONTENT_HINT_MOTION:
+    video_track->set_content_hint(
+        webrtc::VideoTrackInterface::ContentHint::kFluid);
+    break;
+  case CONTENT_HINT_DETAIL:
+    video_track->set_content_hint(
+        webrtc::VideoTrackInterface::ContentHint::kDetailed);
+    break;
+  case CONTENT_HINT_TEXT:
+    video_track->set_content_hint(
+        webrtc::VideoTrackInterface::ContentHint::kText);
+    break;
+  }
+}
+void track_delete(webrtc::MediaStreamTrackInterface *track) {
+  LOG(INFO) << "track_delete(" << std::hex << track << ")";
+  CHECK(track != nullptr);
+  track->Release();
+}
+
+FrameMonitor *frame_monitor_new(webrtc::MediaStreamTrackInterface *track) {
+  LOG(INFO) << "frame_monitor_new(" << std::hex << track << ")";
+  return new FrameMonitor(static_cast<webrtc::VideoTrackInterface *>(track));
+}
+uint64_t frame_monitor_frame_count(FrameMonitor *monitor) {
+  return monitor->frame_count();
+}
+void frame_monitor_delete(FrameMonitor *monitor) {
+  LOG(INFO) << "frame_monitor_delete(" << std::hex << monitor << ")";
+  delete monitor;
+}
+
+ForeignAudioSource *audio_source_new(uint32_t sample_rate, size_t channels,
+                                     const void *ctx,
+                                     audio_source_callback_t callback,
+                                     Error *error) {
+  LOG(INFO) << "audio_source_new(" << sample_rate << "," << channels << ","
+            << std::hex << ctx << ")";
+  return catch_error(error, [&]() {
+    if (sample_rate == 0 || sample_rate % 100 != 0) {
+      throw std::invalid_argument(
+          "sample rate must be a multiple of 100, got " +
+          std::to_string(sample_rate));
+    }
+    if (channels == 0) {
+      throw std::invalid_argument("audio source must have channels");
+    }
+    auto source = new rtc::RefCountedObject<ForeignAudioSource>(
+        sample_rate, channels, ctx, callback);
+    source->AddRef();
+    return static_cast<ForeignAudioSource *>(source);
+  });
+}
+void audio_source_delete(ForeignAudioSource *source) {
+  LOG(INFO) << "audio_source_delete(" << std::hex << source << ")";
+  CHECK(source != nullptr);
+  source->Stop();
+  source->Release();
+}
+
+mediasoupclient::Producer *producer_new(Broadcaster *b,
+                                        webrtc::MediaStreamTrackInterface *track,
+                                        const char *encodings,
+                                        const char *codec_options,
+                                        const char *codec, Error *error) {
+  LOG(INFO) << "producer_new(" << std::hex << b << "," << std::hex << track
+            << "," << (encodings ? encodings : "nullptr") << ","
+            << codec_options << "," << (codec ? codec : "nullptr") << ")";
+  return catch_error(error, [&]() {
+    const std::string codec_mime_type = codec ? codec : "";
+    if (encodings == nullptr) {
+      return b->Produce(track, nullptr, nlohmann::json::parse(codec_options),
+                        codec_mime_type);
+    }
+    auto parsed_encodings = ParseEncodings(nlohmann::json::parse(encodings));
+    return b->Produce(track, &parsed_encodings,
+                      nlohmann::json::parse(codec_options), codec_mime_type);
+  });
+}
+bool producer_replace_track(mediasoupclient::Producer *producer,
+                            webrtc::MediaStreamTrackInterface *track,
+                            Error *error) {
+  LOG(INFO) << "producer_replace_track(" << std::hex << producer << ","
+            << std::hex << track << ")";
+  return catch_error(error, [&]() {
+    producer->ReplaceTrack(track);
+    return true;
+  });
+}
+char *producer_marshal_id(mediasoupclient::Producer *producer) {
+  return cpp_marshal_str(producer->GetId());
+}
+char *producer_marshal_kind(mediasoupclient::Producer *producer) {
+  return cpp_marshal_str(producer->GetKind());
+}
+char *producer_marshal_rtp_parameters(mediasoupclient::Producer *producer) {
+  return cpp_marshal_str(producer->GetRtpParameters().dump());
+}
+bool producer_set_encoding_parameters(mediasoupclient::Producer *producer,
+                                      const char *encoding_parameters,
+                                      Error *error) {
+  LOG(INFO) << "producer_set_encoding_parameters(" << std::hex << producer
+            << "," << encoding_parameters << ")";
+  return catch_error(error, [&]() {
+    auto json = nlohmann::json::parse(encoding_parameters);
+    auto sender = producer->GetRtpSender();
+    webrtc::RtpParameters parameters = sender->GetParameters();
+    if (json.size() > parameters.encodings.size()) {
+      throw FfiException(ERR_UNKNOWN,
+                         "producer has " +
+                             std::to_string(parameters.encodings.size()) +
+                             " encodings");
+    }
+    for (size_t i = 0; i < json.size(); ++i) {
+      const auto &item = json[i];
+      auto &encoding = parameters.encodings[i];
+      if (item.contains("maxBitrate")) {
+        encoding.max_bitrate_bps = item["maxBitrate"].get<int>();
+      }
+      if (item.contains("maxFramerate")) {
+        encoding.max_framerate = item["maxFramerate"].get<double>();
+      }
+      if (item.contains("scaleResolutionDownBy")) {
+        encoding.scale_resolution_down_by =
+            item["scaleResolutionDownBy"].get<double>();
+      }
+      if (item.contains("active")) {
+        encoding.active = item["active"].get<bool>();
+      }
+    }
+    webrtc::RTCError result = sender->SetParameters(parameters);
+    if (!result.ok()) {
+      throw FfiException(ERR_UNKNOWN, result.message());
+    }
+    return true;
+  });
+}
+bool producer_set_degradation_preference(mediasoupclient::Producer *producer,
+                                         DegradationPreference preference,
+                                         Error *error) {
+  LOG(INFO) << "producer_set_degradation_preference(" << std::hex << producer
+            << "," << std::dec << preference << ")";
+  return catch_error(error, [&]() {
+    auto sender = producer->GetRtpSender();
+    webrtc::RtpParameters parameters = sender->GetParameters();
+    switch (preference) {
+    case DEGRADATION_BALANCED:
+      parameters.degradation_preference =
+          webrtc::DegradationPreference::BALANCED;
+      break;
+    case DEGRADATION_MAINTAIN_FRAMERATE:
+      parameters.degradation_preference =
+          webrtc::DegradationPreference::MAINTAIN_FRAMERATE;
+      break;
+    case DEGRADATION_MAINTAIN_RESOLUTION:
+      parameters.degradation_preference =
+          webrtc::DegradationPreference::MAINTAIN_RESOLUTION;
+      break;
+    }
+    webrtc::RTCError result = sender->SetParameters(parameters);
+    if (!result.ok()) {
+      throw FfiException(ERR_UNKNOWN, result.message());
+    }
+    return true;
+  });
+}
+bool producer_set_max_spatial_layer(mediasoupclient::Producer *producer,
+                                    uint8_t spatial_layer, Error *error) {
+  LOG(INFO) << "producer_set_max_spatial_layer(" << std::hex << producer << ","
+            << std::dec << static_cast<int>(spatial_layer) << ")";
+  return catch_error(error, [&]() {
+    producer->SetMaxSpatialLayer(spatial_layer);
+    return true;
+  });
+}
+char *producer_marshal_stats(mediasoupclient::Producer *producer,
+                             Error *error) {
+  LOG(INFO) << "producer_marshal_stats(" << std::hex << producer << ")";
+  return catch_error(
+      error, [&]() { return cpp_marshal_str(producer->GetStats().dump()); });
+}
+void producer_pause(mediasoupclient::Producer *producer) {
+  LOG(INFO) << "producer_pause(" << std::hex << producer << ")";
+  producer->Pause();
+}
+void producer_resume(mediasoupclient::Producer *producer) {
+  LOG(INFO) << "producer_resume(" << std::hex << producer << ")";
+  producer->Resume();
+}
+bool producer_is_paused(mediasoupclient::Producer *producer) {
+  return producer->IsPaused();
 }
 void producer_delete(mediasoupclient::Producer *producer) {
   LOG(INFO) << "producer_delete(" << std::hex << producer << ")";
   CHECK(producer != nullptr);
   producer->Close();
 }
-mediasoupclient::DataProducer *data_producer_new(Broadcaster *b) {
-  LOG(INFO) << "data_producer_new(" << std::hex << b << ")";
-  auto data_producer = b->ProduceData();
-  CHECK(data_producer != nullptr);
-  return data_producer;
+mediasoupclient::DataProducer *
+data_producer_new(Broadcaster *b, bool ordered, uint16_t max_packet_life_time,
+                  uint16_t max_retransmits, const char *label,
+                  const char *protocol, const char *app_data, Error *error) {
+  LOG(INFO) << "data_producer_new(" << std::hex << b << "," << ordered << ","
+            << std::dec << max_packet_life_time << "," << max_retransmits
+            << "," << label << "," << protocol << "," << app_data << ")";
+  return catch_error(error, [&]() {
+    return b->ProduceData(ordered, max_packet_life_time, max_retransmits,
+                          label, protocol, nlohmann::json::parse(app_data));
+  });
 }
 char *data_producer_marshal_id(mediasoupclient::DataProducer *data_producer) {
   return cpp_marshal_str(data_producer->GetId());
 }
+uint16_t data_producer_stream_id(mediasoupclient::DataProducer *data_producer) {
+  return data_producer->GetSctpStreamParameters()["streamId"].get<uint16_t>();
+}
 void data_producer_send(mediasoupclient::DataProducer *data_producer,
                         const uint8_t *data, size_t len) {
   webrtc::DataBuffer data_buffer(rtc::CopyOnWriteBuffer(data, len), true);
@@ -118,6 +505,101 @@ void data_producer_delete(mediasoupclient::DataProducer *data_producer) {
   data_producer->Close();
 }
 
+PlainTransport *plain_transport_new(const char *options,
+                                    const char *router_rtp_capabilities,
+                                    Error *error) {
+  LOG(INFO) << "plain_transport_new(" << options << ")";
+  return catch_error(error, [&]() {
+    return new PlainTransport(nlohmann::json::parse(options),
+                              nlohmann::json::parse(router_rtp_capabilities));
+  });
+}
+char *plain_transport_marshal_id(PlainTransport *transport) {
+  return cpp_marshal_str(transport->GetId());
+}
+char *plain_transport_marshal_srtp_parameters(PlainTransport *transport) {
+  const auto &srtp_parameters = transport->GetSrtpParameters();
+  if (srtp_parameters.is_null()) {
+    return nullptr;
+  }
+  return cpp_marshal_str(srtp_parameters.dump());
+}
+void plain_transport_delete(PlainTransport *transport) {
+  LOG(INFO) << "plain_transport_delete(" << std::hex << transport << ")";
+  delete transport;
+}
+PlainProducer *plain_producer_new(PlainTransport *transport,
+                                  webrtc::MediaStreamTrackInterface *track,
+                                  uint32_t max_bitrate, uint32_t max_framerate,
+                                  Error *error) {
+  LOG(INFO) << "plain_producer_new(" << std::hex << transport << "," << track
+            << std::dec << "," << max_bitrate << "," << max_framerate << ")";
+  return catch_error(error, [&]() {
+    return transport->Produce(track, max_bitrate, max_framerate).release();
+  });
+}
+char *plain_producer_marshal_rtp_parameters(PlainProducer *producer) {
+  return cpp_marshal_str(producer->GetRtpParameters().dump());
+}
+void plain_producer_pause(PlainProducer *producer) {
+  LOG(INFO) << "plain_producer_pause(" << std::hex << producer << ")";
+  producer->Pause();
+}
+void plain_producer_resume(PlainProducer *producer) {
+  LOG(INFO) << "plain_producer_resume(" << std::hex << producer << ")";
+  producer->Resume();
+}
+void plain_producer_delete(PlainProducer *producer) {
+  LOG(INFO) << "plain_producer_delete(" << std::hex << producer << ")";
+  CHECK(producer != nullptr);
+  producer->RemoveFromTransport();
+  delete producer;
+}
+
+char *marshal_video_devices(Error *error) {
+  LOG(INFO) << "marshal_video_devices()";
+  return catch_error(error, [&]() {
+    std::unique_ptr<webrtc::VideoCaptureModule::DeviceInfo> info(
+        webrtc::VideoCaptureFactory::CreateDeviceInfo());
+    if (!info) {
+      throw std::runtime_error("could not get video capture device info");
+    }
+    auto devices = nlohmann::json::array();
+    const uint32_t device_count = info->NumberOfDevices();
+    for (uint32_t i = 0; i < device_count; ++i) {
+      char device_name[256] = {0};
+      char unique_name[256] = {0};
+      if (info->GetDeviceName(i, device_name, sizeof(device_name), unique_name,
+                              sizeof(unique_name)) != 0) {
+        LOG(WARNING) << "could not get name of video capture device " << i;
+        continue;
+      }
+      auto capabilities = nlohmann::json::array();
+      const int cap_count = info->NumberOfCapabilities(unique_name);
+      for (int j = 0; j < cap_count; ++j) {
+        webrtc::VideoCaptureCapability video_caps;
+        if (info->GetCapability(unique_name, j, video_caps) != 0) {
+          continue;
+        }
+        capabilities.push_back({
+            {"width", video_caps.width},
+            {"height", video_caps.height},
+            {"maxFps", video_caps.maxFPS},
+            {"videoType", static_cast<int>(video_caps.videoType)},
+            {"interlaced", video_caps.interlaced},
+        });
+      }
+      devices.push_back({
+          {"index", i},
+          {"name", device_name},
+          {"uniqueId", unique_name},
+          {"capabilities", capabilities},
+      });
+    }
+    return cpp_marshal_str(devices.dump());
+  });
+}
+
 void debug_enumerate_capture_devices() {
   LOG(INFO) << "debug_enumerate_capture_devices()";
   std::unique_ptr<webrtc::VideoCaptureModule::DeviceInfo> info(
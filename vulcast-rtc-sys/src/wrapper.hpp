@@ -4,33 +4,136 @@
 #include <cstdint>
 
 class Broadcaster;
+class ForeignAudioSink;
+class ForeignAudioSource;
+class ForeignVideoSink;
+class FrameMonitor;
+class PlainProducer;
+class PlainTransport;
 namespace mediasoupclient {
+class Consumer;
 class DataConsumer;
 class DataProducer;
 class Producer;
 } // namespace mediasoupclient
+namespace webrtc {
+class MediaStreamTrackInterface;
+} // namespace webrtc
+
+// Kind of error reported by fallible functions.
+enum ErrorKind {
+  ERR_NONE,
+  // A signalling callback failed.
+  ERR_SIGNALLING,
+  // The device could not be loaded with the router RTP capabilities.
+  ERR_DEVICE_LOAD,
+  // The device cannot produce the requested media kind. The message is the
+  // media kind.
+  ERR_CANNOT_PRODUCE,
+  // No capture device could be opened with the requested parameters.
+  ERR_CAPTURE_DEVICE_NOT_FOUND,
+  // The transport is closed.
+  ERR_TRANSPORT_CLOSED,
+  // A signalling callback did not complete in time.
+  ERR_TIMED_OUT,
+  // The device does not support the requested codec. The message is the
+  // codec MIME type.
+  ERR_CODEC_NOT_SUPPORTED,
+  // Any other error raised by libmediasoupclient or WebRTC.
+  ERR_UNKNOWN,
+};
+
+// Out parameter of fallible functions, which return nullptr or false on
+// failure. The message must be released with cpp_unmarshal_str.
+struct Error {
+  ErrorKind kind;
+  char *message;
+};
 
 // foreign callback requesting frame in RGB (little-endian) format
 typedef void (*frame_callback_t)(const void *ctx, uint32_t width,
                                  uint32_t height, int64_t timestamp, uint8_t *);
 
+// foreign callback requesting 10ms of interleaved 16-bit PCM samples
+typedef void (*audio_source_callback_t)(const void *ctx, uint32_t sample_rate,
+                                        size_t channels, size_t frames,
+                                        int16_t *data);
+
+// Pixel format of frames passed to a video_sink_callback_t.
+enum VideoFrameFormat {
+  // Y, U and V planes packed one after the other without padding.
+  FRAME_I420,
+  // Same layout as the frames requested by frame_callback_t.
+  FRAME_RGBA,
+};
+
+// foreign callback receiving a decoded video frame
+typedef void (*video_sink_callback_t)(const void *ctx, uint32_t width,
+                                      uint32_t height, int64_t timestamp,
+                                      const uint8_t *data, size_t len);
+// foreign callback receiving interleaved 16-bit PCM samples
+typedef void (*audio_sink_callback_t)(const void *ctx, const int16_t *data,
+                                      uint32_t sample_rate, size_t channels,
+                                      size_t frames);
+
+// How a video producer degrades when bandwidth or CPU is limited, as in
+// webrtc::DegradationPreference.
+enum DegradationPreference {
+  DEGRADATION_BALANCED,
+  DEGRADATION_MAINTAIN_FRAMERATE,
+  DEGRADATION_MAINTAIN_RESOLUTION,
+};
+
+// Kind of content of a video track, as in
+// webrtc::VideoTrackInterface::ContentHint.
+enum ContentHint {
+  CONTENT_HINT_NONE,
+  CONTENT_HINT_MOTION,
+  CONTENT_HINT_DETAIL,
+  CONTENT_HINT_TEXT,
+};
+
+// Audio processing applied to audio captured from a recording device.
+struct AudioProcessingOptions {
+  bool echo_cancellation;
+  bool noise_suppression;
+  bool auto_gain_control;
+  bool highpass_filter;
+};
+
+// Capture mode of a video capture device. video_type is a webrtc::VideoType.
+struct VideoCapability {
+  uint32_t width;
+  uint32_t height;
+  uint32_t max_fps;
+  int video_type;
+  bool interlaced;
+};
+
+// Signalling callbacks return nullptr or false on failure and fill in error,
+// whose message must be released with rust_unmarshal_str.
 struct SignalHandler {
   // Get router RTP capabilities. Returns RtpCapabilitiesFinalized.
-  char *(*server_rtp_capabilities)(const void *ctx);
+  char *(*server_rtp_capabilities)(const void *ctx, Error *error);
   // Get WebRTC transport. Returns WebRtcTransportOptions.
-  char *(*create_webrtc_transport)(const void *ctx);
+  char *(*create_webrtc_transport)(const void *ctx, Error *error);
 
   // Called when RTP capabilities are available from client.
-  void (*on_rtp_capabilities)(const void *ctx, const char *rtp_capabilities);
+  bool (*on_rtp_capabilities)(const void *ctx, const char *rtp_capabilities,
+                              Error *error);
   // Called when client wants to produce. Expects ProducerId.
   char *(*on_produce)(const void *ctx, const char *transport_id,
-                      const char *kind, const char *rtp_parameters);
+                      const char *kind, const char *rtp_parameters,
+                      Error *error);
   // Called when client wants to produce data. Expects ProducerId.
   char *(*on_produce_data)(const void *ctx, const char *transport_id,
-                           const char *sctp_stream_parameters);
+                           const char *sctp_stream_parameters,
+                           const char *label, const char *protocol,
+                           const char *app_data, Error *error);
   // Called when client wants to connect WebRTC transport.
-  void (*on_connect_webrtc_transport)(const void *ctx, const char *transport_id,
-                                      const char *dtls_parameters);
+  bool (*on_connect_webrtc_transport)(const void *ctx, const char *transport_id,
+                                      const char *dtls_parameters,
+                                      Error *error);
 
   // Called when new message is available from a DataConsumer.
   void (*on_data_consumer_message)(const void *ctx,
@@ -44,39 +147,203 @@ struct SignalHandler {
   void (*on_data_producer_state_changed)(const void *ctx,
                                          const char *data_producer_id,
                                          const char *state);
-  // Called when a transport connection state changes.
+  // Called when a transport connection state changes. Direction is either
+  // "send" or "recv".
   void (*on_connection_state_changed)(const void *ctx, const char *transport_id,
-                                      const char *state);
+                                      const char *direction, const char *state);
+  // Called when the transport of a Producer is closed.
+  void (*on_producer_transport_close)(const void *ctx, const char *producer_id);
+  // Called when the transport of a Consumer is closed.
+  void (*on_consumer_transport_close)(const void *ctx, const char *consumer_id);
+  // Called when the buffered amount of a DataProducer changes.
+  void (*on_data_producer_buffered_amount_change)(const void *ctx,
+                                                  const char *data_producer_id,
+                                                  uint64_t buffered_amount);
 };
 
 void init(const char *argv0);
 
-Broadcaster *broadcaster_new(const void *ctx, SignalHandler signal_handler);
+Broadcaster *broadcaster_new(const void *ctx, SignalHandler signal_handler,
+                             Error *error);
 void broadcaster_delete(Broadcaster *broadcaster);
-char *broadcaster_marshal_recv_transport_id(Broadcaster *b);
+char *broadcaster_marshal_recv_transport_id(Broadcaster *b, Error *error);
+// ice_parameters is an IceParameters JSON object.
+bool broadcaster_restart_ice(Broadcaster *b, const char *transport_id,
+                             const char *ice_parameters, Error *error);
+// Producers and data producers must be created again afterwards, then the
+// old transports deleted with broadcaster_delete_closed_transports. Consumers
+// and data consumers are closed and reported through the signal handler.
+bool broadcaster_recreate_transports(Broadcaster *b, Error *error);
+void broadcaster_delete_closed_transports(Broadcaster *b);
+// Returns a JSON object with "send" and "recv" arrays of the RTCStats of each
+// transport, which must be released with cpp_unmarshal_str.
+char *broadcaster_marshal_stats(Broadcaster *b, Error *error);
 
 mediasoupclient::DataConsumer *
 data_consumer_new(Broadcaster *b, const char *data_consumer_id,
                   const char *data_producer_id,
-                  const char *sctp_stream_parameters);
+                  const char *sctp_stream_parameters, const char *label,
+                  const char *protocol, Error *error);
 void data_consumer_delete(mediasoupclient::DataConsumer *consumer);
 
-mediasoupclient::Producer *producer_new_from_default_audio(Broadcaster *b);
-mediasoupclient::Producer *producer_new_from_fake_video(Broadcaster *b);
-mediasoupclient::Producer *
-producer_new_from_vcm_capturer(Broadcaster *b, int device_idx, uint32_t width,
-                               uint32_t height, uint32_t fps, int video_type);
-mediasoupclient::Producer *
-producer_new_from_foreign(Broadcaster *b, uint32_t width, uint32_t height,
-                          uint32_t fps, void *ctx, frame_callback_t callback);
+// Consume a remote producer on the recv transport. rtp_parameters is an
+// RtpParameters JSON object.
+mediasoupclient::Consumer *consumer_new(Broadcaster *b, const char *consumer_id,
+                                        const char *producer_id,
+                                        const char *kind,
+                                        const char *rtp_parameters,
+                                        Error *error);
+void consumer_delete(mediasoupclient::Consumer *consumer);
+// Sinks must be deleted before their consumer. No callbacks are made after a
+// sink is deleted.
+ForeignVideoSink *video_sink_new(mediasoupclient::Consumer *consumer,
+                                 VideoFrameFormat format, const void *ctx,
+                                 video_sink_callback_t callback);
+void video_sink_delete(mediasoupclient::Consumer *consumer,
+                       ForeignVideoSink *sink);
+ForeignAudioSink *audio_sink_new(mediasoupclient::Consumer *consumer,
+                                 const void *ctx,
+                                 audio_sink_callback_t callback);
+void audio_sink_delete(mediasoupclient::Consumer *consumer,
+                       ForeignAudioSink *sink);
+
+// Tracks are reference counted. The returned track holds a reference which
+// must be released with track_delete.
+// Capture from the recording device with the given name or index, or the
+// current one if device_name is nullptr and device_idx is negative. There is a
+// single recording device, so this switches the device of existing tracks.
+webrtc::MediaStreamTrackInterface *
+track_new_from_alsa(const char *device_name, int device_idx,
+                    AudioProcessingOptions options, Error *error);
+webrtc::MediaStreamTrackInterface *track_new_from_fake_video(Error *error);
+// Capture from the device with the given name or unique id, or index if
+// device_name is nullptr. If neither is given, the first device which can be
+// opened is used. If best_match is true, the closest capability supported by
+// the device is used instead of the requested one. The capability actually
+// used and the unique id of the device are written back. The unique id must be
+// released with cpp_unmarshal_str.
+webrtc::MediaStreamTrackInterface *
+track_new_from_vcm_capturer(const char *device_name, int device_idx,
+                            VideoCapability *capability, bool best_match,
+                            char **device_unique_id, Error *error);
+webrtc::MediaStreamTrackInterface *
+track_new_from_foreign(uint32_t width, uint32_t height, uint32_t fps, void *ctx,
+                       frame_callback_t callback, Error *error);
+webrtc::MediaStreamTrackInterface *
+track_new_from_audio_source(ForeignAudioSource *source, Error *error);
+// Has no effect on audio tracks.
+void track_set_content_hint(webrtc::MediaStreamTrackInterface *track,
+                            ContentHint hint);
+void track_delete(webrtc::MediaStreamTrackInterface *track);
+
+// Count the frames of a local video track. The monitor holds a reference to
+// the track until it is deleted.
+FrameMonitor *frame_monitor_new(webrtc::MediaStreamTrackInterface *track);
+uint64_t frame_monitor_frame_count(FrameMonitor *monitor);
+void frame_monitor_delete(FrameMonitor *monitor);
+
+// Audio sources are pulled from their own thread every 10ms until they are
+// deleted. The sample rate must be a multiple of 100.
+ForeignAudioSource *audio_source_new(uint32_t sample_rate, size_t channels,
+                                     const void *ctx,
+                                     audio_source_callback_t callback,
+                                     Error *error);
+void audio_source_delete(ForeignAudioSource *source);
+
+// Produce track on the send transport. encodings is a JSON array of
+// RtpEncodingParameters for simulcast or SVC, or nullptr to send a single
+// encoding. codec_options is a ProducerCodecOptions JSON object. codec is the
+// MIME type of the codec to send with, e.g. "video/VP9", or nullptr to use the
+// first codec supported by the router.
+mediasoupclient::Producer *producer_new(Broadcaster *b,
+                                        webrtc::MediaStreamTrackInterface *track,
+                                        const char *encodings,
+                                        const char *codec_options,
+                                        const char *codec, Error *error);
+// Send track instead of the current track of the producer. The producer
+// takes its own reference to the track.
+bool producer_replace_track(mediasoupclient::Producer *producer,
+                            webrtc::MediaStreamTrackInterface *track,
+                            Error *error);
+// Returns the id of the producer, which must be released with
+// cpp_unmarshal_str.
+char *producer_marshal_id(mediasoupclient::Producer *producer);
+// Returns "audio" or "video", which must be released with cpp_unmarshal_str.
+char *producer_marshal_kind(mediasoupclient::Producer *producer);
+// Returns an RtpParameters JSON object, which must be released with
+// cpp_unmarshal_str.
+char *producer_marshal_rtp_parameters(mediasoupclient::Producer *producer);
+// Returns a JSON array of the RTCStats of the producer, which must be released
+// with cpp_unmarshal_str.
+char *producer_marshal_stats(mediasoupclient::Producer *producer, Error *error);
+// Pausing stops sending the track without closing the producer.
+// Update the encodings of the producer. encoding_parameters is a JSON array
+// with an object per encoding, in the order they were produced with, whose
+// maxBitrate, maxFramerate, scaleResolutionDownBy and active fields replace
+// those of the encoding if present.
+bool producer_set_encoding_parameters(mediasoupclient::Producer *producer,
+                                      const char *encoding_parameters,
+                                      Error *error);
+bool producer_set_degradation_preference(mediasoupclient::Producer *producer,
+                                         DegradationPreference preference,
+                                         Error *error);
+// Only send spatial layers up to the given one.
+bool producer_set_max_spatial_layer(mediasoupclient::Producer *producer,
+                                    uint8_t spatial_layer, Error *error);
+void producer_pause(mediasoupclient::Producer *producer);
+void producer_resume(mediasoupclient::Producer *producer);
+bool producer_is_paused(mediasoupclient::Producer *producer);
 void producer_delete(mediasoupclient::Producer *producer);
 
-mediasoupclient::DataProducer *data_producer_new(Broadcaster *b);
+// A max_packet_life_time (in milliseconds) or max_retransmits of 0 is unset.
+// The data producer is reliable if both are unset.
+mediasoupclient::DataProducer *
+data_producer_new(Broadcaster *b, bool ordered, uint16_t max_packet_life_time,
+                  uint16_t max_retransmits, const char *label,
+                  const char *protocol, const char *app_data, Error *error);
 char *data_producer_marshal_id(mediasoupclient::DataProducer *data_producer);
+// SCTP stream id of the data producer, which identifies its data channel in
+// the transport stats.
+uint16_t data_producer_stream_id(mediasoupclient::DataProducer *data_producer);
 void data_producer_send(mediasoupclient::DataProducer *data_producer,
                         const uint8_t *data, size_t len);
 void data_producer_delete(mediasoupclient::DataProducer *data_producer);
 
+// Client side of a mediasoup PlainTransport with comedia enabled, sending
+// without libmediasoupclient. options is a PlainTransportOptions JSON object
+// with the id, tuple, rtcpTuple and srtpParameters of the server transport.
+// router_rtp_capabilities is the RtpCapabilities JSON object of the router.
+PlainTransport *plain_transport_new(const char *options,
+                                    const char *router_rtp_capabilities,
+                                    Error *error);
+// Returns the id of the transport, which must be released with
+// cpp_unmarshal_str.
+char *plain_transport_marshal_id(PlainTransport *transport);
+// Returns the SrtpParameters JSON object the server transport must be
+// connected with, or nullptr if SRTP is not enabled. Must be released with
+// cpp_unmarshal_str.
+char *plain_transport_marshal_srtp_parameters(PlainTransport *transport);
+// All producers of the transport must be deleted first.
+void plain_transport_delete(PlainTransport *transport);
+// Send track on the transport, encoded with VP8 at max_bitrate and
+// max_framerate for video, or with Opus for audio, where a max_bitrate of 0
+// uses the encoder default. The producer holds a reference to the track until
+// it is deleted. It is created paused, so nothing is sent until
+// plain_producer_resume is called once it has been produced on the server.
+PlainProducer *plain_producer_new(PlainTransport *transport,
+                                  webrtc::MediaStreamTrackInterface *track,
+                                  uint32_t max_bitrate, uint32_t max_framerate,
+                                  Error *error);
+// Returns the RtpParameters JSON object the producer must be produced with on
+// the server, which must be released with cpp_unmarshal_str.
+char *plain_producer_marshal_rtp_parameters(PlainProducer *producer);
+void plain_producer_pause(PlainProducer *producer);
+void plain_producer_resume(PlainProducer *producer);
+void plain_producer_delete(PlainProducer *producer);
+
+// Returns a JSON array of the video capture devices and their capabilities,
+// which must be released with cpp_unmarshal_str.
+char *marshal_video_devices(Error *error);
 void debug_enumerate_capture_devices();
 
 enum GlogLogLevel { INFO, WARNING, ERROR, FATAL };
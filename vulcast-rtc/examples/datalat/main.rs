@@ -109,11 +109,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .await?;
     let vulcast_gql_signaller = Arc::new(GraphQLSignaller::new(vulcast_conn.clone()));
     let client_gql_signaller = Arc::new(GraphQLSignaller::new(client_conn.clone()));
-    let vulcast_broadcaster = Broadcaster::new(vulcast_gql_signaller.clone()).await;
-    let client_broadcaster = Broadcaster::new(client_gql_signaller.clone()).await;
-    let mut client_data_producer = client_broadcaster.produce_data().await;
+    let vulcast_broadcaster = Broadcaster::new(vulcast_gql_signaller.clone()).await?;
+    let client_broadcaster = Broadcaster::new(client_gql_signaller.clone()).await?;
+    let client_data_producer = client_broadcaster.produce_data().await?;
     let mut vulcast_data_consumer = vulcast_broadcaster
-        .consume_data(client_data_producer.id().clone())
+        .consume_data(client_data_producer.id())
         .await
         .unwrap();
 
@@ -144,7 +144,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             for i in (0..count).rev() {
                 let mut state = state.lock().await;
                 let State { send_time, .. } = &mut *state;
-                client_data_producer.send(i.to_le_bytes().to_vec()).unwrap();
+                client_data_producer
+                    .send(i.to_le_bytes().to_vec())
+                    .await
+                    .unwrap();
                 send_time.insert(i, std::time::Instant::now());
                 drop(state);
 
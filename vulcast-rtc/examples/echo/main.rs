@@ -103,7 +103,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let graphql_signaller = Arc::new(GraphQLSignaller::new(client.clone()));
-    let broadcaster = Broadcaster::new(graphql_signaller.clone()).await;
+    let broadcaster = Broadcaster::new(graphql_signaller.clone()).await?;
 
     let data_producer_available = client.subscribe::<signal_schema::DataProducerAvailable>(
         signal_schema::data_producer_available::Variables,
@@ -111,7 +111,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let echo_frame_source = EchoFrameSource::new(broadcaster.downgrade(), data_producer_available);
     let _producer = broadcaster
         .produce_video_from_frame_source(Arc::new(echo_frame_source), 352, 240, 60)
-        .await;
+        .await?;
 
     let _ = graphql_signaller.shutdown().recv().await;
 
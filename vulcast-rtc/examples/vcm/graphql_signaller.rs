@@ -1,10 +1,25 @@
 use async_trait::async_trait;
 use graphql_ws::GraphQLWebSocket;
 use tokio::sync::broadcast;
-use vulcast_rtc::broadcaster::{Signaller, TransportConnectionState};
+use vulcast_rtc::broadcaster::{Signaller, SignallerError, TransportConnectionState};
 
 use crate::signal_schema as schema;
 
+/// Unwrap the data of a GraphQL response, failing on transport and GraphQL
+/// errors.
+fn response_data<T, E: std::fmt::Debug>(
+    response: Result<graphql_client::Response<T>, E>,
+) -> Result<T, SignallerError> {
+    let response = response.map_err(|e| SignallerError::new(format!("{:?}", e)))?;
+    if let Some(errors) = response.errors.filter(|errors| !errors.is_empty()) {
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        return Err(SignallerError::new(messages.join("; ")));
+    }
+    response
+        .data
+        .ok_or_else(|| SignallerError::new("response has no data"))
+}
+
 pub struct GraphQLSignaller {
     client: GraphQLWebSocket,
     shutdown_tx: broadcast::Sender<()>,
@@ -23,31 +38,51 @@ impl GraphQLSignaller {
 }
 #[async_trait]
 impl Signaller for GraphQLSignaller {
-    async fn server_rtp_capabilities(&self) -> vulcast_rtc::types::RtpCapabilitiesFinalized {
-        self.client
-            .query_unchecked::<schema::ServerRtpCapabilities>(
-                schema::server_rtp_capabilities::Variables,
-            )
-            .await
-            .server_rtp_capabilities
+    async fn server_rtp_capabilities(
+        &self,
+    ) -> Result<vulcast_rtc::types::RtpCapabilitiesFinalized, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::ServerRtpCapabilities>(schema::server_rtp_capabilities::Variables)
+                .await,
+        )?
+        .server_rtp_capabilities)
     }
 
-    async fn create_webrtc_transport(&self) -> vulcast_rtc::types::WebRtcTransportOptions {
-        self.client
-            .query_unchecked::<schema::CreateWebrtcTransport>(
-                schema::create_webrtc_transport::Variables,
-            )
-            .await
-            .create_webrtc_transport
+    async fn create_webrtc_transport(
+        &self,
+    ) -> Result<vulcast_rtc::types::WebRtcTransportOptions, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::CreateWebrtcTransport>(schema::create_webrtc_transport::Variables)
+                .await,
+        )?
+        .create_webrtc_transport)
     }
 
-    async fn on_rtp_capabilities(&self, rtp_capabilities: vulcast_rtc::types::RtpCapabilities) {
-        self.client
-            .query_unchecked::<schema::ClientRtpCapabilities>(
-                schema::client_rtp_capabilities::Variables { rtp_capabilities },
-            )
-            .await
-            .rtp_capabilities;
+    async fn create_plain_transport(
+        &self,
+    ) -> Result<vulcast_rtc::types::PlainTransportOptions, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::CreatePlainTransport>(schema::create_plain_transport::Variables)
+                .await,
+        )?
+        .create_plain_transport)
+    }
+
+    async fn on_rtp_capabilities(
+        &self,
+        rtp_capabilities: vulcast_rtc::types::RtpCapabilities,
+    ) -> Result<(), SignallerError> {
+        response_data(
+            self.client
+                .query::<schema::ClientRtpCapabilities>(
+                    schema::client_rtp_capabilities::Variables { rtp_capabilities },
+                )
+                .await,
+        )?;
+        Ok(())
     }
 
     async fn on_produce(
@@ -55,72 +90,126 @@ impl Signaller for GraphQLSignaller {
         transport_id: vulcast_rtc::types::TransportId,
         kind: vulcast_rtc::types::MediaKind,
         rtp_parameters: vulcast_rtc::types::RtpParameters,
-    ) -> vulcast_rtc::types::ProducerId {
-        self.client
-            .query_unchecked::<schema::Produce>(schema::produce::Variables {
-                transport_id,
-                kind,
-                rtp_parameters,
-            })
-            .await
-            .produce
+    ) -> Result<vulcast_rtc::types::ProducerId, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::Produce>(schema::produce::Variables {
+                    transport_id,
+                    kind,
+                    rtp_parameters,
+                })
+                .await,
+        )?
+        .produce)
+    }
+
+    async fn on_produce_plain(
+        &self,
+        transport_id: vulcast_rtc::types::TransportId,
+        kind: vulcast_rtc::types::MediaKind,
+        rtp_parameters: vulcast_rtc::types::RtpParameters,
+    ) -> Result<vulcast_rtc::types::ProducerId, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::ProducePlain>(schema::produce_plain::Variables {
+                    transport_id,
+                    kind,
+                    rtp_parameters,
+                })
+                .await,
+        )?
+        .produce_plain)
     }
 
     async fn on_produce_data(
         &self,
         transport_id: vulcast_rtc::types::TransportId,
         sctp_stream_parameters: vulcast_rtc::types::SctpStreamParameters,
-    ) -> vulcast_rtc::types::DataProducerId {
-        self.client
-            .query_unchecked::<schema::ProduceData>(schema::produce_data::Variables {
-                transport_id,
-                sctp_stream_parameters,
-            })
-            .await
-            .produce_data
+    ) -> Result<vulcast_rtc::types::DataProducerId, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::ProduceData>(schema::produce_data::Variables {
+                    transport_id,
+                    sctp_stream_parameters,
+                })
+                .await,
+        )?
+        .produce_data)
     }
 
     async fn on_connect_webrtc_transport(
         &self,
         transport_id: vulcast_rtc::types::TransportId,
         dtls_parameters: vulcast_rtc::types::DtlsParameters,
-    ) {
-        self.client
-            .query_unchecked::<schema::ConnectWebrtcTransport>(
-                schema::connect_webrtc_transport::Variables {
+    ) -> Result<(), SignallerError> {
+        response_data(
+            self.client
+                .query::<schema::ConnectWebrtcTransport>(
+                    schema::connect_webrtc_transport::Variables {
+                        transport_id,
+                        dtls_parameters,
+                    },
+                )
+                .await,
+        )?;
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        transport_id: vulcast_rtc::types::TransportId,
+        producer_id: vulcast_rtc::types::ProducerId,
+    ) -> Result<vulcast_rtc::types::ConsumerOptions, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::Consume>(schema::consume::Variables {
                     transport_id,
-                    dtls_parameters,
-                },
-            )
-            .await
-            .connect_webrtc_transport;
+                    producer_id,
+                })
+                .await,
+        )?
+        .consume)
+    }
+
+    async fn consumer_resume(
+        &self,
+        consumer_id: vulcast_rtc::types::ConsumerId,
+    ) -> Result<(), SignallerError> {
+        response_data(
+            self.client
+                .query::<schema::ConsumerResume>(schema::consumer_resume::Variables { consumer_id })
+                .await,
+        )?;
+        Ok(())
     }
 
     async fn consume_data(
         &self,
         transport_id: vulcast_rtc::types::TransportId,
         data_producer_id: vulcast_rtc::types::DataProducerId,
-    ) -> Result<vulcast_rtc::types::DataConsumerOptions, Box<dyn std::error::Error>> {
-        Ok(self
-            .client
-            .query_unchecked::<schema::ConsumeData>(schema::consume_data::Variables {
-                transport_id,
-                data_producer_id,
-            })
-            .await
-            .consume_data)
+    ) -> Result<vulcast_rtc::types::DataConsumerOptions, SignallerError> {
+        Ok(response_data(
+            self.client
+                .query::<schema::ConsumeData>(schema::consume_data::Variables {
+                    transport_id,
+                    data_producer_id,
+                })
+                .await,
+        )?
+        .consume_data)
     }
 
     async fn on_connection_state_changed(
         &self,
         _transport_id: vulcast_rtc::types::TransportId,
         state: vulcast_rtc::broadcaster::TransportConnectionState,
-    ) {
+    ) -> Result<(), SignallerError> {
         match state {
             TransportConnectionState::Closed | TransportConnectionState::Failed => {
                 let _ = self.shutdown_tx.send(());
             }
             _ => (),
         }
+        Ok(())
     }
 }
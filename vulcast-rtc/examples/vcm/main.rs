@@ -91,13 +91,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let graphql_signaller = Arc::new(GraphQLSignaller::new(client.clone()));
-    let broadcaster = Broadcaster::new(graphql_signaller.clone()).await;
+    let broadcaster = Broadcaster::new(graphql_signaller.clone()).await?;
 
     let _vcm_capturer = broadcaster
         .produce_video_from_vcm_capturer(None, 640, 480, 60)
-        .await;
+        .await?;
 
-    let _audio_producer = broadcaster.produce_audio_from_default_alsa().await;
+    let _audio_producer = broadcaster.produce_audio_from_default_alsa().await?;
 
     let _ = graphql_signaller.shutdown().recv().await;
 
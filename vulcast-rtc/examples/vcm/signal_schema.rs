@@ -23,6 +23,13 @@ pub struct DataProducerAvailable;
 )]
 pub struct CreateWebrtcTransport;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "examples/schema/signal_schema.gql",
+    query_path = "examples/schema/signal_query.gql"
+)]
+pub struct CreatePlainTransport;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "examples/schema/signal_schema.gql",
@@ -37,6 +44,13 @@ pub struct ClientRtpCapabilities;
 )]
 pub struct Produce;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "examples/schema/signal_schema.gql",
+    query_path = "examples/schema/signal_query.gql"
+)]
+pub struct ProducePlain;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "examples/schema/signal_schema.gql",
@@ -51,6 +65,20 @@ pub struct ProduceData;
 )]
 pub struct ConnectWebrtcTransport;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "examples/schema/signal_schema.gql",
+    query_path = "examples/schema/signal_query.gql"
+)]
+pub struct Consume;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "examples/schema/signal_schema.gql",
+    query_path = "examples/schema/signal_query.gql"
+)]
+pub struct ConsumerResume;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "examples/schema/signal_schema.gql",
@@ -1,45 +1,179 @@
-use std::{
-    pin::Pin,
-    ptr,
-    sync::{Arc, Mutex},
-};
+use std::{ffi::CString, ops::Deref, pin::Pin, ptr, sync::Arc};
 
+use serde_json::json;
 use vulcast_rtc_sys as sys;
 
+use crate::broadcaster::{try_sys, BroadcasterError, ProducerSignaller};
+use crate::producer::{
+    AudioProducerGuard, AudioProducerKind, Producer, ProducerHandle, ProducerOwner, Reproduce,
+    WeakReproduce,
+};
+
+/// Recording device to capture from, as enumerated by the audio device
+/// module. These are PulseAudio sources if PulseAudio is running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlsaDevice {
+    Index(u16),
+    /// Name or GUID of the device.
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct AlsaOptions {
+    /// Device to capture from. The audio device module records from a single
+    /// device, so this also switches the device of other `AlsaCapturer`s.
+    /// Keeps the current device if `None`.
+    pub device: Option<AlsaDevice>,
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub auto_gain_control: bool,
+    pub highpass_filter: bool,
+    /// Discontinuous transmission, which stops sending packets during silence.
+    pub dtx: bool,
+    /// Maximum average Opus bitrate in bits per second.
+    pub opus_max_average_bitrate: Option<u32>,
+    pub opus_stereo: bool,
+    /// Opus in-band forward error correction.
+    pub opus_fec: bool,
+    /// Opus packet duration in milliseconds.
+    pub opus_ptime: Option<u32>,
+}
+impl Default for AlsaOptions {
+    fn default() -> Self {
+        AlsaOptions {
+            device: None,
+            echo_cancellation: true,
+            noise_suppression: true,
+            auto_gain_control: true,
+            highpass_filter: false,
+            dtx: true,
+            opus_max_average_bitrate: None,
+            opus_stereo: true,
+            opus_fec: true,
+            opus_ptime: None,
+        }
+    }
+}
+impl AlsaOptions {
+    fn codec_options(&self) -> serde_json::Value {
+        let mut codec_options = json!({
+            "opusStereo": self.opus_stereo,
+            "opusDtx": self.dtx,
+            "opusFec": self.opus_fec,
+        });
+        if let Some(bitrate) = self.opus_max_average_bitrate {
+            codec_options["opusMaxAverageBitrate"] = json!(bitrate);
+        }
+        if let Some(ptime) = self.opus_ptime {
+            codec_options["opusPtime"] = json!(ptime);
+        }
+        codec_options
+    }
+}
+
+/// Audio captured from a recording device with
+/// `Broadcaster::produce_audio_from_alsa`. While it is alive, no
+/// `ForeignAudioProducer` can be produced by any broadcaster in the process.
 #[derive(Clone)]
 pub struct AlsaCapturer {
-    _shared: Pin<Arc<Shared>>,
+    shared: Pin<Arc<Shared>>,
+    handle: ProducerHandle,
 }
 struct Shared {
-    state: Mutex<State>,
-}
-unsafe impl Send for Shared {}
-unsafe impl Sync for Shared {}
-struct State {
-    sys_producer: *mut sys::mediasoupclient_Producer,
+    producer: Producer,
+    // released after the producer is closed
+    _audio_guard: AudioProducerGuard,
 }
 
 impl AlsaCapturer {
-    pub fn new(sys_broadcaster: *mut sys::Broadcaster) -> Self {
+    pub(crate) fn new(
+        sys_broadcaster: *mut sys::Broadcaster,
+        signaller: ProducerSignaller,
+        options: AlsaOptions,
+    ) -> Result<Self, BroadcasterError> {
         let shared = Arc::pin(Shared {
-            state: Mutex::new(State {
-                sys_producer: ptr::null_mut(),
-            }),
+            producer: Producer::new(signaller),
+            _audio_guard: AudioProducerGuard::acquire(AudioProducerKind::Alsa)?,
         });
-        unsafe {
-            let sys_producer = sys::producer_new_from_default_audio(sys_broadcaster);
-            let mut state = shared.state.lock().unwrap();
-            state.sys_producer = sys_producer;
-        }
-        AlsaCapturer { _shared: shared }
+        let sys_track = unsafe { track_new_from_alsa(&options)? };
+        shared
+            .producer
+            .produce(sys_broadcaster, sys_track, options.codec_options(), None)?;
+        let handle = ProducerHandle::new(shared.clone());
+        Ok(AlsaCapturer { shared, handle })
+    }
+
+    /// Capture from the device and with the processing options of the given
+    /// options, keeping the same producer. The codec options were negotiated
+    /// when the capturer was produced, and are left unchanged.
+    pub async fn replace_source(&self, options: AlsaOptions) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || {
+                let sys_track = unsafe { track_new_from_alsa(&options)? };
+                shared.producer.replace_track(sys_track, None)
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    pub(crate) fn downgrade_reproduce(&self) -> WeakReproduce {
+        Arc::<Shared>::downgrade(&Pin::into_inner(self.shared.clone()))
     }
 }
 
-impl Drop for State {
-    fn drop(&mut self) {
-        log::trace!("producer delete {:?}", &self.sys_producer);
-        unsafe {
-            sys::producer_delete(self.sys_producer);
-        }
+impl Deref for AlsaCapturer {
+    type Target = ProducerHandle;
+    fn deref(&self) -> &ProducerHandle {
+        &self.handle
+    }
+}
+
+impl Reproduce for Shared {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        self.producer.reproduce(sys_broadcaster)
+    }
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        Some(&self.producer)
+    }
+}
+
+impl ProducerOwner for Shared {
+    fn producer(&self) -> &Producer {
+        &self.producer
     }
 }
+
+unsafe fn track_new_from_alsa(
+    options: &AlsaOptions,
+) -> Result<*mut sys::webrtc_MediaStreamTrackInterface, BroadcasterError> {
+    let (device_name, device_idx) = match &options.device {
+        Some(AlsaDevice::Index(idx)) => (None, *idx as i32),
+        Some(AlsaDevice::Name(name)) => {
+            // no device has a name containing NUL
+            let name = CString::new(name.as_str())
+                .map_err(|_| BroadcasterError::CaptureDeviceNotFound(name.clone()))?;
+            (Some(name), -1)
+        }
+        None => (None, -1),
+    };
+    let processing_options = sys::AudioProcessingOptions {
+        echo_cancellation: options.echo_cancellation,
+        noise_suppression: options.noise_suppression,
+        auto_gain_control: options.auto_gain_control,
+        highpass_filter: options.highpass_filter,
+    };
+    try_sys(|error| {
+        sys::track_new_from_alsa(
+            device_name
+                .as_ref()
+                .map_or(ptr::null(), |name| name.as_ptr()),
+            device_idx,
+            processing_options,
+            error,
+        )
+    })
+}
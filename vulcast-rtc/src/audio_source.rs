@@ -0,0 +1,25 @@
+/// Format of the samples provided by an `AudioSource`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SampleFormat {
+    I16,
+    /// 32-bit float samples in the range [-1, 1].
+    F32,
+}
+
+/// Buffer of interleaved PCM samples to be filled by an `AudioSource`.
+pub enum AudioBuffer<'a> {
+    I16(&'a mut [i16]),
+    F32(&'a mut [f32]),
+}
+
+pub trait AudioSource: Send + Sync {
+    /// Format of the buffers passed to `next_samples`. Only queried once,
+    /// when the source is produced.
+    fn sample_format(&self) -> SampleFormat {
+        SampleFormat::I16
+    }
+
+    /// Get the next 10ms of audio from this source. The provided buffer holds
+    /// `sample_rate / 100` frames of `channels` interleaved samples.
+    fn next_samples(&self, sample_rate: u32, channels: usize, buffer: AudioBuffer<'_>);
+}
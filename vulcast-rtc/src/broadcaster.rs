@@ -1,23 +1,151 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::os::raw::c_ulong;
 use std::ptr;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 use std::{
     ffi::{c_void, CStr, CString},
     os::raw::c_char,
 };
 
 use async_trait::async_trait;
-use tokio::sync::{broadcast, mpsc};
+use futures::{Future, Stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_stream::wrappers::BroadcastStream;
 
-use crate::alsa_capturer::AlsaCapturer;
-use crate::data_channel::{self, DataConsumer, DataProducer};
+use crate::alsa_capturer::{AlsaCapturer, AlsaOptions};
+use crate::audio_source::AudioSource;
+use crate::consumer::{AudioConsumer, MediaConsumer, VideoConsumer, VideoFrameFormat};
+use crate::data_channel::{
+    ConsumeDataOptions, DataChannelRouter, DataChannelState, DataConsumer, DataProducer,
+    DataProducerOptions,
+};
+use crate::foreign_audio_producer::ForeignAudioProducer;
 use crate::foreign_producer::ForeignProducer;
 use crate::frame_source::FrameSource;
+use crate::producer::WeakReproduce;
+use crate::stats::{BroadcasterStats, StatsReport};
 use crate::types::*;
-use crate::vcm_capturer::{VcmCapturer, VideoType};
+use crate::vcm_capturer::{VcmCapturer, VcmDevice, VcmOptions, VideoType};
+use crate::video_options::VideoProducerOptions;
 use vulcast_rtc_sys as sys;
 
+#[derive(Debug, Error)]
+pub enum BroadcasterError {
+    #[error("signalling failed: {0}")]
+    SignallingFailed(String),
+    #[error("failed to load device: {0}")]
+    DeviceLoadFailed(String),
+    #[error("cannot produce {0:?}")]
+    CannotProduce(MediaKind),
+    #[error("codec not supported: {0}")]
+    CodecNotSupported(String),
+    #[error("capture device not found: {0}")]
+    CaptureDeviceNotFound(String),
+    #[error("transport closed")]
+    TransportClosed,
+    #[error("producer closed")]
+    ProducerClosed,
+    #[error("cannot produce audio from ALSA and from an audio source at the same time")]
+    ConflictingAudioSources,
+    #[error("data channel has no SCTP stream id")]
+    NoStreamId,
+    #[error("invalid options: {0}")]
+    InvalidOptions(String),
+    #[error("timed out: {0}")]
+    TimedOut(String),
+    #[error("native error: {0}")]
+    Native(String),
+}
+impl From<SignallerError> for BroadcasterError {
+    fn from(e: SignallerError) -> Self {
+        BroadcasterError::SignallingFailed(e.to_string())
+    }
+}
+impl From<serde_json::Error> for BroadcasterError {
+    fn from(e: serde_json::Error) -> Self {
+        BroadcasterError::Native(format!("invalid JSON: {}", e))
+    }
+}
+impl BroadcasterError {
+    /// Take ownership of an error reported by a native function.
+    unsafe fn from_sys(error: sys::Error) -> Self {
+        let message = if error.message.is_null() {
+            String::new()
+        } else {
+            let message = CStr::from_ptr(error.message).to_string_lossy().into_owned();
+            sys::cpp_unmarshal_str(error.message);
+            message
+        };
+        match error.kind {
+            sys::ErrorKind_ERR_SIGNALLING => BroadcasterError::SignallingFailed(message),
+            sys::ErrorKind_ERR_DEVICE_LOAD => BroadcasterError::DeviceLoadFailed(message),
+            sys::ErrorKind_ERR_CANNOT_PRODUCE => match MediaKind::from_str(&message) {
+                Ok(kind) => BroadcasterError::CannotProduce(kind),
+                Err(()) => BroadcasterError::Native(message),
+            },
+            sys::ErrorKind_ERR_CAPTURE_DEVICE_NOT_FOUND => {
+                BroadcasterError::CaptureDeviceNotFound(message)
+            }
+            sys::ErrorKind_ERR_TRANSPORT_CLOSED => BroadcasterError::TransportClosed,
+            sys::ErrorKind_ERR_TIMED_OUT => BroadcasterError::TimedOut(message),
+            sys::ErrorKind_ERR_CODEC_NOT_SUPPORTED => BroadcasterError::CodecNotSupported(message),
+            _ => BroadcasterError::Native(message),
+        }
+    }
+}
+
+/// Return value of a fallible native function.
+pub(crate) trait SysResult {
+    fn is_failure(&self) -> bool;
+}
+impl<T> SysResult for *mut T {
+    fn is_failure(&self) -> bool {
+        self.is_null()
+    }
+}
+impl SysResult for bool {
+    fn is_failure(&self) -> bool {
+        !*self
+    }
+}
+
+/// Call a native function which returns null or false on failure and reports
+/// the reason through its `Error` out parameter.
+pub(crate) unsafe fn try_sys<T: SysResult>(
+    f: impl FnOnce(*mut sys::Error) -> T,
+) -> Result<T, BroadcasterError> {
+    let mut error = sys::Error {
+        kind: sys::ErrorKind_ERR_NONE,
+        message: ptr::null_mut(),
+    };
+    let result = f(&mut error);
+    if result.is_failure() {
+        Err(BroadcasterError::from_sys(error))
+    } else {
+        Ok(result)
+    }
+}
+
+/// Take ownership of a string marshalled by a native function. Invalid UTF-8
+/// is replaced rather than failing, as for native error messages.
+pub(crate) unsafe fn unmarshal_str(marshal: *mut c_char) -> String {
+    let string = CStr::from_ptr(marshal).to_string_lossy().into_owned();
+    sys::cpp_unmarshal_str(marshal);
+    string
+}
+
+/// Marshal a string passed to a native function, which cannot contain NUL.
+pub(crate) fn marshal_str(s: impl Into<Vec<u8>>) -> Result<CString, BroadcasterError> {
+    CString::new(s)
+        .map_err(|e| BroadcasterError::Native(format!("invalid string for native code: {}", e)))
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TransportConnectionState {
     Closed,
@@ -47,12 +175,152 @@ impl FromStr for TransportConnectionState {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransportDirection {
+    Send,
+    Recv,
+}
+impl FromStr for TransportDirection {
+    type Err = ();
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "send" => Ok(TransportDirection::Send),
+            "recv" => Ok(TransportDirection::Recv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Lifecycle events of a broadcaster's transports, producers and data
+/// channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcasterEvent {
+    TransportConnectionStateChanged {
+        direction: TransportDirection,
+        transport_id: TransportId,
+        state: TransportConnectionState,
+    },
+    ProducerTransportClosed {
+        producer_id: ProducerId,
+    },
+    ConsumerTransportClosed {
+        consumer_id: ConsumerId,
+    },
+    DataProducerStateChanged {
+        data_producer_id: DataProducerId,
+        state: DataChannelState,
+    },
+    DataProducerBufferedAmountChanged {
+        data_producer_id: DataProducerId,
+        buffered_amount: u64,
+    },
+    DataConsumerStateChanged {
+        data_consumer_id: DataConsumerId,
+        state: DataChannelState,
+    },
+    /// ICE was restarted on a disconnected or failed transport.
+    IceRestarted {
+        transport_id: TransportId,
+    },
+    /// Both transports were recreated and the live producers and data
+    /// producers were produced again on the new send transport.
+    TransportsRecreated,
+    /// The recovery policy gave up on a disconnected or failed transport.
+    RecoveryFailed {
+        transport_id: TransportId,
+    },
+    /// The capture device of a `VcmCapturer` stalled or was unplugged, and its
+    /// fallback source is being sent instead.
+    CaptureDeviceLost {
+        unique_id: String,
+    },
+    /// The capture device of a `VcmCapturer` was reopened after being lost.
+    CaptureDeviceRestored {
+        unique_id: String,
+    },
+}
+
+#[derive(Debug)]
 enum InternalMessage {
     TransportConnectionStateChanged {
         transport_id: TransportId,
         state: TransportConnectionState,
     },
+    ProducerPaused {
+        producer_id: ProducerId,
+    },
+    ProducerResumed {
+        producer_id: ProducerId,
+    },
+    ProducerClosed {
+        producer_id: ProducerId,
+        done: Option<oneshot::Sender<Result<(), BroadcasterError>>>,
+    },
+}
+
+/// Notifies the signaller of producers being paused, resumed and closed, in
+/// the order it happens.
+#[derive(Clone)]
+pub(crate) struct ProducerSignaller {
+    channel_tx: mpsc::UnboundedSender<InternalMessage>,
+}
+
+/// Shortest interval at which stats are polled by `Broadcaster::stats_stream`
+/// and `metrics::monitor`, since getting them blocks on native threads.
+pub const MIN_STATS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct BroadcasterOptions {
+    /// Maximum time to wait for any single signaller call, or None to wait
+    /// indefinitely.
+    pub signalling_timeout: Option<Duration>,
+    /// Maximum time for `Broadcaster::new_with_options` to load the device and
+    /// create its transports, or None to wait indefinitely.
+    pub connect_timeout: Option<Duration>,
+    /// How to recover a transport that becomes disconnected or fails, or None
+    /// to leave it to the application.
+    pub recovery: Option<RecoveryPolicy>,
+}
+impl Default for BroadcasterOptions {
+    fn default() -> Self {
+        Self {
+            signalling_timeout: Some(Duration::from_secs(10)),
+            connect_timeout: Some(Duration::from_secs(30)),
+            recovery: None,
+        }
+    }
+}
+
+/// Recovery of disconnected or failed transports. Each attempt waits for the
+/// current backoff first, and stops early if the transport reconnects by
+/// itself. Consumers and data consumers are closed when transports are
+/// recreated, which is reported by `ConsumerTransportClosed` and
+/// `DataConsumerStateChanged` events, and must be consumed again.
+#[derive(Debug, Clone)]
+pub struct RecoveryPolicy {
+    /// Restart ICE through `Signaller::restart_ice` before anything else.
+    pub ice_restart: bool,
+    /// Recreate both transports and produce all live producers and data
+    /// producers again if restarting ICE is disabled, fails or does not
+    /// reconnect the transport.
+    pub recreate_transports: bool,
+    /// Maximum number of attempts before giving up.
+    pub max_attempts: u32,
+    /// Delay before the first attempt. Doubled after every attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between attempts.
+    pub max_backoff: Duration,
+}
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            ice_restart: true,
+            recreate_transports: true,
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -62,9 +330,17 @@ pub struct Broadcaster {
 struct Shared {
     state: Mutex<State>,
     signaller: Arc<dyn Signaller>,
+    options: BroadcasterOptions,
+    connect_deadline: Mutex<Option<Instant>>,
+    producers: Mutex<Vec<WeakReproduce>>,
+    transport_states: Mutex<HashMap<TransportId, TransportConnectionState>>,
+    recovering: AtomicBool,
+    #[cfg(feature = "metrics")]
+    metrics_label: String,
 
-    data_channel_tx: broadcast::Sender<data_channel::Message>,
+    data_channel_router: Arc<DataChannelRouter>,
     channel_tx: mpsc::UnboundedSender<InternalMessage>,
+    event_tx: broadcast::Sender<BroadcasterEvent>,
 }
 unsafe impl Send for Shared {}
 unsafe impl Sync for Shared {}
@@ -77,44 +353,151 @@ pub struct WeakBroadcaster {
     shared: Weak<Shared>,
 }
 
+/// Error returned by a `Signaller` implementation.
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct SignallerError(#[from] Box<dyn std::error::Error + Send + Sync>);
+impl SignallerError {
+    pub fn new(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        SignallerError(e.into())
+    }
+}
+
 #[async_trait]
 pub trait Signaller: Send + Sync {
-    async fn server_rtp_capabilities(&self) -> RtpCapabilitiesFinalized;
-    async fn create_webrtc_transport(&self) -> WebRtcTransportOptions;
-    async fn on_rtp_capabilities(&self, rtp_caps: RtpCapabilities);
+    async fn server_rtp_capabilities(&self) -> Result<RtpCapabilitiesFinalized, SignallerError>;
+    async fn create_webrtc_transport(&self) -> Result<WebRtcTransportOptions, SignallerError>;
+    async fn on_rtp_capabilities(&self, rtp_caps: RtpCapabilities) -> Result<(), SignallerError>;
     async fn on_produce(
         &self,
         transport_id: TransportId,
         kind: MediaKind,
         rtp_parameters: RtpParameters,
-    ) -> ProducerId;
+    ) -> Result<ProducerId, SignallerError>;
     async fn on_produce_data(
         &self,
         transport_id: TransportId,
         sctp_stream_parameters: SctpStreamParameters,
-    ) -> DataProducerId;
+    ) -> Result<DataProducerId, SignallerError>;
+    /// Called when client wants to produce data, with the label, protocol and
+    /// app data of the data producer. Implement this instead of
+    /// `on_produce_data` to pass them on to the server.
+    async fn on_produce_data_with(
+        &self,
+        transport_id: TransportId,
+        sctp_stream_parameters: SctpStreamParameters,
+        label: String,
+        protocol: String,
+        app_data: serde_json::Value,
+    ) -> Result<DataProducerId, SignallerError> {
+        let _ = (label, protocol, app_data);
+        self.on_produce_data(transport_id, sctp_stream_parameters)
+            .await
+    }
     async fn on_connect_webrtc_transport(
         &self,
         transport_id: TransportId,
         dtls_parameters: DtlsParameters,
-    );
+    ) -> Result<(), SignallerError>;
+    /// Create a paused consumer of the given producer on the server. Only
+    /// called by `Broadcaster::consume`.
+    async fn consume(
+        &self,
+        transport_id: TransportId,
+        producer_id: ProducerId,
+    ) -> Result<ConsumerOptions, SignallerError> {
+        let _ = (transport_id, producer_id);
+        Err(SignallerError::new("consume is not supported"))
+    }
+    /// Resume a consumer created by `consume` once it is ready on the client.
+    async fn consumer_resume(&self, consumer_id: ConsumerId) -> Result<(), SignallerError> {
+        let _ = consumer_id;
+        Err(SignallerError::new("consumer_resume is not supported"))
+    }
     async fn consume_data(
         &self,
         transport_id: TransportId,
         data_producer_id: DataProducerId,
-    ) -> Result<DataConsumerOptions, Box<dyn std::error::Error>>;
+    ) -> Result<DataConsumerOptions, SignallerError>;
     async fn on_connection_state_changed(
         &self,
         transport_id: TransportId,
         state: TransportConnectionState,
-    );
+    ) -> Result<(), SignallerError>;
+    /// Called when a producer is paused. The server should pause it too, so
+    /// that it stops being forwarded to consumers.
+    async fn on_producer_pause(&self, producer_id: ProducerId) -> Result<(), SignallerError> {
+        let _ = producer_id;
+        Ok(())
+    }
+    /// Called when a paused producer is resumed.
+    async fn on_producer_resume(&self, producer_id: ProducerId) -> Result<(), SignallerError> {
+        let _ = producer_id;
+        Ok(())
+    }
+    /// Called when a producer is closed or dropped.
+    async fn on_producer_close(&self, producer_id: ProducerId) -> Result<(), SignallerError> {
+        let _ = producer_id;
+        Ok(())
+    }
+    /// Restart ICE on the server side of the given transport, returning its
+    /// new ICE parameters. Only called when a `RecoveryPolicy` enables ICE
+    /// restarts.
+    async fn restart_ice(
+        &self,
+        transport_id: TransportId,
+    ) -> Result<IceParameters, SignallerError> {
+        let _ = transport_id;
+        Err(SignallerError::new("restart_ice is not supported"))
+    }
+    /// Create a plain transport with comedia enabled on the server, returning
+    /// its id, tuple, rtcpTuple and srtpParameters. Only called by
+    /// `PlainBroadcaster`.
+    async fn create_plain_transport(&self) -> Result<PlainTransportOptions, SignallerError> {
+        Err(SignallerError::new(
+            "create_plain_transport is not supported",
+        ))
+    }
+    /// Connect a plain transport with SRTP enabled, passing the parameters the
+    /// server should decrypt with. Only called if the options returned by
+    /// `create_plain_transport` have srtpParameters.
+    async fn connect_plain_transport(
+        &self,
+        transport_id: TransportId,
+        srtp_parameters: SrtpParameters,
+    ) -> Result<(), SignallerError> {
+        let _ = (transport_id, srtp_parameters);
+        Err(SignallerError::new(
+            "connect_plain_transport is not supported",
+        ))
+    }
+    /// Produce on a plain transport created by `create_plain_transport`.
+    async fn on_produce_plain(
+        &self,
+        transport_id: TransportId,
+        kind: MediaKind,
+        rtp_parameters: RtpParameters,
+    ) -> Result<ProducerId, SignallerError> {
+        let _ = (transport_id, kind, rtp_parameters);
+        Err(SignallerError::new("on_produce_plain is not supported"))
+    }
 }
 
 impl Broadcaster {
     /// Create a new broadcaster with the given signalling handlers.
-    pub async fn new(signaller: Arc<dyn Signaller>) -> Self {
+    pub async fn new(signaller: Arc<dyn Signaller>) -> Result<Self, BroadcasterError> {
+        Self::new_with_options(signaller, BroadcasterOptions::default()).await
+    }
+
+    /// Create a new broadcaster with the given signalling handlers and
+    /// options.
+    pub async fn new_with_options(
+        signaller: Arc<dyn Signaller>,
+        options: BroadcasterOptions,
+    ) -> Result<Self, BroadcasterError> {
         super::native_init();
 
+        let signalling_timeout = options.signalling_timeout;
         let (channel_tx, mut channel_rx) = mpsc::unbounded_channel();
         let shared = tokio::task::spawn_blocking({
             let signaller = signaller.clone();
@@ -124,93 +507,315 @@ impl Broadcaster {
                         sys_broadcaster: ptr::null_mut(),
                     }),
                     signaller,
-                    data_channel_tx: broadcast::channel(64).0,
+                    connect_deadline: Mutex::new(
+                        options
+                            .connect_timeout
+                            .map(|timeout| Instant::now() + timeout),
+                    ),
+                    options,
+                    producers: Mutex::new(Vec::new()),
+                    transport_states: Mutex::new(HashMap::new()),
+                    recovering: AtomicBool::new(false),
+                    #[cfg(feature = "metrics")]
+                    metrics_label: crate::metrics::next_broadcaster_label(),
+                    data_channel_router: Arc::new(DataChannelRouter::default()),
                     channel_tx,
+                    event_tx: broadcast::channel(64).0,
                 });
                 let sys_broadcaster = unsafe {
-                    sys::broadcaster_new(
-                        &*shared as *const _ as *const c_void,
-                        sys::SignalHandler {
-                            server_rtp_capabilities: Some(server_rtp_capabilities),
-                            on_rtp_capabilities: Some(on_rtp_capabilities),
-                            on_produce: Some(on_produce),
-                            on_produce_data: Some(on_produce_data),
-                            on_connect_webrtc_transport: Some(on_connect_webrtc_transport),
-                            create_webrtc_transport: Some(create_webrtc_transport),
-                            on_data_consumer_message: Some(on_data_consumer_message),
-                            on_data_consumer_state_changed: Some(on_data_consumer_state_changed),
-                            on_data_producer_state_changed: Some(on_data_producer_state_changed),
-                            on_connection_state_changed: Some(on_connection_state_changed),
-                        },
-                    )
+                    try_sys(|error| {
+                        sys::broadcaster_new(
+                            &*shared as *const _ as *const c_void,
+                            sys::SignalHandler {
+                                server_rtp_capabilities: Some(server_rtp_capabilities),
+                                on_rtp_capabilities: Some(on_rtp_capabilities),
+                                on_produce: Some(on_produce),
+                                on_produce_data: Some(on_produce_data),
+                                on_connect_webrtc_transport: Some(on_connect_webrtc_transport),
+                                create_webrtc_transport: Some(create_webrtc_transport),
+                                on_data_consumer_message: Some(on_data_consumer_message),
+                                on_data_consumer_state_changed: Some(
+                                    on_data_consumer_state_changed,
+                                ),
+                                on_data_producer_state_changed: Some(
+                                    on_data_producer_state_changed,
+                                ),
+                                on_connection_state_changed: Some(on_connection_state_changed),
+                                on_producer_transport_close: Some(on_producer_transport_close),
+                                on_consumer_transport_close: Some(on_consumer_transport_close),
+                                on_data_producer_buffered_amount_change: Some(
+                                    on_data_producer_buffered_amount_change,
+                                ),
+                            },
+                            error,
+                        )
+                    })?
                 };
                 log::trace!("broadcaster new {:?}", sys_broadcaster);
+                *shared.connect_deadline.lock().unwrap() = None;
                 let mut state = shared.state.lock().unwrap();
                 state.sys_broadcaster = sys_broadcaster;
                 drop(state);
-                shared
+                Ok::<_, BroadcasterError>(shared)
             }
         })
         .await
-        .unwrap();
-        tokio::spawn(async move {
-            while let Some(message) = channel_rx.recv().await {
-                match message {
-                    InternalMessage::TransportConnectionStateChanged {
-                        transport_id,
-                        state,
-                    } => {
-                        signaller
-                            .on_connection_state_changed(transport_id, state)
+        .unwrap()?;
+        tokio::spawn({
+            let broadcaster = WeakBroadcaster {
+                shared: Arc::downgrade(&shared),
+            };
+            async move {
+                while let Some(message) = channel_rx.recv().await {
+                    match message {
+                        InternalMessage::TransportConnectionStateChanged {
+                            transport_id,
+                            state,
+                        } => {
+                            let _ = signal(
+                                "on_connection_state_changed",
+                                signalling_timeout,
+                                signaller.on_connection_state_changed(transport_id.clone(), state),
+                            )
+                            .await;
+                            if matches!(
+                                state,
+                                TransportConnectionState::Disconnected
+                                    | TransportConnectionState::Failed
+                            ) {
+                                if let Some(broadcaster) = broadcaster.upgrade() {
+                                    broadcaster.start_recovery(transport_id);
+                                }
+                            }
+                        }
+                        InternalMessage::ProducerPaused { producer_id } => {
+                            let _ = signal(
+                                "on_producer_pause",
+                                signalling_timeout,
+                                signaller.on_producer_pause(producer_id),
+                            )
+                            .await;
+                        }
+                        InternalMessage::ProducerResumed { producer_id } => {
+                            let _ = signal(
+                                "on_producer_resume",
+                                signalling_timeout,
+                                signaller.on_producer_resume(producer_id),
+                            )
+                            .await;
+                        }
+                        InternalMessage::ProducerClosed { producer_id, done } => {
+                            let result = signal(
+                                "on_producer_close",
+                                signalling_timeout,
+                                signaller.on_producer_close(producer_id),
+                            )
                             .await;
+                            if let Some(done) = done {
+                                let _ = done.send(result);
+                            }
+                        }
                     }
                 }
             }
         });
-        Self { shared }
+        Ok(Self { shared })
+    }
+
+    /// Subscribe to lifecycle events of this broadcaster. Events emitted before
+    /// subscribing are not replayed, and events are dropped if the stream is
+    /// not polled quickly enough.
+    pub fn events(&self) -> impl Stream<Item = BroadcasterEvent> {
+        BroadcastStream::new(self.shared.event_tx.subscribe()).filter_map(|event| async move {
+            match event {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    log::warn!("broadcaster event stream lagged: {}", e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Consume media from the given producer. Video frames are received in
+    /// I420 format.
+    pub async fn consume(
+        &self,
+        producer_id: ProducerId,
+    ) -> Result<MediaConsumer, BroadcasterError> {
+        self.consume_with_format(producer_id, VideoFrameFormat::I420)
+            .await
+    }
+
+    /// Consume media from the given producer, receiving video frames in the
+    /// given format. The consumer is resumed once it has been created.
+    pub async fn consume_with_format(
+        &self,
+        producer_id: ProducerId,
+        format: VideoFrameFormat,
+    ) -> Result<MediaConsumer, BroadcasterError> {
+        let recv_transport_id = self.get_recv_transport_id()?;
+
+        let consumer_options = signal(
+            "consume",
+            self.shared.options.signalling_timeout,
+            self.shared
+                .signaller
+                .consume(recv_transport_id, producer_id),
+        )
+        .await?;
+        let consumer_id = consumer_options.id.clone();
+
+        // spawn on blocking thread
+        let consumer = tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let sys = broadcaster.sys();
+                match consumer_options.kind {
+                    MediaKind::Audio => {
+                        AudioConsumer::new(sys, consumer_options).map(MediaConsumer::Audio)
+                    }
+                    MediaKind::Video => {
+                        VideoConsumer::new(sys, consumer_options, format).map(MediaConsumer::Video)
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap()?;
+
+        signal(
+            "consumer_resume",
+            self.shared.options.signalling_timeout,
+            self.shared.signaller.consumer_resume(consumer_id),
+        )
+        .await?;
+        Ok(consumer)
     }
 
-    /// Consume data from the given data producer.
+    /// Consume data from the given data producer, buffering up to 32
+    /// messages and dropping new messages when the buffer is full.
     pub async fn consume_data(
         &self,
         data_producer_id: DataProducerId,
-    ) -> Result<DataConsumer, Box<dyn std::error::Error>> {
-        let recv_transport_id = self.get_recv_transport_id();
+    ) -> Result<DataConsumer, BroadcasterError> {
+        self.consume_data_with(data_producer_id, ConsumeDataOptions::default())
+            .await
+    }
 
-        let data_consumer_options = self
-            .shared
-            .signaller
-            .consume_data(recv_transport_id, data_producer_id.clone())
-            .await?;
+    /// Consume data from the given data producer, with the given buffer
+    /// capacity and overflow policy. Fails with
+    /// `BroadcasterError::InvalidOptions` if the capacity is 0.
+    pub async fn consume_data_with(
+        &self,
+        data_producer_id: DataProducerId,
+        options: ConsumeDataOptions,
+    ) -> Result<DataConsumer, BroadcasterError> {
+        options.validate()?;
+        let recv_transport_id = self.get_recv_transport_id()?;
+
+        let data_consumer_options = signal(
+            "consume_data",
+            self.shared.options.signalling_timeout,
+            self.shared
+                .signaller
+                .consume_data(recv_transport_id, data_producer_id.clone()),
+        )
+        .await?;
 
         // spawn on blocking thread
         let data_consumer = tokio::task::spawn_blocking({
             let broadcaster = self.clone();
             move || {
                 let sys = broadcaster.sys();
-                let data_consumer_rx = broadcaster.shared.data_channel_tx.subscribe();
-                DataConsumer::new(sys, data_consumer_options, data_consumer_rx)
+                DataConsumer::new(
+                    sys,
+                    broadcaster.downgrade(),
+                    data_consumer_options,
+                    options,
+                    broadcaster.shared.data_channel_router.clone(),
+                )
             }
         })
         .await
-        .unwrap();
+        .unwrap()?;
         Ok(data_consumer)
     }
 
-    /// Produce data on send transport.
-    pub async fn produce_data(&self) -> DataProducer {
+    /// Stats of both transports, including those of all producers, consumers
+    /// and data channels.
+    pub async fn get_stats(&self) -> Result<BroadcasterStats, BroadcasterError> {
         // spawn on blocking thread
-        let data_producer = tokio::task::spawn_blocking({
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let stats = unsafe {
+                    unmarshal_str(try_sys(|error| {
+                        sys::broadcaster_marshal_stats(broadcaster.sys(), error)
+                    })?)
+                };
+                let mut stats: serde_json::Value = serde_json::from_str(&stats)?;
+                Ok(BroadcasterStats {
+                    send_transport: StatsReport::from_json(stats["send"].take())?,
+                    recv_transport: StatsReport::from_json(stats["recv"].take())?,
+                })
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Get the stats of both transports every `interval`, until the
+    /// broadcaster is dropped. Failures to get the stats are logged and
+    /// skipped. Intervals shorter than `MIN_STATS_INTERVAL` are raised to it.
+    pub fn stats_stream(&self, interval: Duration) -> impl Stream<Item = BroadcasterStats> {
+        let broadcaster = self.downgrade();
+        let interval = tokio::time::interval(interval.max(MIN_STATS_INTERVAL));
+        futures::stream::unfold(
+            (broadcaster, interval),
+            |(broadcaster, mut interval)| async move {
+                loop {
+                    interval.tick().await;
+                    let stats = broadcaster.upgrade()?.get_stats().await;
+                    match stats {
+                        Ok(stats) => return Some((stats, (broadcaster, interval))),
+                        Err(e) => log::warn!("failed to get stats: {}", e),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Produce data on send transport, unordered but reliable and without a
+    /// label, with the default `DataProducerOptions`.
+    pub async fn produce_data(&self) -> Result<DataProducer, BroadcasterError> {
+        self.produce_data_with(DataProducerOptions::default()).await
+    }
+
+    /// Produce data on send transport with the given reliability, ordering,
+    /// label and protocol. Fails with `BroadcasterError::InvalidOptions` if
+    /// both reliability limits are set, or either is 0.
+    pub async fn produce_data_with(
+        &self,
+        options: DataProducerOptions,
+    ) -> Result<DataProducer, BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
             let broadcaster = self.clone();
             move || {
                 let sys = broadcaster.sys();
-                let data_producer_rx = broadcaster.shared.data_channel_tx.subscribe();
-                DataProducer::new(sys, data_producer_rx)
+                let data_producer = DataProducer::new(
+                    sys,
+                    broadcaster.downgrade(),
+                    options,
+                    broadcaster.shared.data_channel_router.clone(),
+                )?;
+                broadcaster.register_producer(data_producer.downgrade_reproduce());
+                Ok(data_producer)
             }
         })
         .await
-        .unwrap();
-        data_producer
+        .unwrap()
     }
 
     /// Produce a fake media stream for debugging purposes (leaks memory).
@@ -231,14 +836,58 @@ impl Broadcaster {
     //     }
     // }
 
+    /// Produce an audio stream using AlsaCapturer, from the recording device
+    /// and with the audio processing and Opus settings in the given options.
+    ///
+    /// Fails with `BroadcasterError::ConflictingAudioSources` while a
+    /// `ForeignAudioProducer` of any broadcaster in the process is alive. All
+    /// broadcasters share one audio device module, which would also send the
+    /// captured audio on its stream.
+    pub async fn produce_audio_from_alsa(
+        &self,
+        options: AlsaOptions,
+    ) -> Result<AlsaCapturer, BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let sys = broadcaster.sys();
+                let producer = AlsaCapturer::new(sys, broadcaster.producer_signaller(), options)?;
+                broadcaster.register_producer(producer.downgrade_reproduce());
+                Ok(producer)
+            }
+        })
+        .await
+        .unwrap()
+    }
+
     // Produce an audio stream using AlsaCapturer, from the default ALSA device.
-    pub async fn produce_audio_from_default_alsa(&self) -> AlsaCapturer {
+    pub async fn produce_audio_from_default_alsa(&self) -> Result<AlsaCapturer, BroadcasterError> {
+        self.produce_audio_from_alsa(AlsaOptions::default()).await
+    }
+
+    /// Produce a video stream using VcmCapturer, allowing us to capture from
+    /// any video device (e.g. webcam, capture card). The device is selected
+    /// and the capture mode negotiated according to the given options. You
+    /// can query the devices and their capabilities with
+    /// `devices::list_video_devices`.
+    pub async fn produce_video_from_vcm_capturer_with_options(
+        &self,
+        options: VcmOptions,
+    ) -> Result<VcmCapturer, BroadcasterError> {
         // spawn on blocking thread
         tokio::task::spawn_blocking({
             let broadcaster = self.clone();
             move || {
                 let sys = broadcaster.sys();
-                AlsaCapturer::new(sys)
+                let producer = VcmCapturer::new(
+                    sys,
+                    broadcaster.producer_signaller(),
+                    options,
+                    broadcaster.shared.event_tx.clone(),
+                )?;
+                broadcaster.register_producer(producer.downgrade_reproduce());
+                Ok(producer)
             }
         })
         .await
@@ -249,7 +898,7 @@ impl Broadcaster {
     /// any video device (e.g. webcam, capture card). The video device must
     /// support the given width, height, and FPS with the specified video
     /// format. You can query the capabilities of your video device with
-    /// `v4l2-ctl -d /dev/videoX --list-formats-ext`.
+    /// `devices::list_video_devices`.
     pub async fn produce_video_from_vcm_capturer_with_format(
         &self,
         device_idx: Option<i32>,
@@ -257,17 +906,20 @@ impl Broadcaster {
         height: u32,
         fps: u32,
         video_type: VideoType,
-    ) -> VcmCapturer {
-        // spawn on blocking thread
-        tokio::task::spawn_blocking({
-            let broadcaster = self.clone();
-            move || {
-                let sys = broadcaster.sys();
-                VcmCapturer::new(sys, device_idx, width, height, fps, video_type)
-            }
+    ) -> Result<VcmCapturer, BroadcasterError> {
+        self.produce_video_from_vcm_capturer_with_options(VcmOptions {
+            device: device_idx
+                .and_then(|idx| u32::try_from(idx).ok())
+                .map(VcmDevice::Index),
+            width,
+            height,
+            fps,
+            video_type,
+            best_match: false,
+            failover: None,
+            producer: VideoProducerOptions::default(),
         })
         .await
-        .unwrap()
     }
 
     pub async fn produce_video_from_vcm_capturer(
@@ -276,7 +928,7 @@ impl Broadcaster {
         width: u32,
         height: u32,
         fps: u32,
-    ) -> VcmCapturer {
+    ) -> Result<VcmCapturer, BroadcasterError> {
         self.produce_video_from_vcm_capturer_with_format(
             device_idx,
             width,
@@ -296,13 +948,79 @@ impl Broadcaster {
         width: u32,
         height: u32,
         fps: u32,
-    ) -> ForeignProducer {
+    ) -> Result<ForeignProducer, BroadcasterError> {
+        self.produce_video_from_frame_source_with_options(
+            frame_source,
+            width,
+            height,
+            fps,
+            VideoProducerOptions::default(),
+        )
+        .await
+    }
+
+    /// Produce a video stream from a programatically generated source, with
+    /// the given producer options.
+    pub async fn produce_video_from_frame_source_with_options(
+        &self,
+        frame_source: Arc<dyn FrameSource>,
+        width: u32,
+        height: u32,
+        fps: u32,
+        options: VideoProducerOptions,
+    ) -> Result<ForeignProducer, BroadcasterError> {
         // spawn on blocking thread
         tokio::task::spawn_blocking({
             let broadcaster = self.clone();
             move || {
                 let sys = broadcaster.sys();
-                ForeignProducer::new(sys, frame_source, width, height, fps)
+                let producer = ForeignProducer::new(
+                    sys,
+                    broadcaster.producer_signaller(),
+                    frame_source,
+                    width,
+                    height,
+                    fps,
+                    options,
+                )?;
+                broadcaster.register_producer(producer.downgrade_reproduce());
+                Ok(producer)
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Produce an audio stream from a programatically generated source.
+    /// The provided audio source will be polled from a dedicated thread for
+    /// 10ms of audio at a time, at the given sample rate and channel count.
+    /// The sample rate must be a multiple of 100.
+    ///
+    /// Fails with `BroadcasterError::ConflictingAudioSources` while an
+    /// `AlsaCapturer` of any broadcaster in the process is alive. All
+    /// broadcasters share one audio device module, which would also send the
+    /// audio it captures on this stream. `PlainBroadcaster` has no such
+    /// restriction.
+    pub async fn produce_audio_from_audio_source(
+        &self,
+        audio_source: Arc<dyn AudioSource>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<ForeignAudioProducer, BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let sys = broadcaster.sys();
+                let producer = ForeignAudioProducer::new(
+                    sys,
+                    broadcaster.producer_signaller(),
+                    audio_source,
+                    sample_rate,
+                    channels,
+                )?;
+                broadcaster.register_producer(producer.downgrade_reproduce());
+                Ok(producer)
             }
         })
         .await
@@ -314,24 +1032,162 @@ impl Broadcaster {
         state.sys_broadcaster
     }
 
+    fn producer_signaller(&self) -> ProducerSignaller {
+        ProducerSignaller {
+            channel_tx: self.shared.channel_tx.clone(),
+        }
+    }
+
+    /// Keep track of a producer so that it is produced again when transports
+    /// are recreated.
+    fn register_producer(&self, producer: WeakReproduce) {
+        let mut producers = self.shared.producers.lock().unwrap();
+        producers.retain(|producer| producer.strong_count() > 0);
+        producers.push(producer);
+    }
+
+    /// Spawn a recovery task for the given transport according to the
+    /// recovery policy, unless one is already running.
+    fn start_recovery(&self, transport_id: TransportId) {
+        let policy = match &self.shared.options.recovery {
+            Some(policy) => policy.clone(),
+            None => return,
+        };
+        if self.shared.recovering.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let broadcaster = self.downgrade();
+        tokio::spawn(async move {
+            recover(broadcaster.clone(), transport_id, policy).await;
+            if let Some(broadcaster) = broadcaster.upgrade() {
+                broadcaster.shared.recovering.store(false, Ordering::SeqCst);
+            }
+        });
+    }
+
+    /// Value of the `broadcaster` label of the metrics of this broadcaster,
+    /// unique within the process.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_label(&self) -> &str {
+        &self.shared.metrics_label
+    }
+
+    /// Stats of all live media producers.
+    #[cfg(feature = "metrics")]
+    pub(crate) async fn producer_stats(
+        &self,
+    ) -> Result<Vec<(ProducerId, MediaKind, StatsReport)>, BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let producers: Vec<_> = {
+                    let producers = broadcaster.shared.producers.lock().unwrap();
+                    producers.iter().filter_map(Weak::upgrade).collect()
+                };
+                let mut stats = Vec::new();
+                for producer in &producers {
+                    let producer = match producer.media_producer() {
+                        Some(producer) => producer,
+                        None => continue,
+                    };
+                    let (producer_id, kind) = match (producer.id(), producer.kind()) {
+                        (Some(producer_id), Some(kind)) => (producer_id, kind),
+                        _ => continue,
+                    };
+                    match producer.get_stats() {
+                        Ok(report) => stats.push((producer_id, kind, report)),
+                        Err(BroadcasterError::ProducerClosed) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(stats)
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    fn transport_state(&self, transport_id: &TransportId) -> Option<TransportConnectionState> {
+        let transport_states = self.shared.transport_states.lock().unwrap();
+        transport_states.get(transport_id).copied()
+    }
+
+    async fn restart_ice(&self, transport_id: TransportId) -> Result<(), BroadcasterError> {
+        let ice_parameters = signal(
+            "restart_ice",
+            self.shared.options.signalling_timeout,
+            self.shared.signaller.restart_ice(transport_id.clone()),
+        )
+        .await?;
+
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let transport_id_cstr = marshal_str(String::from(transport_id))?;
+                let ice_parameters_cstr = marshal_str(serde_json::to_string(&ice_parameters)?)?;
+                unsafe {
+                    try_sys(|error| {
+                        sys::broadcaster_restart_ice(
+                            broadcaster.sys(),
+                            transport_id_cstr.as_ptr(),
+                            ice_parameters_cstr.as_ptr(),
+                            error,
+                        )
+                    })?;
+                }
+                Ok(())
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Recreate both transports and produce all live producers and data
+    /// producers again. Producers which fail to be produced again are logged
+    /// and skipped.
+    async fn recreate_transports(&self) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let broadcaster = self.clone();
+            move || {
+                let sys = broadcaster.sys();
+                unsafe {
+                    try_sys(|error| sys::broadcaster_recreate_transports(sys, error))?;
+                }
+                broadcaster.shared.transport_states.lock().unwrap().clear();
+                let producers: Vec<_> = {
+                    let mut producers = broadcaster.shared.producers.lock().unwrap();
+                    producers.retain(|producer| producer.strong_count() > 0);
+                    producers.iter().filter_map(Weak::upgrade).collect()
+                };
+                for producer in producers {
+                    if let Err(e) = producer.reproduce(sys) {
+                        log::error!("failed to produce again: {}", e);
+                    }
+                }
+                unsafe { sys::broadcaster_delete_closed_transports(sys) };
+                Ok(())
+            }
+        })
+        .await
+        .unwrap()
+    }
+
     pub fn downgrade(&self) -> WeakBroadcaster {
         WeakBroadcaster {
             shared: Arc::downgrade(&self.shared),
         }
     }
 
-    fn get_recv_transport_id(&self) -> TransportId {
-        unsafe {
-            let recv_transport_id_marshal = sys::broadcaster_marshal_recv_transport_id(self.sys());
-            let recv_transport_id = TransportId::from(
-                CStr::from_ptr(recv_transport_id_marshal)
-                    .to_str()
-                    .unwrap()
-                    .to_owned(),
-            );
-            sys::cpp_unmarshal_str(recv_transport_id_marshal);
-            recv_transport_id
-        }
+    fn get_recv_transport_id(&self) -> Result<TransportId, BroadcasterError> {
+        let recv_transport_id = unsafe {
+            unmarshal_str(try_sys(|error| {
+                sys::broadcaster_marshal_recv_transport_id(self.sys(), error)
+            })?)
+        };
+        Ok(TransportId::from(recv_transport_id))
     }
 }
 impl WeakBroadcaster {
@@ -341,128 +1197,370 @@ impl WeakBroadcaster {
     }
 }
 
+impl ProducerSignaller {
+    pub(crate) fn paused(&self, producer_id: ProducerId) {
+        let _ = self
+            .channel_tx
+            .send(InternalMessage::ProducerPaused { producer_id });
+    }
+
+    pub(crate) fn resumed(&self, producer_id: ProducerId) {
+        let _ = self
+            .channel_tx
+            .send(InternalMessage::ProducerResumed { producer_id });
+    }
+
+    /// Notify the signaller without waiting for it, e.g. when a producer is
+    /// dropped.
+    pub(crate) fn closed(&self, producer_id: ProducerId) {
+        let _ = self.channel_tx.send(InternalMessage::ProducerClosed {
+            producer_id,
+            done: None,
+        });
+    }
+
+    /// Notify the signaller and wait until it has handled the producer being
+    /// closed.
+    pub(crate) async fn close(&self, producer_id: ProducerId) -> Result<(), BroadcasterError> {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.channel_tx
+            .send(InternalMessage::ProducerClosed {
+                producer_id,
+                done: Some(done_tx),
+            })
+            .map_err(|_| BroadcasterError::SignallingFailed("broadcaster dropped".to_owned()))?;
+        done_rx.await.unwrap_or_else(|_| {
+            Err(BroadcasterError::SignallingFailed(
+                "broadcaster dropped".to_owned(),
+            ))
+        })
+    }
+}
+
+impl Shared {
+    /// Timeout for the next signaller call, taking into account the connect
+    /// deadline while the broadcaster is being created.
+    fn signalling_timeout(&self) -> Option<Duration> {
+        let remaining = self
+            .connect_deadline
+            .lock()
+            .unwrap()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        match (self.options.signalling_timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        }
+    }
+}
+
 impl Drop for Shared {
     fn drop(&mut self) {
         let state = self.state.lock().unwrap();
-        log::trace!("broadcaster delete {:?}", state.sys_broadcaster);
-        unsafe { sys::broadcaster_delete(state.sys_broadcaster) }
+        if !state.sys_broadcaster.is_null() {
+            log::trace!("broadcaster delete {:?}", state.sys_broadcaster);
+            unsafe { sys::broadcaster_delete(state.sys_broadcaster) }
+        }
+        // no more connection states are recorded once deleted
+        #[cfg(feature = "metrics")]
+        crate::metrics::remove_broadcaster(&self.metrics_label);
     }
 }
 
-extern "C" fn server_rtp_capabilities(ctx: *const c_void) -> *mut c_char {
-    log::trace!("server_rtp_capabilities({:?})", ctx);
-    let shared = unsafe { &*(ctx as *const Shared) };
+/// Recover a disconnected or failed transport according to the given policy.
+async fn recover(broadcaster: WeakBroadcaster, transport_id: TransportId, policy: RecoveryPolicy) {
+    let mut backoff = policy.initial_backoff;
+    let mut ice_restarted = false;
+    // once transports are being recreated the old transport is closed, so its
+    // state no longer tells whether recovery is needed
+    let mut recreating = false;
+    for attempt in 1..=policy.max_attempts {
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+        let broadcaster = match broadcaster.upgrade() {
+            Some(broadcaster) => broadcaster,
+            None => return,
+        };
+        if !recreating {
+            match broadcaster.transport_state(&transport_id) {
+                Some(TransportConnectionState::Disconnected)
+                | Some(TransportConnectionState::Failed) => {}
+                state => {
+                    log::info!("{:?}: recovered ({:?})", &transport_id, state);
+                    return;
+                }
+            }
+        }
+        log::info!("{:?}: recovery attempt {}", &transport_id, attempt);
 
+        if policy.ice_restart && !ice_restarted {
+            match broadcaster.restart_ice(transport_id.clone()).await {
+                Ok(()) => {
+                    ice_restarted = true;
+                    let _ = broadcaster
+                        .shared
+                        .event_tx
+                        .send(BroadcasterEvent::IceRestarted {
+                            transport_id: transport_id.clone(),
+                        });
+                    // wait for the next attempt to see whether it reconnected
+                    continue;
+                }
+                Err(e) => log::warn!("{:?}: ICE restart failed: {}", &transport_id, e),
+            }
+        }
+        if policy.recreate_transports {
+            recreating = true;
+            match broadcaster.recreate_transports().await {
+                Ok(()) => {
+                    let _ = broadcaster
+                        .shared
+                        .event_tx
+                        .send(BroadcasterEvent::TransportsRecreated);
+                    return;
+                }
+                Err(e) => log::warn!("{:?}: recreating transports failed: {}", &transport_id, e),
+            }
+        }
+    }
+    log::error!("{:?}: recovery failed", &transport_id);
+    if let Some(broadcaster) = broadcaster.upgrade() {
+        let _ = broadcaster
+            .shared
+            .event_tx
+            .send(BroadcasterEvent::RecoveryFailed { transport_id });
+    }
+}
+
+/// Await a signaller call, failing if it does not complete within the given
+/// timeout. Failures are logged along with the signalling step.
+pub(crate) async fn signal<T>(
+    step: &str,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, SignallerError>>,
+) -> Result<T, BroadcasterError> {
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+    let result = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+            Ok(result) => {
+                result.map_err(|e| BroadcasterError::SignallingFailed(format!("{}: {}", step, e)))
+            }
+            Err(_) => Err(BroadcasterError::TimedOut(format!(
+                "{} after {:?}",
+                step, timeout
+            ))),
+        },
+        None => fut
+            .await
+            .map_err(|e| BroadcasterError::SignallingFailed(format!("{}: {}", step, e))),
+    };
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_signalling(step, started.elapsed(), result.is_ok());
+    if let Err(e) = &result {
+        log::error!("signaller failed {}: {}", step, e);
+    }
+    result
+}
+
+/// Run a signaller call to completion on the runtime, blocking the calling
+/// RTC thread until it resolves or times out.
+fn block_on_signaller<T: Send + 'static>(
+    step: &'static str,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, SignallerError>> + Send + 'static,
+) -> Result<T, BroadcasterError> {
     let (tx, mut rx) = mpsc::channel(1);
-    let fut = shared.signaller.server_rtp_capabilities();
     tokio::spawn(async move {
-        tx.send(fut.await).await.unwrap();
+        let _ = tx.send(signal(step, timeout, fut).await).await;
     });
+    rx.blocking_recv().unwrap_or_else(|| {
+        Err(BroadcasterError::SignallingFailed(format!(
+            "{}: signaller did not complete",
+            step
+        )))
+    })
+}
 
-    let server_rtp_capabilities = rx.blocking_recv().unwrap();
-    CString::new(serde_json::to_string(&server_rtp_capabilities).unwrap())
-        .unwrap()
-        .into_raw()
+/// Report a signalling failure through a native `Error` out parameter.
+fn marshal_error(error: *mut sys::Error, e: BroadcasterError) {
+    let (kind, message) = match e {
+        BroadcasterError::TimedOut(message) => (sys::ErrorKind_ERR_TIMED_OUT, message),
+        BroadcasterError::SignallingFailed(message) => (sys::ErrorKind_ERR_SIGNALLING, message),
+        e => (sys::ErrorKind_ERR_SIGNALLING, e.to_string()),
+    };
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    unsafe {
+        (*error).kind = kind;
+        (*error).message = message.into_raw();
+    }
+}
+
+/// Borrow a string passed to a callback by native code.
+unsafe fn callback_str<'a>(s: *const c_char) -> Result<&'a str, BroadcasterError> {
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| BroadcasterError::Native(format!("invalid string from native code: {}", e)))
+}
+
+/// Parse a string passed to a callback by native code.
+unsafe fn callback_parse<T: FromStr>(what: &str, s: *const c_char) -> Result<T, BroadcasterError> {
+    let s = callback_str(s)?;
+    T::from_str(s).map_err(|_| BroadcasterError::Native(format!("unknown {}: {}", what, s)))
+}
+
+/// Parse a JSON document passed to a callback by native code.
+unsafe fn callback_json<T: serde::de::DeserializeOwned>(
+    s: *const c_char,
+) -> Result<T, BroadcasterError> {
+    Ok(serde_json::from_str(callback_str(s)?)?)
+}
+
+/// Marshal a string returned from a callback to native code.
+fn callback_return(s: String) -> Result<*mut c_char, BroadcasterError> {
+    marshal_str(s).map(CString::into_raw)
 }
-extern "C" fn create_webrtc_transport(ctx: *const c_void) -> *mut c_char {
+
+extern "C" fn server_rtp_capabilities(ctx: *const c_void, error: *mut sys::Error) -> *mut c_char {
+    log::trace!("server_rtp_capabilities({:?})", ctx);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    let fut = shared.signaller.server_rtp_capabilities();
+    let result = block_on_signaller("server_rtp_capabilities", shared.signalling_timeout(), fut)
+        .and_then(|server_rtp_capabilities| {
+            callback_return(serde_json::to_string(&server_rtp_capabilities)?)
+        });
+    match result {
+        Ok(server_rtp_capabilities) => server_rtp_capabilities,
+        Err(e) => {
+            marshal_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+extern "C" fn create_webrtc_transport(ctx: *const c_void, error: *mut sys::Error) -> *mut c_char {
     log::trace!("create_webrtc_transport({:?})", ctx);
     let shared = unsafe { &*(ctx as *const Shared) };
-    let (tx, mut rx) = mpsc::channel(1);
-    let fut = shared.signaller.create_webrtc_transport();
-    tokio::spawn(async move {
-        tx.send(fut.await).await.unwrap();
-    });
 
-    let webrtc_transport_options = rx.blocking_recv().unwrap();
-    CString::new(serde_json::to_string(&webrtc_transport_options).unwrap())
-        .unwrap()
-        .into_raw()
+    let fut = shared.signaller.create_webrtc_transport();
+    let result = block_on_signaller("create_webrtc_transport", shared.signalling_timeout(), fut)
+        .and_then(|webrtc_transport_options| {
+            callback_return(serde_json::to_string(&webrtc_transport_options)?)
+        });
+    match result {
+        Ok(webrtc_transport_options) => webrtc_transport_options,
+        Err(e) => {
+            marshal_error(error, e);
+            ptr::null_mut()
+        }
+    }
 }
-extern "C" fn on_rtp_capabilities(ctx: *const c_void, rtp_caps: *const c_char) {
+extern "C" fn on_rtp_capabilities(
+    ctx: *const c_void,
+    rtp_caps: *const c_char,
+    error: *mut sys::Error,
+) -> bool {
     log::trace!("on_rtp_capabilities({:?})", ctx);
     let shared = unsafe { &*(ctx as *const Shared) };
-    let rtp_caps = unsafe { CStr::from_ptr(rtp_caps).to_str().unwrap() };
 
-    let (tx, mut rx) = mpsc::channel(1);
-    let fut = shared.signaller.on_rtp_capabilities(RtpCapabilities::from(
-        serde_json::from_str::<serde_json::Value>(rtp_caps).unwrap(),
-    ));
-    tokio::spawn(async move { tx.send(fut.await).await.unwrap() });
-    let _ = rx.blocking_recv().unwrap();
+    let result = unsafe { callback_json::<serde_json::Value>(rtp_caps) }.and_then(|rtp_caps| {
+        let fut = shared
+            .signaller
+            .on_rtp_capabilities(RtpCapabilities::from(rtp_caps));
+        block_on_signaller("on_rtp_capabilities", shared.signalling_timeout(), fut)
+    });
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            marshal_error(error, e);
+            false
+        }
+    }
 }
 extern "C" fn on_produce(
     ctx: *const c_void,
     transport_id: *const c_char,
     kind: *const c_char,
     rtp_parameters: *const c_char,
+    error: *mut sys::Error,
 ) -> *mut c_char {
     log::trace!("on_produce({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let transport_id_cstr = CStr::from_ptr(transport_id);
-        let kind_cstr = CStr::from_ptr(kind);
-        let rtp_parameters = CStr::from_ptr(rtp_parameters).to_str().unwrap();
+    let shared = unsafe { &*(ctx as *const Shared) };
 
-        let (tx, mut rx) = mpsc::channel(1);
+    let result = (|| unsafe {
         let fut = shared.signaller.on_produce(
-            TransportId::from(transport_id_cstr.to_str().unwrap().to_owned()),
-            MediaKind::from_str(kind_cstr.to_string_lossy().as_ref()).unwrap(),
-            RtpParameters::from(serde_json::from_str::<serde_json::Value>(rtp_parameters).unwrap()),
+            TransportId::from(callback_str(transport_id)?.to_owned()),
+            callback_parse::<MediaKind>("media kind", kind)?,
+            RtpParameters::from(callback_json::<serde_json::Value>(rtp_parameters)?),
         );
-        tokio::spawn(async move {
-            tx.send(fut.await).await.unwrap();
-        });
-
-        let producer_id = rx.blocking_recv().unwrap();
-        CString::new(String::from(producer_id)).unwrap().into_raw()
+        let producer_id = block_on_signaller("on_produce", shared.signalling_timeout(), fut)?;
+        callback_return(String::from(producer_id))
+    })();
+    match result {
+        Ok(producer_id) => producer_id,
+        Err(e) => {
+            marshal_error(error, e);
+            ptr::null_mut()
+        }
     }
 }
 extern "C" fn on_produce_data(
     ctx: *const c_void,
     transport_id: *const c_char,
     sctp_stream_parameters: *const c_char,
+    label: *const c_char,
+    protocol: *const c_char,
+    app_data: *const c_char,
+    error: *mut sys::Error,
 ) -> *mut c_char {
     log::trace!("on_produce_data({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let transport_id_cstr = CStr::from_ptr(transport_id);
-        let sctp_stream_parameters = CStr::from_ptr(sctp_stream_parameters).to_str().unwrap();
-
-        let (tx, mut rx) = mpsc::channel(1);
-        let fut = shared.signaller.on_produce_data(
-            TransportId::from(transport_id_cstr.to_str().unwrap().to_owned()),
-            SctpStreamParameters::from(
-                serde_json::from_str::<serde_json::Value>(sctp_stream_parameters).unwrap(),
-            ),
-        );
-        tokio::spawn(async move {
-            tx.send(fut.await).await.unwrap();
-        });
+    let shared = unsafe { &*(ctx as *const Shared) };
 
-        let producer_id = rx.blocking_recv().unwrap();
-        CString::new(String::from(producer_id)).unwrap().into_raw()
+    let result = (|| unsafe {
+        let fut = shared.signaller.on_produce_data_with(
+            TransportId::from(callback_str(transport_id)?.to_owned()),
+            SctpStreamParameters::from(callback_json::<serde_json::Value>(sctp_stream_parameters)?),
+            callback_str(label)?.to_owned(),
+            callback_str(protocol)?.to_owned(),
+            callback_json(app_data)?,
+        );
+        let data_producer_id =
+            block_on_signaller("on_produce_data", shared.signalling_timeout(), fut)?;
+        callback_return(String::from(data_producer_id))
+    })();
+    match result {
+        Ok(data_producer_id) => data_producer_id,
+        Err(e) => {
+            marshal_error(error, e);
+            ptr::null_mut()
+        }
     }
 }
 extern "C" fn on_connect_webrtc_transport(
     ctx: *const c_void,
     transport_id: *const c_char,
     dtls_parameters: *const c_char,
-) {
+    error: *mut sys::Error,
+) -> bool {
     log::trace!("on_connect_webrtc_transport({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let transport_id_cstr = CStr::from_ptr(transport_id);
-        let dtls_parameters = CStr::from_ptr(dtls_parameters).to_str().unwrap();
+    let shared = unsafe { &*(ctx as *const Shared) };
 
-        let (tx, mut rx) = mpsc::channel(1);
+    let result = (|| unsafe {
         let fut = shared.signaller.on_connect_webrtc_transport(
-            TransportId::from(transport_id_cstr.to_str().unwrap().to_owned()),
-            DtlsParameters::from(
-                serde_json::from_str::<serde_json::Value>(dtls_parameters).unwrap(),
-            ),
+            TransportId::from(callback_str(transport_id)?.to_owned()),
+            DtlsParameters::from(callback_json::<serde_json::Value>(dtls_parameters)?),
         );
-        tokio::spawn(async move { tx.send(fut.await).await.unwrap() });
-        let _ = rx.blocking_recv().unwrap();
+        block_on_signaller(
+            "on_connect_webrtc_transport",
+            shared.signalling_timeout(),
+            fut,
+        )
+    })();
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            marshal_error(error, e);
+            false
+        }
     }
 }
 extern "C" fn on_data_consumer_message(
@@ -472,16 +1570,18 @@ extern "C" fn on_data_consumer_message(
     len: c_ulong,
 ) {
     log::trace!("on_data_consumer_message({:?}, len={})", ctx, len);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let data_consumer_id_cstr = CStr::from_ptr(data_consumer_id);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    let result = (|| unsafe {
+        let data_consumer_id = DataConsumerId::from(callback_str(data_consumer_id)?.to_owned());
         let message_data = std::slice::from_raw_parts(data as *const u8, len as usize).to_vec();
-        let _ = shared.data_channel_tx.send(data_channel::Message::Data {
-            data_consumer_id: DataConsumerId::from(
-                data_consumer_id_cstr.to_str().unwrap().to_owned(),
-            ),
-            data: message_data,
-        });
+        shared
+            .data_channel_router
+            .on_data_consumer_message(&data_consumer_id, message_data);
+        Ok::<_, BroadcasterError>(())
+    })();
+    if let Err(e) = result {
+        log::error!("on_data_consumer_message: {}", e);
     }
 }
 extern "C" fn on_data_consumer_state_changed(
@@ -490,19 +1590,24 @@ extern "C" fn on_data_consumer_state_changed(
     state: *const c_char,
 ) {
     log::trace!("on_data_consumer_state_changed({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let data_consumer_id_cstr = CStr::from_ptr(data_consumer_id);
-        let state_cstr = CStr::from_ptr(state);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    let result = (|| unsafe {
+        let data_consumer_id = DataConsumerId::from(callback_str(data_consumer_id)?.to_owned());
+        let state = callback_parse::<DataChannelState>("data channel state", state)?;
+        shared
+            .data_channel_router
+            .on_data_consumer_state_changed(&data_consumer_id, state);
         let _ = shared
-            .data_channel_tx
-            .send(data_channel::Message::DataConsumerStateChanged {
-                data_consumer_id: DataConsumerId::from(
-                    data_consumer_id_cstr.to_str().unwrap().to_owned(),
-                ),
-                state: data_channel::DataChannelState::from_str(state_cstr.to_str().unwrap())
-                    .unwrap(),
+            .event_tx
+            .send(BroadcasterEvent::DataConsumerStateChanged {
+                data_consumer_id,
+                state,
             });
+        Ok::<_, BroadcasterError>(())
+    })();
+    if let Err(e) = result {
+        log::error!("on_data_consumer_state_changed: {}", e);
     }
 }
 extern "C" fn on_data_producer_state_changed(
@@ -511,37 +1616,115 @@ extern "C" fn on_data_producer_state_changed(
     state: *const c_char,
 ) {
     log::trace!("on_data_producer_state_changed({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let data_producer_id_cstr = CStr::from_ptr(data_producer_id);
-        let state_cstr = CStr::from_ptr(state);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    let result = (|| unsafe {
+        let data_producer_id = DataProducerId::from(callback_str(data_producer_id)?.to_owned());
+        let state = callback_parse::<DataChannelState>("data channel state", state)?;
+        shared
+            .data_channel_router
+            .on_data_producer_state_changed(&data_producer_id, state);
         let _ = shared
-            .data_channel_tx
-            .send(data_channel::Message::DataProducerStateChanged {
-                data_producer_id: DataProducerId::from(
-                    data_producer_id_cstr.to_str().unwrap().to_owned(),
-                ),
-                state: data_channel::DataChannelState::from_str(state_cstr.to_str().unwrap())
-                    .unwrap(),
+            .event_tx
+            .send(BroadcasterEvent::DataProducerStateChanged {
+                data_producer_id,
+                state,
             });
+        Ok::<_, BroadcasterError>(())
+    })();
+    if let Err(e) = result {
+        log::error!("on_data_producer_state_changed: {}", e);
     }
 }
 extern "C" fn on_connection_state_changed(
     ctx: *const c_void,
     transport_id: *const c_char,
+    direction: *const c_char,
     state: *const c_char,
 ) {
     log::trace!("on_connection_state_changed({:?})", ctx);
-    unsafe {
-        let shared = &*(ctx as *const Shared);
-        let transport_id_cstr = CStr::from_ptr(transport_id);
-        let state_cstr = CStr::from_ptr(state);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    let result = (|| unsafe {
+        let transport_id = TransportId::from(callback_str(transport_id)?.to_owned());
+        let direction = callback_parse::<TransportDirection>("transport direction", direction)?;
+        let state = callback_parse::<TransportConnectionState>("connection state", state)?;
 
+        shared
+            .transport_states
+            .lock()
+            .unwrap()
+            .insert(transport_id.clone(), state);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_transport_connection_state(&shared.metrics_label, direction, state);
         let _ = shared
             .channel_tx
             .send(InternalMessage::TransportConnectionStateChanged {
-                transport_id: TransportId::from(transport_id_cstr.to_str().unwrap().to_owned()),
-                state: TransportConnectionState::from_str(state_cstr.to_str().unwrap()).unwrap(),
+                transport_id: transport_id.clone(),
+                state,
             });
+        let _ = shared
+            .event_tx
+            .send(BroadcasterEvent::TransportConnectionStateChanged {
+                direction,
+                transport_id,
+                state,
+            });
+        Ok::<_, BroadcasterError>(())
+    })();
+    if let Err(e) = result {
+        log::error!("on_connection_state_changed: {}", e);
+    }
+}
+extern "C" fn on_producer_transport_close(ctx: *const c_void, producer_id: *const c_char) {
+    log::trace!("on_producer_transport_close({:?})", ctx);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    match unsafe { callback_str(producer_id) } {
+        Ok(producer_id) => {
+            let producer_id = ProducerId::from(producer_id.to_owned());
+            let _ = shared
+                .event_tx
+                .send(BroadcasterEvent::ProducerTransportClosed { producer_id });
+        }
+        Err(e) => log::error!("on_producer_transport_close: {}", e),
+    }
+}
+extern "C" fn on_consumer_transport_close(ctx: *const c_void, consumer_id: *const c_char) {
+    log::trace!("on_consumer_transport_close({:?})", ctx);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    match unsafe { callback_str(consumer_id) } {
+        Ok(consumer_id) => {
+            let consumer_id = ConsumerId::from(consumer_id.to_owned());
+            let _ = shared
+                .event_tx
+                .send(BroadcasterEvent::ConsumerTransportClosed { consumer_id });
+        }
+        Err(e) => log::error!("on_consumer_transport_close: {}", e),
+    }
+}
+extern "C" fn on_data_producer_buffered_amount_change(
+    ctx: *const c_void,
+    data_producer_id: *const c_char,
+    buffered_amount: u64,
+) {
+    log::trace!("on_data_producer_buffered_amount_change({:?})", ctx);
+    let shared = unsafe { &*(ctx as *const Shared) };
+
+    match unsafe { callback_str(data_producer_id) } {
+        Ok(data_producer_id) => {
+            let data_producer_id = DataProducerId::from(data_producer_id.to_owned());
+            shared
+                .data_channel_router
+                .on_data_producer_buffered_amount_change(&data_producer_id, buffered_amount);
+            let _ = shared
+                .event_tx
+                .send(BroadcasterEvent::DataProducerBufferedAmountChanged {
+                    data_producer_id,
+                    buffered_amount,
+                });
+        }
+        Err(e) => log::error!("on_data_producer_buffered_amount_change: {}", e),
     }
 }
@@ -0,0 +1,283 @@
+use std::{
+    ffi::c_void,
+    os::raw::c_ulong,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::broadcaster::{marshal_str, try_sys, BroadcasterError};
+use crate::types::*;
+use vulcast_rtc_sys as sys;
+
+/// Number of decoded frames buffered before new ones are dropped.
+const VIDEO_FRAME_BUFFER: usize = 4;
+/// Number of 10ms audio frames buffered before new ones are dropped.
+const AUDIO_FRAME_BUFFER: usize = 32;
+
+/// Pixel format of frames received from a `VideoConsumer`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VideoFrameFormat {
+    /// Y, U and V planes packed one after the other without padding. The
+    /// chroma planes are half the width and height of the frame, rounded up.
+    I420,
+    /// 32-bit pixels, in the same layout a `FrameSource` provides them.
+    Rgba,
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Timestamp in microseconds.
+    pub timestamp: i64,
+    pub format: VideoFrameFormat,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioFrame {
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// Interleaved 16-bit PCM samples.
+    pub samples: Vec<i16>,
+}
+
+/// Consumer of a remote producer, depending on its media kind.
+pub enum MediaConsumer {
+    Audio(AudioConsumer),
+    Video(VideoConsumer),
+}
+impl MediaConsumer {
+    pub fn id(&self) -> ConsumerId {
+        match self {
+            MediaConsumer::Audio(consumer) => consumer.id(),
+            MediaConsumer::Video(consumer) => consumer.id(),
+        }
+    }
+    pub fn producer_id(&self) -> ProducerId {
+        match self {
+            MediaConsumer::Audio(consumer) => consumer.producer_id(),
+            MediaConsumer::Video(consumer) => consumer.producer_id(),
+        }
+    }
+    pub fn kind(&self) -> MediaKind {
+        match self {
+            MediaConsumer::Audio(_) => MediaKind::Audio,
+            MediaConsumer::Video(_) => MediaKind::Video,
+        }
+    }
+}
+
+/// Stream of decoded frames from a remote video producer. Frames are dropped
+/// if the stream is not polled quickly enough.
+pub struct VideoConsumer {
+    sys_consumer: *mut sys::mediasoupclient_Consumer,
+    sys_sink: *mut sys::ForeignVideoSink,
+    consumer_id: ConsumerId,
+    producer_id: ProducerId,
+    // context of sys_sink, which must outlive it
+    _sink: Box<VideoSink>,
+    frame_rx: mpsc::Receiver<VideoFrame>,
+}
+unsafe impl Send for VideoConsumer {}
+unsafe impl Sync for VideoConsumer {}
+struct VideoSink {
+    format: VideoFrameFormat,
+    frame_tx: mpsc::Sender<VideoFrame>,
+}
+
+impl VideoConsumer {
+    pub(crate) fn new(
+        sys_broadcaster: *mut sys::Broadcaster,
+        consumer_options: ConsumerOptions,
+        format: VideoFrameFormat,
+    ) -> Result<Self, BroadcasterError> {
+        let sys_consumer = unsafe { consumer_new(sys_broadcaster, &consumer_options)? };
+        let (frame_tx, frame_rx) = mpsc::channel(VIDEO_FRAME_BUFFER);
+        let sink = Box::new(VideoSink { format, frame_tx });
+        let sys_sink = unsafe {
+            sys::video_sink_new(
+                sys_consumer,
+                match format {
+                    VideoFrameFormat::I420 => sys::VideoFrameFormat_FRAME_I420,
+                    VideoFrameFormat::Rgba => sys::VideoFrameFormat_FRAME_RGBA,
+                },
+                &*sink as *const _ as *const c_void,
+                Some(video_sink_on_frame),
+            )
+        };
+        Ok(Self {
+            sys_consumer,
+            sys_sink,
+            consumer_id: consumer_options.id,
+            producer_id: consumer_options.producer_id,
+            _sink: sink,
+            frame_rx,
+        })
+    }
+
+    pub fn id(&self) -> ConsumerId {
+        self.consumer_id.clone()
+    }
+    pub fn producer_id(&self) -> ProducerId {
+        self.producer_id.clone()
+    }
+}
+impl Drop for VideoConsumer {
+    fn drop(&mut self) {
+        log::trace!("consumer delete {:?}", &self.sys_consumer);
+        unsafe {
+            sys::video_sink_delete(self.sys_consumer, self.sys_sink);
+            sys::consumer_delete(self.sys_consumer);
+        }
+    }
+}
+impl Stream for VideoConsumer {
+    type Item = VideoFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frame_rx.poll_recv(cx)
+    }
+}
+
+/// Stream of PCM samples from a remote audio producer. Samples are only
+/// delivered while the audio device module is playing out, and are dropped if
+/// the stream is not polled quickly enough.
+pub struct AudioConsumer {
+    sys_consumer: *mut sys::mediasoupclient_Consumer,
+    sys_sink: *mut sys::ForeignAudioSink,
+    consumer_id: ConsumerId,
+    producer_id: ProducerId,
+    // context of sys_sink, which must outlive it
+    _sink: Box<AudioSink>,
+    frame_rx: mpsc::Receiver<AudioFrame>,
+}
+unsafe impl Send for AudioConsumer {}
+unsafe impl Sync for AudioConsumer {}
+struct AudioSink {
+    frame_tx: mpsc::Sender<AudioFrame>,
+}
+
+impl AudioConsumer {
+    pub(crate) fn new(
+        sys_broadcaster: *mut sys::Broadcaster,
+        consumer_options: ConsumerOptions,
+    ) -> Result<Self, BroadcasterError> {
+        let sys_consumer = unsafe { consumer_new(sys_broadcaster, &consumer_options)? };
+        let (frame_tx, frame_rx) = mpsc::channel(AUDIO_FRAME_BUFFER);
+        let sink = Box::new(AudioSink { frame_tx });
+        let sys_sink = unsafe {
+            sys::audio_sink_new(
+                sys_consumer,
+                &*sink as *const _ as *const c_void,
+                Some(audio_sink_on_data),
+            )
+        };
+        Ok(Self {
+            sys_consumer,
+            sys_sink,
+            consumer_id: consumer_options.id,
+            producer_id: consumer_options.producer_id,
+            _sink: sink,
+            frame_rx,
+        })
+    }
+
+    pub fn id(&self) -> ConsumerId {
+        self.consumer_id.clone()
+    }
+    pub fn producer_id(&self) -> ProducerId {
+        self.producer_id.clone()
+    }
+}
+impl Drop for AudioConsumer {
+    fn drop(&mut self) {
+        log::trace!("consumer delete {:?}", &self.sys_consumer);
+        unsafe {
+            sys::audio_sink_delete(self.sys_consumer, self.sys_sink);
+            sys::consumer_delete(self.sys_consumer);
+        }
+    }
+}
+impl Stream for AudioConsumer {
+    type Item = AudioFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frame_rx.poll_recv(cx)
+    }
+}
+
+unsafe fn consumer_new(
+    sys_broadcaster: *mut sys::Broadcaster,
+    consumer_options: &ConsumerOptions,
+) -> Result<*mut sys::mediasoupclient_Consumer, BroadcasterError> {
+    let consumer_id_cstr = marshal_str(String::from(consumer_options.id.clone()))?;
+    let producer_id_cstr = marshal_str(String::from(consumer_options.producer_id.clone()))?;
+    let kind_cstr = marshal_str(match consumer_options.kind {
+        MediaKind::Audio => "audio",
+        MediaKind::Video => "video",
+    })?;
+    let rtp_parameters_cstr =
+        marshal_str(serde_json::to_string(&consumer_options.rtp_parameters)?)?;
+    let sys_consumer = try_sys(|error| {
+        sys::consumer_new(
+            sys_broadcaster,
+            consumer_id_cstr.as_ptr(),
+            producer_id_cstr.as_ptr(),
+            kind_cstr.as_ptr(),
+            rtp_parameters_cstr.as_ptr(),
+            error,
+        )
+    })?;
+    log::trace!("consumer new {:?}", &sys_consumer);
+    Ok(sys_consumer)
+}
+
+extern "C" fn video_sink_on_frame(
+    ctx: *const c_void,
+    width: u32,
+    height: u32,
+    timestamp: i64,
+    data: *const u8,
+    len: c_ulong,
+) {
+    let sink = unsafe { &*(ctx as *const VideoSink) };
+    match sink.frame_tx.try_reserve() {
+        Ok(permit) => permit.send(VideoFrame {
+            width,
+            height,
+            timestamp,
+            format: sink.format,
+            data: unsafe { std::slice::from_raw_parts(data, len as usize) }.to_vec(),
+        }),
+        Err(TrySendError::Full(())) => {
+            log::warn!("video frame dropped, you are reading stream too slowly!")
+        }
+        Err(TrySendError::Closed(())) => {}
+    }
+}
+
+extern "C" fn audio_sink_on_data(
+    ctx: *const c_void,
+    data: *const i16,
+    sample_rate: u32,
+    channels: c_ulong,
+    frames: c_ulong,
+) {
+    let sink = unsafe { &*(ctx as *const AudioSink) };
+    match sink.frame_tx.try_reserve() {
+        Ok(permit) => permit.send(AudioFrame {
+            sample_rate,
+            channels: channels as usize,
+            samples: unsafe { std::slice::from_raw_parts(data, (channels * frames) as usize) }
+                .to_vec(),
+        }),
+        Err(TrySendError::Full(())) => {
+            log::warn!("audio frame dropped, you are reading stream too slowly!")
+        }
+        Err(TrySendError::Closed(())) => {}
+    }
+}
@@ -1,19 +1,23 @@
 use std::{
-    ffi::{CStr, CString},
+    collections::{HashMap, VecDeque},
+    future::Future,
     pin::Pin,
     str::FromStr,
-    task::{Context, Poll},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll, Waker},
 };
 
-use futures::Stream;
+use futures::{Sink, Stream};
 use std::convert::TryInto;
 use thiserror::Error;
-use tokio::sync::{
-    broadcast::{self},
-    mpsc::{self, error::TrySendError},
-    watch,
-};
+use tokio::sync::Notify;
 
+use crate::broadcaster::{marshal_str, try_sys, unmarshal_str, BroadcasterError, WeakBroadcaster};
+use crate::producer::{Reproduce, WeakReproduce};
+use crate::stats::StatsReport;
 use crate::types::*;
 use vulcast_rtc_sys as sys;
 
@@ -21,6 +25,8 @@ use vulcast_rtc_sys as sys;
 pub enum DataChannelError {
     #[error("channel is closed")]
     ChannelClosed,
+    #[error("channel is not open yet")]
+    NotOpen,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -43,98 +49,551 @@ impl FromStr for DataChannelState {
     }
 }
 
-#[derive(Debug, Clone)]
-pub(crate) enum Message {
-    Data {
-        data_consumer_id: DataConsumerId,
-        data: Data,
-    },
-    DataConsumerStateChanged {
-        data_consumer_id: DataConsumerId,
+pub type Data = Vec<u8>;
+
+/// Dispatches the messages and state changes reported by native data channels
+/// to the data producer or consumer they belong to. Called from native threads,
+/// so it must not wait on anything held while calling native functions.
+#[derive(Default)]
+pub(crate) struct DataChannelRouter {
+    consumers: Mutex<HashMap<DataConsumerId, Arc<MessageQueue>>>,
+    producers: Mutex<HashMap<DataProducerId, Weak<DataProducerShared>>>,
+}
+impl DataChannelRouter {
+    pub(crate) fn on_data_consumer_message(&self, data_consumer_id: &DataConsumerId, data: Data) {
+        let queue = match self.consumers.lock().unwrap().get(data_consumer_id) {
+            Some(queue) => queue.clone(),
+            None => {
+                log::debug!("{:?}: data for unknown data consumer", data_consumer_id);
+                return;
+            }
+        };
+        log::trace!("{:?}: data (len={:?})", data_consumer_id, data.len());
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_data_message_received();
+        if !queue.push(data_consumer_id, data) {
+            // data consumer is dropped, or closed by the overflow policy
+            log::debug!("{:?}: stop - consumer closed", data_consumer_id);
+            self.remove_consumer(data_consumer_id);
+        }
+    }
+
+    pub(crate) fn on_data_consumer_state_changed(
+        &self,
+        data_consumer_id: &DataConsumerId,
         state: DataChannelState,
-    },
-    DataProducerStateChanged {
-        data_producer_id: DataProducerId,
+    ) {
+        log::debug!("{:?}: state_changed {:?}", data_consumer_id, state);
+        if state == DataChannelState::Closed {
+            // end the stream of the data consumer
+            log::info!("{:?}: stop - data channel closed", data_consumer_id);
+            self.remove_consumer(data_consumer_id);
+        }
+    }
+
+    pub(crate) fn on_data_producer_state_changed(
+        &self,
+        data_producer_id: &DataProducerId,
         state: DataChannelState,
-    },
+    ) {
+        if let Some(producer) = self.producer(data_producer_id) {
+            log::debug!("{:?}: state_changed {:?}", data_producer_id, state);
+            *producer.channel_state.lock().unwrap() = Some(state);
+            producer.changed.notify_waiters();
+        }
+    }
+
+    pub(crate) fn on_data_producer_buffered_amount_change(
+        &self,
+        data_producer_id: &DataProducerId,
+        buffered_amount: u64,
+    ) {
+        if let Some(producer) = self.producer(data_producer_id) {
+            producer
+                .buffered_amount
+                .store(buffered_amount, Ordering::SeqCst);
+            producer.changed.notify_waiters();
+        }
+    }
+
+    fn add_consumer(&self, data_consumer_id: DataConsumerId, queue: Arc<MessageQueue>) {
+        self.consumers
+            .lock()
+            .unwrap()
+            .insert(data_consumer_id, queue);
+    }
+
+    fn remove_consumer(&self, data_consumer_id: &DataConsumerId) {
+        let queue = self.consumers.lock().unwrap().remove(data_consumer_id);
+        if let Some(queue) = queue {
+            queue.close();
+        }
+    }
+
+    fn add_producer(&self, producer: &Arc<DataProducerShared>) {
+        let data_producer_id = producer.state.lock().unwrap().data_producer_id.clone();
+        let mut producers = self.producers.lock().unwrap();
+        producers.retain(|_, producer| producer.strong_count() > 0);
+        producers.insert(data_producer_id, Arc::downgrade(producer));
+    }
+
+    /// Route the messages of a data producer produced again by its new id.
+    fn rename_producer(&self, old_id: &DataProducerId, new_id: DataProducerId) {
+        let mut producers = self.producers.lock().unwrap();
+        if let Some(producer) = producers.remove(old_id) {
+            producers.insert(new_id, producer);
+        }
+    }
+
+    fn remove_producer(&self, data_producer_id: &DataProducerId) {
+        self.producers.lock().unwrap().remove(data_producer_id);
+    }
+
+    fn producer(&self, data_producer_id: &DataProducerId) -> Option<Arc<DataProducerShared>> {
+        let producers = self.producers.lock().unwrap();
+        producers.get(data_producer_id).and_then(Weak::upgrade)
+    }
 }
 
-pub type Data = Vec<u8>;
+/// Options of a data producer. By default messages are unordered, i.e. may be
+/// delivered out of order, but reliable, since neither retransmission limit is
+/// set. This is the same data channel `Broadcaster::produce_data` has always
+/// created: it passed 0 for both limits, which libmediasoupclient treats as
+/// unset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataProducerOptions {
+    /// Whether messages are delivered in the order they were sent.
+    pub ordered: bool,
+    /// Time in milliseconds during which a message is retransmitted, after
+    /// which it is dropped. Cannot be combined with `max_retransmits`, and
+    /// must not be 0.
+    pub max_packet_life_time: Option<u16>,
+    /// Number of times a message is retransmitted before it is dropped. Must
+    /// not be 0, since libmediasoupclient treats 0 as no limit.
+    pub max_retransmits: Option<u16>,
+    pub label: String,
+    /// Subprotocol of the messages, e.g. "json".
+    pub protocol: String,
+    /// Custom data passed to the signaller with the data producer.
+    pub app_data: serde_json::Value,
+    /// Number of bytes queued to be sent above which `send` waits for the
+    /// queue to drain.
+    pub buffered_amount_high_water_mark: u64,
+}
+impl Default for DataProducerOptions {
+    fn default() -> Self {
+        DataProducerOptions {
+            ordered: false,
+            max_packet_life_time: None,
+            max_retransmits: None,
+            label: String::new(),
+            protocol: String::new(),
+            app_data: serde_json::Value::Object(Default::default()),
+            buffered_amount_high_water_mark: 1024 * 1024,
+        }
+    }
+}
+impl DataProducerOptions {
+    fn validate(&self) -> Result<(), BroadcasterError> {
+        if self.label.contains('\0') || self.protocol.contains('\0') {
+            return Err(BroadcasterError::InvalidOptions(
+                "label and protocol must not contain NUL".to_owned(),
+            ));
+        }
+        match (self.max_packet_life_time, self.max_retransmits) {
+            (Some(_), Some(_)) => Err(BroadcasterError::InvalidOptions(
+                "max_packet_life_time and max_retransmits cannot be combined".to_owned(),
+            )),
+            (Some(0), None) => Err(BroadcasterError::InvalidOptions(
+                "max_packet_life_time must not be 0".to_owned(),
+            )),
+            (None, Some(0)) => Err(BroadcasterError::InvalidOptions(
+                "max_retransmits must not be 0".to_owned(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a data consumer does with messages received while its buffer is full.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the received message.
+    DropNewest,
+    /// Drop the oldest buffered message, so that the latest messages are
+    /// kept, e.g. for controller state.
+    DropOldest,
+    /// Keep buffering messages past the capacity until `max_buffered_bytes`
+    /// are buffered, without making the native thread receiving them wait.
+    /// The data consumer is then closed, ending its stream once the buffered
+    /// messages are read, instead of losing messages silently. WebRTC keeps
+    /// reading the data channel regardless, so the sender is not slowed down.
+    Backpressure { max_buffered_bytes: usize },
+}
+
+/// Options of a data consumer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumeDataOptions {
+    /// Number of messages buffered until the consumer reads them, which must
+    /// not be 0.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+impl Default for ConsumeDataOptions {
+    fn default() -> Self {
+        ConsumeDataOptions {
+            capacity: 32,
+            overflow: OverflowPolicy::DropNewest,
+        }
+    }
+}
+impl ConsumeDataOptions {
+    pub(crate) fn validate(&self) -> Result<(), BroadcasterError> {
+        if self.capacity == 0 {
+            return Err(BroadcasterError::InvalidOptions(
+                "capacity must not be 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Bounded queue of the messages received by a data consumer.
+struct MessageQueue {
+    options: ConsumeDataOptions,
+    state: Mutex<MessageQueueState>,
+    dropped: AtomicU64,
+}
+struct MessageQueueState {
+    messages: VecDeque<Data>,
+    // total length of the messages
+    buffered_bytes: usize,
+    closed: bool,
+    waker: Option<Waker>,
+}
+impl MessageQueue {
+    fn new(options: ConsumeDataOptions) -> Self {
+        MessageQueue {
+            state: Mutex::new(MessageQueueState {
+                messages: VecDeque::with_capacity(options.capacity),
+                buffered_bytes: 0,
+                closed: false,
+                waker: None,
+            }),
+            options,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Push a message according to the overflow policy, returning false if
+    /// the queue is closed or the policy closed it.
+    fn push(&self, data_consumer_id: &DataConsumerId, data: Data) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+        if state.messages.len() >= self.options.capacity {
+            match self.options.overflow {
+                OverflowPolicy::DropNewest => {
+                    self.record_dropped();
+                    log::warn!(
+                        "{:?}: message dropped, you are reading stream too slowly!",
+                        data_consumer_id
+                    );
+                    return true;
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = state.messages.pop_front() {
+                        state.buffered_bytes -= oldest.len();
+                    }
+                    self.record_dropped();
+                    log::debug!("{:?}: oldest message dropped", data_consumer_id);
+                }
+                OverflowPolicy::Backpressure { max_buffered_bytes } => {
+                    if state.buffered_bytes + data.len() > max_buffered_bytes {
+                        self.record_dropped();
+                        log::error!(
+                            "{:?}: closed, more than {} bytes buffered",
+                            data_consumer_id,
+                            max_buffered_bytes
+                        );
+                        state.closed = true;
+                        if let Some(waker) = state.waker.take() {
+                            waker.wake();
+                        }
+                        return false;
+                    }
+                }
+            }
+        }
+        state.buffered_bytes += data.len();
+        state.messages.push_back(data);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+
+    fn poll_pop(&self, cx: &mut Context<'_>) -> Poll<Option<Data>> {
+        let mut state = self.state.lock().unwrap();
+        match state.messages.pop_front() {
+            Some(data) => {
+                state.buffered_bytes -= data.len();
+                Poll::Ready(Some(data))
+            }
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// End the stream once the buffered messages are read.
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_data_message_dropped();
+    }
+}
+
+type SendFuture = Pin<Box<dyn Future<Output = Result<(), DataChannelError>> + Send>>;
 
 pub struct DataProducer {
+    shared: Arc<DataProducerShared>,
+    // message being sent through the `Sink` implementation
+    pending_send: Option<SendFuture>,
+}
+struct DataProducerShared {
+    state: Mutex<DataProducerState>,
+    // not part of the state, which is locked while calling native functions
+    // that may wait on the thread reporting state changes
+    channel_state: Mutex<Option<DataChannelState>>,
+    // reported by the data channel when queued messages are sent, and raised
+    // by send before queueing a message, since queueing is not reported
+    buffered_amount: AtomicU64,
+    // copied from the options, which are locked while sending
+    buffered_amount_high_water_mark: u64,
+    broadcaster: WeakBroadcaster,
+    router: Arc<DataChannelRouter>,
+    // notified when the buffered amount or channel state changes
+    changed: Notify,
+}
+unsafe impl Send for DataProducerShared {}
+unsafe impl Sync for DataProducerShared {}
+struct DataProducerState {
     sys_data_producer: *mut sys::mediasoupclient_DataProducer,
+    options: DataProducerOptions,
     data_producer_id: DataProducerId,
-    state: watch::Receiver<Option<DataChannelState>>,
+    stream_id: u16,
 }
-unsafe impl Send for DataProducer {}
-unsafe impl Sync for DataProducer {}
 impl DataProducer {
     pub(crate) fn new(
         sys_broadcaster: *mut sys::Broadcaster,
-        mut message_rx: broadcast::Receiver<Message>,
-    ) -> Self {
-        let sys_data_producer = unsafe { sys::data_producer_new(sys_broadcaster) };
-        let data_producer_id_marshal = unsafe { sys::data_producer_marshal_id(sys_data_producer) };
-        let data_producer_id = DataProducerId::from(unsafe {
-            CStr::from_ptr(data_producer_id_marshal)
-                .to_str()
-                .unwrap()
-                .to_owned()
+        broadcaster: WeakBroadcaster,
+        options: DataProducerOptions,
+        router: Arc<DataChannelRouter>,
+    ) -> Result<Self, BroadcasterError> {
+        let sys_data_producer = unsafe { data_producer_new(sys_broadcaster, &options)? };
+        let buffered_amount_high_water_mark = options.buffered_amount_high_water_mark;
+        let shared = Arc::new(DataProducerShared {
+            state: Mutex::new(DataProducerState {
+                sys_data_producer,
+                options,
+                data_producer_id: unsafe { marshal_data_producer_id(sys_data_producer) },
+                stream_id: unsafe { sys::data_producer_stream_id(sys_data_producer) },
+            }),
+            channel_state: Mutex::new(None),
+            buffered_amount: AtomicU64::new(0),
+            buffered_amount_high_water_mark,
+            broadcaster,
+            router: router.clone(),
+            changed: Notify::new(),
         });
-        unsafe { sys::cpp_unmarshal_str(data_producer_id_marshal) };
-        let (state_tx, state_rx) = watch::channel(None);
-        tokio::spawn({
-            let data_producer_id = data_producer_id.clone();
-            async move {
-                loop {
-                    tokio::select! {
-                        Ok(message) = message_rx.recv() => {
-                            match message {
-                                Message::DataProducerStateChanged {
-                                    data_producer_id: id,
-                                    state: channel_state,
-                                } if id == data_producer_id => {
-                                    log::debug!("{:?}: state_changed {:?}", &id, &channel_state);
-                                    if channel_state == DataChannelState::Closed {
-                                        let _ = state_tx.send(Some(channel_state));
-                                        return;
-                                    }
-                                }
-                                _ => (),
-                            }
-                        },
-                        _ = state_tx.closed() => {break},
-                        else => {break}
-                    }
-                }
+        router.add_producer(&shared);
+        Ok(Self {
+            shared,
+            pending_send: None,
+        })
+    }
+
+    /// Send a message, first waiting until the number of bytes queued to be
+    /// sent is at most the high-water mark of the data producer. Fails with
+    /// `DataChannelError::NotOpen` until the data channel is open, including
+    /// while it is produced again after the transports are recreated, since
+    /// messages sent meanwhile would be dropped.
+    pub async fn send(&self, data: Data) -> Result<(), DataChannelError> {
+        self.shared.clone().send(data).await
+    }
+
+    /// Number of bytes queued to be sent, as last reported by the data
+    /// channel plus the size of the messages sent since.
+    pub fn buffered_amount(&self) -> u64 {
+        self.shared.buffered_amount.load(Ordering::SeqCst)
+    }
+    /// Id of this data producer. The id changes if the data producer is
+    /// produced again after the broadcaster recovers its transports.
+    pub fn id(&self) -> DataProducerId {
+        self.shared.state.lock().unwrap().data_producer_id.clone()
+    }
+
+    pub fn label(&self) -> String {
+        self.shared.state.lock().unwrap().options.label.clone()
+    }
+
+    pub fn protocol(&self) -> String {
+        self.shared.state.lock().unwrap().options.protocol.clone()
+    }
+
+    /// Stats of the data channel and of the send transport.
+    pub async fn get_stats(&self) -> Result<StatsReport, BroadcasterError> {
+        let broadcaster = self
+            .shared
+            .broadcaster
+            .upgrade()
+            .ok_or(BroadcasterError::TransportClosed)?;
+        let stats = broadcaster.get_stats().await?;
+        let stream_id = self.shared.state.lock().unwrap().stream_id;
+        Ok(stats.send_transport.for_data_channel(stream_id))
+    }
+
+    pub(crate) fn downgrade_reproduce(&self) -> WeakReproduce {
+        Arc::<DataProducerShared>::downgrade(&self.shared)
+    }
+}
+impl DataProducerShared {
+    async fn send(self: Arc<Self>, data: Data) -> Result<(), DataChannelError> {
+        // reserved before sending, so that the amount reported once the
+        // message is sent is not overwritten, and concurrent sends wait
+        self.reserve_buffered_amount(data.len() as u64).await?;
+        // spawn on blocking thread
+        tokio::task::spawn_blocking(move || {
+            let state = self.state.lock().unwrap();
+            if let Err(e) = self.check_open() {
+                self.release_buffered_amount(data.len() as u64);
+                return Err(e);
             }
-        });
-        Self {
-            sys_data_producer,
-            data_producer_id,
-            state: state_rx,
+            unsafe {
+                sys::data_producer_send(
+                    state.sys_data_producer,
+                    data.as_ptr(),
+                    data.len().try_into().unwrap(),
+                )
+            }
+            Ok(())
+        })
+        .await
+        .unwrap()?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_data_message_sent();
+        Ok(())
+    }
+
+    /// Wait until the buffered amount is at most the high-water mark, then
+    /// add `len` to it.
+    async fn reserve_buffered_amount(&self, len: u64) -> Result<(), DataChannelError> {
+        loop {
+            // register before checking so that no change is missed
+            let changed = self.changed.notified();
+            self.check_open()?;
+            let reserved =
+                self.buffered_amount
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |amount| {
+                        if amount <= self.buffered_amount_high_water_mark {
+                            Some(amount + len)
+                        } else {
+                            None
+                        }
+                    });
+            if reserved.is_ok() {
+                return Ok(());
+            }
+            changed.await;
         }
     }
-    pub fn send(&mut self, data: Data) -> Result<(), DataChannelError> {
-        let state = self.state.borrow_and_update();
-        if let Some(DataChannelState::Closed) = *state {
-            return Err(DataChannelError::ChannelClosed);
+
+    fn release_buffered_amount(&self, len: u64) {
+        // may have been overwritten by a report since
+        let _ = self
+            .buffered_amount
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |amount| {
+                Some(amount.saturating_sub(len))
+            });
+        self.changed.notify_waiters();
+    }
+
+    fn check_open(&self) -> Result<(), DataChannelError> {
+        match *self.channel_state.lock().unwrap() {
+            Some(DataChannelState::Open) => Ok(()),
+            Some(DataChannelState::Closing) | Some(DataChannelState::Closed) => {
+                Err(DataChannelError::ChannelClosed)
+            }
+            Some(DataChannelState::Connecting) | None => Err(DataChannelError::NotOpen),
         }
-        // this could potentially freeze the executor
-        unsafe {
-            sys::data_producer_send(
-                self.sys_data_producer,
-                data.as_ptr(),
-                data.len().try_into().unwrap(),
-            )
+    }
+}
+/// Messages are sent in order, one at a time. Flushing waits until the last
+/// message is queued, not until it is sent.
+impl Sink<Data> for DataProducer {
+    type Error = DataChannelError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, data: Data) -> Result<(), Self::Error> {
+        let send = self.shared.clone().send(data);
+        self.pending_send = Some(Box::pin(send));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(pending_send) = &mut self.pending_send {
+            let result = futures::ready!(pending_send.as_mut().poll(cx));
+            self.pending_send = None;
+            result?;
         }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+impl Reproduce for DataProducerShared {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().unwrap();
+        let sys_data_producer = unsafe { data_producer_new(sys_broadcaster, &state.options)? };
+        let data_producer_id = unsafe { marshal_data_producer_id(sys_data_producer) };
+        // so that closing the old data producer does not close this one
+        self.router
+            .rename_producer(&state.data_producer_id, data_producer_id.clone());
+        log::trace!("data producer delete {:?}", &state.sys_data_producer);
+        unsafe { sys::data_producer_delete(state.sys_data_producer) };
+        state.sys_data_producer = sys_data_producer;
+        state.data_producer_id = data_producer_id;
+        state.stream_id = unsafe { sys::data_producer_stream_id(sys_data_producer) };
+        *self.channel_state.lock().unwrap() = None;
+        self.buffered_amount.store(0, Ordering::SeqCst);
+        self.changed.notify_waiters();
         Ok(())
     }
-    pub fn id(&self) -> &DataProducerId {
-        &self.data_producer_id
+}
+impl Drop for DataProducerShared {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        self.router.remove_producer(&state.data_producer_id);
     }
 }
-impl Drop for DataProducer {
+impl Drop for DataProducerState {
     fn drop(&mut self) {
         log::trace!("data producer delete {:?}", &self.sys_data_producer);
         unsafe {
@@ -143,106 +602,141 @@ impl Drop for DataProducer {
     }
 }
 
+unsafe fn data_producer_new(
+    sys_broadcaster: *mut sys::Broadcaster,
+    options: &DataProducerOptions,
+) -> Result<*mut sys::mediasoupclient_DataProducer, BroadcasterError> {
+    options.validate()?;
+    let label_cstr = marshal_str(options.label.clone())?;
+    let protocol_cstr = marshal_str(options.protocol.clone())?;
+    let app_data_cstr = marshal_str(options.app_data.to_string())?;
+    try_sys(|error| {
+        sys::data_producer_new(
+            sys_broadcaster,
+            options.ordered,
+            options.max_packet_life_time.unwrap_or(0),
+            options.max_retransmits.unwrap_or(0),
+            label_cstr.as_ptr(),
+            protocol_cstr.as_ptr(),
+            app_data_cstr.as_ptr(),
+            error,
+        )
+    })
+}
+
+unsafe fn marshal_data_producer_id(
+    sys_data_producer: *mut sys::mediasoupclient_DataProducer,
+) -> DataProducerId {
+    DataProducerId::from(unmarshal_str(sys::data_producer_marshal_id(
+        sys_data_producer,
+    )))
+}
+
 pub struct DataConsumer {
     sys_data_consumer: *mut sys::mediasoupclient_DataConsumer,
+    router: Arc<DataChannelRouter>,
     data_consumer_id: DataConsumerId,
-    data_rx: mpsc::Receiver<Data>,
+    label: String,
+    protocol: String,
+    stream_id: Option<u16>,
+    broadcaster: WeakBroadcaster,
+    queue: Arc<MessageQueue>,
 }
 unsafe impl Send for DataConsumer {}
 unsafe impl Sync for DataConsumer {}
 impl DataConsumer {
     pub(crate) fn new(
         sys_broadcaster: *mut sys::Broadcaster,
+        broadcaster: WeakBroadcaster,
         data_consumer_options: DataConsumerOptions,
-        mut message_rx: broadcast::Receiver<Message>,
-    ) -> Self {
+        options: ConsumeDataOptions,
+        router: Arc<DataChannelRouter>,
+    ) -> Result<Self, BroadcasterError> {
         let data_consumer_id = data_consumer_options.id;
+        let stream_id = data_consumer_options.sctp_stream_parameters["streamId"]
+            .as_u64()
+            .map(|stream_id| stream_id as u16);
 
-        let (tx, rx) = mpsc::channel(32);
-
-        tokio::spawn({
-            let data_consumer_id = data_consumer_id.clone();
-            async move {
-                loop {
-                    tokio::select! {
-                        message = message_rx.recv() => {
-                            match message {
-                                Ok(Message::Data {
-                                    data_consumer_id: id,
-                                    data,
-                                }) if id == data_consumer_id => {
-                                    log::trace!("{:?}: data (len={:?})", &id, data.len());
-                                    match tx.try_send(data) {
-                                        Err(TrySendError::Closed(_)) => {
-                                            // data consumer is dropped
-                                            log::debug!("{:?}: stop - consumer dropped, cannot send", &data_consumer_id);
-                                            return;
-                                        },
-                                        Err(TrySendError::Full(_)) => {
-                                            // send buffer full
-                                            log::warn!("{:?}: message dropped, you are reading stream too slowly!", &data_consumer_id)
-                                        },
-                                        _ => {}
-                                    }
-                                }
-                                Ok(Message::DataConsumerStateChanged {
-                                    data_consumer_id: id,
-                                    state: channel_state,
-                                }) if id == data_consumer_id => {
-                                    log::debug!("{:?}: state_changed {:?}", &id, &channel_state);
-                                    if channel_state == DataChannelState::Closed {
-                                        log::info!("{:?}: stop - data channel closed", &data_consumer_id);
-                                        return;
-                                    }
-                                }
-                                Ok(_) => {}
-                                Err(broadcast::error::RecvError::Closed) => {
-                                    log::debug!("{:?}: stop - broadcaster dropped", &data_consumer_id);
-                                    return;
-                                }
-                                Err(broadcast::error::RecvError::Lagged(x)) => {
-                                    log::error!("{:?}: data channel receiver can't keep up, missed {} messages", &data_consumer_id, x)
-                                }
-                            }
-                        },
-                        _ = tx.closed() => {
-                            log::debug!("{:?}: stop - consumer dropped", &data_consumer_id);
-                            break;
-                        },
-                    }
-                }
-            }
-        });
-
-        let data_consumer_id_cstr = CString::new(String::from(data_consumer_id.clone())).unwrap();
+        let data_consumer_id_cstr = marshal_str(String::from(data_consumer_id.clone()))?;
         let data_producer_id_cstr =
-            CString::new(String::from(data_consumer_options.data_producer_id)).unwrap();
-        let sctp_stream_parameters_cstr = CString::new(
-            serde_json::to_string(&data_consumer_options.sctp_stream_parameters).unwrap(),
-        )
-        .unwrap();
+            marshal_str(String::from(data_consumer_options.data_producer_id))?;
+        let sctp_stream_parameters_cstr = marshal_str(serde_json::to_string(
+            &data_consumer_options.sctp_stream_parameters,
+        )?)?;
+        let label_cstr = marshal_str(data_consumer_options.label.clone())?;
+        let protocol_cstr = marshal_str(data_consumer_options.protocol.clone())?;
+
+        let queue = Arc::new(MessageQueue::new(options));
+
+        // registered first so that no message is missed
+        router.add_consumer(data_consumer_id.clone(), queue.clone());
+
         let sys_data_consumer = unsafe {
-            sys::data_consumer_new(
-                sys_broadcaster,
-                data_consumer_id_cstr.as_ptr(),
-                data_producer_id_cstr.as_ptr(),
-                sctp_stream_parameters_cstr.as_ptr(),
-            )
+            try_sys(|error| {
+                sys::data_consumer_new(
+                    sys_broadcaster,
+                    data_consumer_id_cstr.as_ptr(),
+                    data_producer_id_cstr.as_ptr(),
+                    sctp_stream_parameters_cstr.as_ptr(),
+                    label_cstr.as_ptr(),
+                    protocol_cstr.as_ptr(),
+                    error,
+                )
+            })
+        };
+        let sys_data_consumer = match sys_data_consumer {
+            Ok(sys_data_consumer) => sys_data_consumer,
+            Err(e) => {
+                router.remove_consumer(&data_consumer_id);
+                return Err(e);
+            }
         };
         log::trace!("data consumer new {:?}", &sys_data_consumer);
-        Self {
+        Ok(Self {
             sys_data_consumer,
+            router,
             data_consumer_id,
-            data_rx: rx,
-        }
+            label: data_consumer_options.label,
+            protocol: data_consumer_options.protocol,
+            stream_id,
+            broadcaster,
+            queue,
+        })
     }
 
     pub fn id(&self) -> DataConsumerId {
         self.data_consumer_id.clone()
     }
+
+    /// Number of messages dropped because the buffer was full.
+    pub fn dropped_messages(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Label of the data producer this consumes from.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Subprotocol of the data producer this consumes from.
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Stats of the data channel and of the recv transport.
+    pub async fn get_stats(&self) -> Result<StatsReport, BroadcasterError> {
+        let broadcaster = self
+            .broadcaster
+            .upgrade()
+            .ok_or(BroadcasterError::TransportClosed)?;
+        let stats = broadcaster.get_stats().await?;
+        let stream_id = self.stream_id.ok_or(BroadcasterError::NoStreamId)?;
+        Ok(stats.recv_transport.for_data_channel(stream_id))
+    }
 }
 impl Drop for DataConsumer {
     fn drop(&mut self) {
+        self.router.remove_consumer(&self.data_consumer_id);
         log::trace!("data consumer delete {:?}", &self.sys_data_consumer);
         unsafe {
             sys::data_consumer_delete(self.sys_data_consumer);
@@ -252,7 +746,7 @@ impl Drop for DataConsumer {
 impl Stream for DataConsumer {
     type Item = Data;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.data_rx.poll_recv(cx)
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.queue.poll_pop(cx)
     }
 }
@@ -0,0 +1,41 @@
+use std::ffi::CStr;
+
+use serde::Deserialize;
+use vulcast_rtc_sys as sys;
+
+use crate::broadcaster::{try_sys, BroadcasterError};
+use crate::vcm_capturer::VideoType;
+
+/// A video capture device which can be passed to `VcmCapturer`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoDevice {
+    pub index: u32,
+    pub name: String,
+    /// Identifier of the device, e.g. its USB bus info.
+    pub unique_id: String,
+    pub capabilities: Vec<VideoCaptureCapability>,
+}
+
+/// A capture mode supported by a video capture device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoCaptureCapability {
+    pub width: u32,
+    pub height: u32,
+    pub max_fps: u32,
+    pub video_type: VideoType,
+    pub interlaced: bool,
+}
+
+/// List the video capture devices (e.g. webcams, capture cards) and the
+/// capture modes they support.
+pub fn list_video_devices() -> Result<Vec<VideoDevice>, BroadcasterError> {
+    crate::native_init();
+    unsafe {
+        let devices_ptr = try_sys(|error| sys::marshal_video_devices(error))?;
+        let devices = CStr::from_ptr(devices_ptr).to_string_lossy().into_owned();
+        sys::cpp_unmarshal_str(devices_ptr);
+        serde_json::from_str(&devices).map_err(|e| BroadcasterError::Native(e.to_string()))
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    ffi::c_void,
+    ops::Deref,
+    os::raw::c_ulong,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::json;
+use vulcast_rtc_sys as sys;
+
+use crate::audio_source::{AudioBuffer, AudioSource, SampleFormat};
+use crate::broadcaster::{try_sys, BroadcasterError, ProducerSignaller};
+use crate::producer::{
+    AudioProducerGuard, AudioProducerKind, Producer, ProducerHandle, ProducerOwner, Reproduce,
+    WeakReproduce,
+};
+
+/// Audio produced from an `AudioSource` with
+/// `Broadcaster::produce_audio_from_audio_source`. While it is alive, no
+/// `AlsaCapturer` can be produced by any broadcaster in the process.
+#[derive(Clone)]
+pub struct ForeignAudioProducer {
+    shared: Pin<Arc<Shared>>,
+    handle: ProducerHandle,
+}
+struct Shared {
+    // dropped first, so that the track is released before its source stops
+    // being pulled
+    producer: Producer,
+    source: SysAudioSource,
+    _audio_guard: AudioProducerGuard,
+}
+
+/// Native source pulling samples from an `AudioSource`.
+pub(crate) struct SysAudioSource {
+    sys_source: *mut sys::ForeignAudioSource,
+    // context of sys_source, which must outlive it
+    _ctx: Box<SourceContext>,
+}
+unsafe impl Send for SysAudioSource {}
+unsafe impl Sync for SysAudioSource {}
+struct SourceContext {
+    audio_source: Arc<dyn AudioSource>,
+    sample_format: SampleFormat,
+    // conversion buffer for float samples, only used from the source thread
+    float_buffer: Mutex<Vec<f32>>,
+}
+
+impl ForeignAudioProducer {
+    pub(crate) fn new(
+        sys_broadcaster: *mut sys::Broadcaster,
+        signaller: ProducerSignaller,
+        audio_source: Arc<dyn AudioSource>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<Self, BroadcasterError> {
+        let audio_guard = AudioProducerGuard::acquire(AudioProducerKind::Foreign)?;
+        let shared = Arc::pin(Shared {
+            producer: Producer::new(signaller),
+            _audio_guard: audio_guard,
+            source: SysAudioSource::new(audio_source, sample_rate, channels)?,
+        });
+        let sys_track = shared.source.track_new()?;
+        shared.producer.produce(
+            sys_broadcaster,
+            sys_track,
+            json!({ "opusStereo": channels > 1 }),
+            None,
+        )?;
+        let handle = ProducerHandle::new(shared.clone());
+        Ok(ForeignAudioProducer { shared, handle })
+    }
+
+    pub(crate) fn downgrade_reproduce(&self) -> WeakReproduce {
+        Arc::<Shared>::downgrade(&Pin::into_inner(self.shared.clone()))
+    }
+}
+
+impl Deref for ForeignAudioProducer {
+    type Target = ProducerHandle;
+    fn deref(&self) -> &ProducerHandle {
+        &self.handle
+    }
+}
+
+impl Reproduce for Shared {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        self.producer.reproduce(sys_broadcaster)
+    }
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        Some(&self.producer)
+    }
+}
+
+impl ProducerOwner for Shared {
+    fn producer(&self) -> &Producer {
+        &self.producer
+    }
+}
+
+impl SysAudioSource {
+    pub(crate) fn new(
+        audio_source: Arc<dyn AudioSource>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<Self, BroadcasterError> {
+        let ctx = Box::new(SourceContext {
+            sample_format: audio_source.sample_format(),
+            audio_source,
+            float_buffer: Mutex::new(Vec::new()),
+        });
+        let sys_source = unsafe {
+            try_sys(|error| {
+                sys::audio_source_new(
+                    sample_rate,
+                    channels as c_ulong,
+                    &*ctx as *const _ as *const c_void,
+                    Some(audio_source_next_samples),
+                    error,
+                )
+            })?
+        };
+        log::trace!("audio source new {:?}", sys_source);
+        Ok(SysAudioSource {
+            sys_source,
+            _ctx: ctx,
+        })
+    }
+
+    /// Create a track of the source, which holds a reference released with
+    /// `track_delete`.
+    pub(crate) fn track_new(
+        &self,
+    ) -> Result<*mut sys::webrtc_MediaStreamTrackInterface, BroadcasterError> {
+        unsafe { try_sys(|error| sys::track_new_from_audio_source(self.sys_source, error)) }
+    }
+}
+
+impl Drop for SysAudioSource {
+    fn drop(&mut self) {
+        log::trace!("audio source delete {:?}", &self.sys_source);
+        unsafe { sys::audio_source_delete(self.sys_source) };
+    }
+}
+
+extern "C" fn audio_source_next_samples(
+    ctx: *const c_void,
+    sample_rate: u32,
+    channels: c_ulong,
+    frames: c_ulong,
+    data: *mut i16,
+) {
+    let ctx = unsafe { &*(ctx as *const SourceContext) };
+    let channels = channels as usize;
+    let data = unsafe { std::slice::from_raw_parts_mut(data, channels * frames as usize) };
+    match ctx.sample_format {
+        SampleFormat::I16 => {
+            ctx.audio_source
+                .next_samples(sample_rate, channels, AudioBuffer::I16(data));
+        }
+        SampleFormat::F32 => {
+            let mut float_buffer = ctx.float_buffer.lock().unwrap();
+            float_buffer.resize(data.len(), 0.0);
+            ctx.audio_source.next_samples(
+                sample_rate,
+                channels,
+                AudioBuffer::F32(&mut float_buffer),
+            );
+            for (sample, &float_sample) in data.iter_mut().zip(float_buffer.iter()) {
+                *sample = (float_sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            }
+        }
+    }
+}
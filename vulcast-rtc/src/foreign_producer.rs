@@ -1,68 +1,112 @@
-use std::{
-    ffi::c_void,
-    pin::Pin,
-    ptr,
-    sync::{Arc, Mutex},
-};
+use std::{ffi::c_void, ops::Deref, pin::Pin, sync::Arc};
 
+use serde_json::json;
 use vulcast_rtc_sys as sys;
 
+use crate::broadcaster::{try_sys, BroadcasterError, ProducerSignaller};
 use crate::frame_source::FrameSource;
+use crate::producer::{Producer, ProducerHandle, ProducerOwner, Reproduce, WeakReproduce};
+use crate::video_options::VideoProducerOptions;
+use crate::video_source::VideoSource;
 
 #[derive(Clone)]
 pub struct ForeignProducer {
-    _shared: Pin<Arc<Shared>>,
+    shared: Pin<Arc<Shared>>,
+    handle: ProducerHandle,
 }
 struct Shared {
-    state: Mutex<State>,
+    // dropped first, so that the track stops polling the frame source before
+    // it is released
+    producer: Producer,
     frame_source: Arc<dyn FrameSource>,
 }
-unsafe impl Send for Shared {}
-unsafe impl Sync for Shared {}
-struct State {
-    sys_producer: *mut sys::mediasoupclient_Producer,
-}
 
 impl ForeignProducer {
-    pub fn new(
+    pub(crate) fn new(
         sys_broadcaster: *mut sys::Broadcaster,
+        signaller: ProducerSignaller,
         frame_source: Arc<dyn FrameSource>,
         width: u32,
         height: u32,
         fps: u32,
-    ) -> Self {
+        options: VideoProducerOptions,
+    ) -> Result<Self, BroadcasterError> {
         let shared = Arc::pin(Shared {
-            state: Mutex::new(State {
-                sys_producer: ptr::null_mut(),
-            }),
+            producer: Producer::new(signaller),
             frame_source,
         });
-        unsafe {
-            let ctx = &*shared as *const _ as *mut c_void;
-            let sys_producer = sys::producer_new_from_foreign(
-                sys_broadcaster,
-                width,
-                height,
-                fps,
-                ctx,
-                Some(frame_source_next_frame),
-            );
-            let mut state = shared.state.lock().unwrap();
-            state.sys_producer = sys_producer;
-        }
-        ForeignProducer { _shared: shared }
+        let sys_track =
+            unsafe { track_new_from_frame_source(&shared.frame_source, width, height, fps)? };
+        shared
+            .producer
+            .produce(sys_broadcaster, sys_track, json!({}), Some(&options))?;
+        let handle = ProducerHandle::new(shared.clone());
+        Ok(ForeignProducer { shared, handle })
+    }
+
+    /// Send the given source instead of the frame source, keeping the same
+    /// producer.
+    pub async fn replace_source(&self, source: VideoSource) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || {
+                let (sys_track, track_context) = source.track_new()?;
+                shared.producer.replace_track(sys_track, track_context)
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    pub(crate) fn downgrade_reproduce(&self) -> WeakReproduce {
+        Arc::<Shared>::downgrade(&Pin::into_inner(self.shared.clone()))
     }
 }
 
-impl Drop for State {
-    fn drop(&mut self) {
-        log::trace!("producer delete {:?}", &self.sys_producer);
-        unsafe {
-            sys::producer_delete(self.sys_producer);
-        }
+impl Deref for ForeignProducer {
+    type Target = ProducerHandle;
+    fn deref(&self) -> &ProducerHandle {
+        &self.handle
+    }
+}
+
+impl Reproduce for Shared {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        self.producer.reproduce(sys_broadcaster)
+    }
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        Some(&self.producer)
     }
 }
 
+impl ProducerOwner for Shared {
+    fn producer(&self) -> &Producer {
+        &self.producer
+    }
+}
+
+/// Create a track polling the given frame source, which must outlive it.
+pub(crate) unsafe fn track_new_from_frame_source(
+    frame_source: &Arc<dyn FrameSource>,
+    width: u32,
+    height: u32,
+    fps: u32,
+) -> Result<*mut sys::webrtc_MediaStreamTrackInterface, BroadcasterError> {
+    let ctx = frame_source as *const Arc<dyn FrameSource> as *mut c_void;
+    try_sys(|error| {
+        sys::track_new_from_foreign(
+            width,
+            height,
+            fps,
+            ctx,
+            Some(frame_source_next_frame),
+            error,
+        )
+    })
+}
+
 extern "C" fn frame_source_next_frame(
     ctx: *const c_void,
     width: u32,
@@ -71,12 +115,13 @@ extern "C" fn frame_source_next_frame(
     data: *mut u8,
 ) {
     unsafe {
-        let shared = &*(ctx as *const Shared);
-        shared.frame_source.next_frame(
-            width as u32,
-            height as u32,
-            timestamp,
-            std::slice::from_raw_parts_mut(data, (width * height * 4) as usize),
-        );
+        let frame_source = &*(ctx as *const Arc<dyn FrameSource>);
+        let data = std::slice::from_raw_parts_mut(data, (width * height * 4) as usize);
+        #[cfg(feature = "metrics")]
+        crate::metrics::time_next_frame(|| {
+            frame_source.next_frame(width as u32, height as u32, timestamp, data)
+        });
+        #[cfg(not(feature = "metrics"))]
+        frame_source.next_frame(width as u32, height as u32, timestamp, data);
     }
 }
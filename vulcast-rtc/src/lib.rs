@@ -1,10 +1,21 @@
 pub mod alsa_capturer;
+pub mod audio_source;
 pub mod broadcaster;
+pub mod consumer;
 pub mod data_channel;
+pub mod devices;
+pub mod foreign_audio_producer;
 pub mod foreign_producer;
 pub mod frame_source;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod plain_broadcaster;
+pub mod producer;
+pub mod stats;
 pub mod types;
 pub mod vcm_capturer;
+pub mod video_options;
+pub mod video_source;
 
 use std::{ffi::CString, sync::Once};
 
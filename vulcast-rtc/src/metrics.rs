@@ -0,0 +1,313 @@
+//! Prometheus metrics of the broadcasters in this process, enabled by the
+//! `metrics` feature. Metrics are recorded in the default registry and served
+//! in the text exposition format by `serve`. Metrics of a broadcaster are
+//! labelled with `Broadcaster::metrics_label`, and removed once it is dropped.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_gauge_vec, Encoder, GaugeVec, Histogram, HistogramVec, IntCounter, IntGaugeVec,
+    TextEncoder,
+};
+
+use crate::broadcaster::{
+    Broadcaster, TransportConnectionState, TransportDirection, MIN_STATS_INTERVAL,
+};
+use crate::stats::StatsReport;
+use crate::types::{MediaKind, ProducerId};
+
+lazy_static! {
+    static ref TRANSPORT_CONNECTION_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "vulcast_transport_connection_state",
+        "1 for the current connection state of each transport direction, 0 otherwise.",
+        &["broadcaster", "direction", "state"]
+    )
+    .unwrap();
+    static ref AVAILABLE_OUTGOING_BITRATE: GaugeVec = register_gauge_vec!(
+        "vulcast_available_outgoing_bitrate_bps",
+        "Estimated bandwidth available on the send transport, in bits per second.",
+        &["broadcaster"]
+    )
+    .unwrap();
+    static ref PRODUCER_TARGET_BITRATE: GaugeVec = register_gauge_vec!(
+        "vulcast_producer_target_bitrate_bps",
+        "Bitrate the encoders of a producer are targeting, in bits per second.",
+        &["broadcaster", "producer_id", "kind"]
+    )
+    .unwrap();
+    static ref PRODUCER_BYTES_SENT: GaugeVec = register_gauge_vec!(
+        "vulcast_producer_bytes_sent",
+        "Bytes sent by a producer since it was produced.",
+        &["broadcaster", "producer_id", "kind"]
+    )
+    .unwrap();
+    static ref PRODUCER_FRAMES_ENCODED: GaugeVec = register_gauge_vec!(
+        "vulcast_producer_frames_encoded",
+        "Frames encoded by a video producer since it was produced.",
+        &["broadcaster", "producer_id"]
+    )
+    .unwrap();
+    static ref PRODUCER_FRAMES_DROPPED: GaugeVec = register_gauge_vec!(
+        "vulcast_producer_frames_dropped",
+        "Frames captured but not encoded by a video producer since it was produced.",
+        &["broadcaster", "producer_id"]
+    )
+    .unwrap();
+    static ref NEXT_FRAME_DURATION: Histogram = register_histogram!(
+        "vulcast_frame_source_next_frame_seconds",
+        "Duration of FrameSource::next_frame calls.",
+        vec![0.0005, 0.001, 0.002, 0.004, 0.008, 0.016, 0.033, 0.066, 0.1]
+    )
+    .unwrap();
+    static ref DATA_MESSAGES_SENT: IntCounter = register_int_counter!(
+        "vulcast_data_messages_sent_total",
+        "Messages sent by data producers."
+    )
+    .unwrap();
+    static ref DATA_MESSAGES_RECEIVED: IntCounter = register_int_counter!(
+        "vulcast_data_messages_received_total",
+        "Messages received by data consumers."
+    )
+    .unwrap();
+    static ref DATA_MESSAGES_DROPPED: IntCounter = register_int_counter!(
+        "vulcast_data_messages_dropped_total",
+        "Messages dropped because a data consumer was not read quickly enough."
+    )
+    .unwrap();
+    static ref SIGNALLING_DURATION: HistogramVec = register_histogram_vec!(
+        "vulcast_signalling_duration_seconds",
+        "Duration of signaller calls, by signalling step and outcome.",
+        &["step", "result"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .unwrap();
+}
+
+static NEXT_BROADCASTER_LABEL: AtomicU64 = AtomicU64::new(0);
+
+const TRANSPORT_CONNECTION_STATES: &[(TransportConnectionState, &str)] = &[
+    (TransportConnectionState::Closed, "closed"),
+    (TransportConnectionState::Failed, "failed"),
+    (TransportConnectionState::Disconnected, "disconnected"),
+    (TransportConnectionState::New, "new"),
+    (TransportConnectionState::Connecting, "connecting"),
+    (TransportConnectionState::Connected, "connected"),
+    (TransportConnectionState::Checking, "checking"),
+    (TransportConnectionState::Completed, "completed"),
+];
+
+/// Producer id and kind label values recorded for the producers of a
+/// broadcaster.
+type RecordedProducers = HashSet<(String, &'static str)>;
+
+/// Serve the metrics at `/metrics` on the given address until the server
+/// fails.
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+    log::info!("serving metrics on http://{}/metrics", addr);
+    Server::try_bind(&addr)?.serve(make_service).await
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        log::error!("failed to encode metrics: {}", e);
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return Ok(response);
+    }
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+/// Update the bitrate and per-producer metrics from the stats of the given
+/// broadcaster every `interval`, until it is dropped. Metrics of producers
+/// which are closed are removed. Intervals shorter than `MIN_STATS_INTERVAL`
+/// are raised to it.
+pub fn monitor(broadcaster: &Broadcaster, interval: Duration) {
+    let label = broadcaster.metrics_label().to_owned();
+    let broadcaster = broadcaster.downgrade();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval.max(MIN_STATS_INTERVAL));
+        let mut recorded = RecordedProducers::new();
+        loop {
+            interval.tick().await;
+            let broadcaster = match broadcaster.upgrade() {
+                Some(broadcaster) => broadcaster,
+                None => {
+                    for (producer_id, kind) in &recorded {
+                        remove_producer(&label, producer_id, kind);
+                    }
+                    return;
+                }
+            };
+            match broadcaster.get_stats().await {
+                Ok(stats) => record_send_transport(&label, &stats.send_transport),
+                Err(e) => log::warn!("failed to get stats for metrics: {}", e),
+            }
+            match broadcaster.producer_stats().await {
+                Ok(stats) => record_producers(&label, &stats, &mut recorded),
+                Err(e) => log::warn!("failed to get producer stats for metrics: {}", e),
+            }
+        }
+    });
+}
+
+fn record_send_transport(broadcaster: &str, stats: &StatsReport) {
+    let selected: Vec<_> = stats
+        .transports
+        .iter()
+        .filter_map(|transport| transport.selected_candidate_pair_id.as_ref())
+        .collect();
+    let bitrate = stats
+        .candidate_pairs
+        .iter()
+        .filter(|pair| selected.contains(&&pair.id))
+        .filter_map(|pair| pair.available_outgoing_bitrate)
+        .next();
+    if let Some(bitrate) = bitrate {
+        AVAILABLE_OUTGOING_BITRATE
+            .with_label_values(&[broadcaster])
+            .set(bitrate);
+    }
+}
+
+/// Record the stats of the live producers of a broadcaster, and remove the
+/// metrics of those recorded before which are closed.
+fn record_producers(
+    broadcaster: &str,
+    stats: &[(ProducerId, MediaKind, StatsReport)],
+    recorded: &mut RecordedProducers,
+) {
+    let mut live = RecordedProducers::new();
+    for (producer_id, kind, stats) in stats {
+        let producer_id = String::from(producer_id.clone());
+        let kind_label = match kind {
+            MediaKind::Audio => "audio",
+            MediaKind::Video => "video",
+        };
+        let target_bitrate: f64 = stats
+            .outbound_rtp
+            .iter()
+            .filter_map(|rtp| rtp.target_bitrate)
+            .sum();
+        let bytes_sent: u64 = stats
+            .outbound_rtp
+            .iter()
+            .filter_map(|rtp| rtp.bytes_sent)
+            .sum();
+        PRODUCER_TARGET_BITRATE
+            .with_label_values(&[broadcaster, &producer_id, kind_label])
+            .set(target_bitrate);
+        PRODUCER_BYTES_SENT
+            .with_label_values(&[broadcaster, &producer_id, kind_label])
+            .set(bytes_sent as f64);
+        if let MediaKind::Video = kind {
+            let frames_encoded: u32 = stats
+                .outbound_rtp
+                .iter()
+                .filter_map(|rtp| rtp.frames_encoded)
+                .sum();
+            PRODUCER_FRAMES_ENCODED
+                .with_label_values(&[broadcaster, &producer_id])
+                .set(frames_encoded as f64);
+            // with simulcast, every encoding encodes each captured frame
+            let encodings = stats.outbound_rtp.len().max(1) as u32;
+            let frames: u32 = stats
+                .media_sources
+                .iter()
+                .filter_map(|source| source.frames)
+                .sum();
+            PRODUCER_FRAMES_DROPPED
+                .with_label_values(&[broadcaster, &producer_id])
+                .set(frames.saturating_sub(frames_encoded / encodings) as f64);
+        }
+        live.insert((producer_id, kind_label));
+    }
+    for (producer_id, kind) in recorded.difference(&live) {
+        remove_producer(broadcaster, producer_id, kind);
+    }
+    *recorded = live;
+}
+
+fn remove_producer(broadcaster: &str, producer_id: &str, kind: &str) {
+    let _ = PRODUCER_TARGET_BITRATE.remove_label_values(&[broadcaster, producer_id, kind]);
+    let _ = PRODUCER_BYTES_SENT.remove_label_values(&[broadcaster, producer_id, kind]);
+    let _ = PRODUCER_FRAMES_ENCODED.remove_label_values(&[broadcaster, producer_id]);
+    let _ = PRODUCER_FRAMES_DROPPED.remove_label_values(&[broadcaster, producer_id]);
+}
+
+/// Label identifying a new broadcaster in the metrics of this process.
+pub(crate) fn next_broadcaster_label() -> String {
+    NEXT_BROADCASTER_LABEL
+        .fetch_add(1, Ordering::Relaxed)
+        .to_string()
+}
+
+/// Remove the transport metrics of a dropped broadcaster.
+pub(crate) fn remove_broadcaster(broadcaster: &str) {
+    let _ = AVAILABLE_OUTGOING_BITRATE.remove_label_values(&[broadcaster]);
+    for direction in &["send", "recv"] {
+        for (_, name) in TRANSPORT_CONNECTION_STATES {
+            let _ = TRANSPORT_CONNECTION_STATE.remove_label_values(&[broadcaster, direction, name]);
+        }
+    }
+}
+
+pub(crate) fn record_transport_connection_state(
+    broadcaster: &str,
+    direction: TransportDirection,
+    state: TransportConnectionState,
+) {
+    let direction = match direction {
+        TransportDirection::Send => "send",
+        TransportDirection::Recv => "recv",
+    };
+    for (other, name) in TRANSPORT_CONNECTION_STATES {
+        TRANSPORT_CONNECTION_STATE
+            .with_label_values(&[broadcaster, direction, name])
+            .set((*other == state) as i64);
+    }
+}
+
+/// Time a `FrameSource::next_frame` call.
+pub(crate) fn time_next_frame<T>(f: impl FnOnce() -> T) -> T {
+    let _timer = NEXT_FRAME_DURATION.start_timer();
+    f()
+}
+
+pub(crate) fn record_signalling(step: &str, duration: Duration, succeeded: bool) {
+    let result = if succeeded { "ok" } else { "error" };
+    SIGNALLING_DURATION
+        .with_label_values(&[step, result])
+        .observe(duration.as_secs_f64());
+}
+
+pub(crate) fn record_data_message_sent() {
+    DATA_MESSAGES_SENT.inc();
+}
+
+pub(crate) fn record_data_message_received() {
+    DATA_MESSAGES_RECEIVED.inc();
+}
+
+pub(crate) fn record_data_message_dropped() {
+    DATA_MESSAGES_DROPPED.inc();
+}
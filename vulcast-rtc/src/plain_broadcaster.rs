@@ -0,0 +1,428 @@
+//! Broadcasting over a mediasoup plain transport, for servers or networks
+//! where WebRTC transports cannot be used. Tracks are encoded with VP8 or Opus
+//! and sent as plain RTP from a single UDP socket, SRTP protected if the
+//! server transport enables it. The server transport must have comedia
+//! enabled, since the client port is only known once it starts sending.
+//!
+//! There is no congestion control and lost packets are not retransmitted, so
+//! video is sent at a fixed bitrate which must fit the network. Audio captured
+//! with `AlsaCapturer` cannot be sent, since it only reaches WebRTC transports.
+
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use vulcast_rtc_sys as sys;
+
+use crate::audio_source::AudioSource;
+use crate::broadcaster::{
+    marshal_str, signal, try_sys, unmarshal_str, BroadcasterError, Signaller,
+};
+use crate::foreign_audio_producer::SysAudioSource;
+use crate::producer::TrackContext;
+use crate::types::*;
+use crate::video_source::VideoSource;
+
+#[derive(Debug, Clone)]
+pub struct PlainBroadcasterOptions {
+    /// Maximum time to wait for any single signaller call, or None to wait
+    /// indefinitely.
+    pub signalling_timeout: Option<Duration>,
+}
+impl Default for PlainBroadcasterOptions {
+    fn default() -> Self {
+        Self {
+            signalling_timeout: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// Sends producers on a single plain transport created with
+/// `Signaller::create_plain_transport`. The transport is closed once the
+/// broadcaster and all of its producers are dropped.
+///
+/// Only VP8 video and Opus audio can be sent, see `produce_video` for the
+/// limits of video.
+#[derive(Clone)]
+pub struct PlainBroadcaster {
+    shared: Arc<Shared>,
+}
+struct Shared {
+    sys_transport: *mut sys::PlainTransport,
+    transport_id: TransportId,
+    signaller: Arc<dyn Signaller>,
+    options: PlainBroadcasterOptions,
+}
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl PlainBroadcaster {
+    /// Create a new plain broadcaster with the given signalling handlers.
+    pub async fn new(signaller: Arc<dyn Signaller>) -> Result<Self, BroadcasterError> {
+        Self::new_with_options(signaller, PlainBroadcasterOptions::default()).await
+    }
+
+    /// Create a new plain broadcaster with the given signalling handlers and
+    /// options.
+    pub async fn new_with_options(
+        signaller: Arc<dyn Signaller>,
+        options: PlainBroadcasterOptions,
+    ) -> Result<Self, BroadcasterError> {
+        super::native_init();
+
+        let timeout = options.signalling_timeout;
+        let router_rtp_capabilities = signal(
+            "server_rtp_capabilities",
+            timeout,
+            signaller.server_rtp_capabilities(),
+        )
+        .await?;
+        let transport_options = signal(
+            "create_plain_transport",
+            timeout,
+            signaller.create_plain_transport(),
+        )
+        .await?;
+        // spawn on blocking thread
+        let (shared, srtp_parameters) = tokio::task::spawn_blocking({
+            let signaller = signaller.clone();
+            move || {
+                let options_cstr = marshal_str(serde_json::to_string(&transport_options)?)?;
+                let router_rtp_capabilities_cstr =
+                    marshal_str(serde_json::to_string(&router_rtp_capabilities)?)?;
+                let sys_transport = unsafe {
+                    try_sys(|error| {
+                        sys::plain_transport_new(
+                            options_cstr.as_ptr(),
+                            router_rtp_capabilities_cstr.as_ptr(),
+                            error,
+                        )
+                    })?
+                };
+                log::trace!("plain transport new {:?}", sys_transport);
+                let shared = Arc::new(Shared {
+                    sys_transport,
+                    transport_id: unsafe {
+                        unmarshal_str(sys::plain_transport_marshal_id(sys_transport))
+                    }
+                    .into(),
+                    signaller,
+                    options,
+                });
+                let srtp_parameters =
+                    unsafe { sys::plain_transport_marshal_srtp_parameters(sys_transport) };
+                let srtp_parameters: Option<SrtpParameters> = if srtp_parameters.is_null() {
+                    None
+                } else {
+                    Some(serde_json::from_str(&unsafe {
+                        unmarshal_str(srtp_parameters)
+                    })?)
+                };
+                Ok::<_, BroadcasterError>((shared, srtp_parameters))
+            }
+        })
+        .await
+        .unwrap()?;
+        if let Some(srtp_parameters) = srtp_parameters {
+            signal(
+                "connect_plain_transport",
+                timeout,
+                signaller.connect_plain_transport(shared.transport_id.clone(), srtp_parameters),
+            )
+            .await?;
+        }
+        Ok(PlainBroadcaster { shared })
+    }
+
+    /// Id of the server transport returned by
+    /// `Signaller::create_plain_transport`.
+    pub fn transport_id(&self) -> TransportId {
+        self.shared.transport_id.clone()
+    }
+
+    /// Produce video from the given source, encoded with VP8 at `max_bitrate`
+    /// bits per second. Key frames are sent when the server requests them.
+    ///
+    /// VP8 is the only video codec supported, and fails with
+    /// `BroadcasterError::CodecNotSupported` if the router does not have it.
+    /// The bitrate is fixed, since there is no congestion control, and lost
+    /// packets are not retransmitted since NACK is not supported.
+    pub async fn produce_video(
+        &self,
+        source: VideoSource,
+        max_bitrate: u32,
+    ) -> Result<PlainProducer, BroadcasterError> {
+        let max_framerate = source.fps();
+        self.produce(MediaKind::Video, max_bitrate, max_framerate, move |state| {
+            let (sys_track, track_context) = source.track_new()?;
+            state.sys_track = sys_track;
+            state.track_context = track_context;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Produce audio from a programatically generated source, encoded with
+    /// Opus. The source is polled from a dedicated thread for 10ms of audio at
+    /// a time, at the given sample rate and channel count. The sample rate
+    /// must be a multiple of 100.
+    pub async fn produce_audio_from_audio_source(
+        &self,
+        audio_source: Arc<dyn AudioSource>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<PlainProducer, BroadcasterError> {
+        self.produce(MediaKind::Audio, 0, 0, move |state| {
+            let source = SysAudioSource::new(audio_source, sample_rate, channels)?;
+            state.sys_track = source.track_new()?;
+            state.audio_source = Some(source);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn produce(
+        &self,
+        kind: MediaKind,
+        max_bitrate: u32,
+        max_framerate: u32,
+        track_new: impl FnOnce(&mut ProducerState) -> Result<(), BroadcasterError> + Send + 'static,
+    ) -> Result<PlainProducer, BroadcasterError> {
+        // spawn on blocking thread
+        let (state, rtp_parameters) = tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || {
+                let mut state = ProducerState::new();
+                track_new(&mut state)?;
+                state.sys_producer = unsafe {
+                    try_sys(|error| {
+                        sys::plain_producer_new(
+                            shared.sys_transport,
+                            state.sys_track,
+                            max_bitrate,
+                            max_framerate,
+                            error,
+                        )
+                    })?
+                };
+                log::trace!("plain producer new {:?}", state.sys_producer);
+                let rtp_parameters: RtpParameters = serde_json::from_str(&unsafe {
+                    unmarshal_str(sys::plain_producer_marshal_rtp_parameters(
+                        state.sys_producer,
+                    ))
+                })?;
+                Ok::<_, BroadcasterError>((state, rtp_parameters))
+            }
+        })
+        .await
+        .unwrap()?;
+        let producer_id = signal(
+            "on_produce_plain",
+            self.shared.options.signalling_timeout,
+            self.shared.signaller.on_produce_plain(
+                self.shared.transport_id.clone(),
+                kind.clone(),
+                rtp_parameters.clone(),
+            ),
+        )
+        .await?;
+        // created paused, so that nothing is sent before the server knows the
+        // producer
+        unsafe { sys::plain_producer_resume(state.sys_producer) };
+        Ok(PlainProducer {
+            shared: Arc::new(ProducerShared {
+                state: Mutex::new(state),
+                producer_id,
+                kind,
+                rtp_parameters,
+                paused: AtomicBool::new(false),
+                broadcaster: self.clone(),
+            }),
+        })
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        log::trace!("plain transport delete {:?}", self.sys_transport);
+        unsafe { sys::plain_transport_delete(self.sys_transport) };
+    }
+}
+
+/// A track sent on a `PlainBroadcaster`. The producer is closed when the last
+/// clone is dropped, notifying the signaller without waiting for it.
+#[derive(Clone)]
+pub struct PlainProducer {
+    shared: Arc<ProducerShared>,
+}
+struct ProducerShared {
+    // dropped first, so that the producer is deleted before its transport
+    state: Mutex<ProducerState>,
+    producer_id: ProducerId,
+    kind: MediaKind,
+    rtp_parameters: RtpParameters,
+    paused: AtomicBool,
+    broadcaster: PlainBroadcaster,
+}
+
+struct ProducerState {
+    sys_producer: *mut sys::PlainProducer,
+    sys_track: *mut sys::webrtc_MediaStreamTrackInterface,
+    // context the track refers to, which must outlive it
+    track_context: Option<TrackContext>,
+    audio_source: Option<SysAudioSource>,
+    closed: bool,
+}
+unsafe impl Send for ProducerState {}
+
+impl PlainProducer {
+    /// Id of the producer returned by `Signaller::on_produce_plain`.
+    pub fn id(&self) -> ProducerId {
+        self.shared.producer_id.clone()
+    }
+
+    pub fn kind(&self) -> MediaKind {
+        self.shared.kind.clone()
+    }
+
+    /// RTP parameters the producer was produced with.
+    pub fn rtp_parameters(&self) -> RtpParameters {
+        self.shared.rtp_parameters.clone()
+    }
+
+    /// Stop sending until resumed, and notify the signaller so that the
+    /// server pauses the producer too.
+    pub async fn pause(&self) -> Result<(), BroadcasterError> {
+        self.set_paused(true).await
+    }
+
+    pub async fn resume(&self) -> Result<(), BroadcasterError> {
+        self.set_paused(false).await
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::SeqCst)
+    }
+
+    async fn set_paused(&self, paused: bool) -> Result<(), BroadcasterError> {
+        {
+            let state = self.shared.state.lock().unwrap();
+            if state.closed {
+                return Err(BroadcasterError::ProducerClosed);
+            }
+            if self.shared.paused.swap(paused, Ordering::SeqCst) == paused {
+                return Ok(());
+            }
+            // only sets a flag checked by the encoding thread
+            unsafe {
+                if paused {
+                    sys::plain_producer_pause(state.sys_producer);
+                } else {
+                    sys::plain_producer_resume(state.sys_producer);
+                }
+            }
+        }
+        let broadcaster = &self.shared.broadcaster.shared;
+        let producer_id = self.id();
+        if paused {
+            signal(
+                "on_producer_pause",
+                broadcaster.options.signalling_timeout,
+                broadcaster.signaller.on_producer_pause(producer_id),
+            )
+            .await
+        } else {
+            signal(
+                "on_producer_resume",
+                broadcaster.options.signalling_timeout,
+                broadcaster.signaller.on_producer_resume(producer_id),
+            )
+            .await
+        }
+    }
+
+    /// Close the producer, waiting for the signaller to be notified.
+    /// Other methods fail with `BroadcasterError::ProducerClosed` afterwards.
+    pub async fn close(&self) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        let closed = tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || shared.state.lock().unwrap().close()
+        })
+        .await
+        .unwrap();
+        if !closed {
+            return Ok(());
+        }
+        let broadcaster = &self.shared.broadcaster.shared;
+        signal(
+            "on_producer_close",
+            broadcaster.options.signalling_timeout,
+            broadcaster.signaller.on_producer_close(self.id()),
+        )
+        .await
+    }
+}
+
+impl Drop for ProducerShared {
+    fn drop(&mut self) {
+        if !self.state.get_mut().unwrap().close() {
+            return;
+        }
+        // notify the signaller without waiting for it
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let signaller = self.broadcaster.shared.signaller.clone();
+            let timeout = self.broadcaster.shared.options.signalling_timeout;
+            let producer_id = self.producer_id.clone();
+            handle.spawn(async move {
+                let _ = signal(
+                    "on_producer_close",
+                    timeout,
+                    signaller.on_producer_close(producer_id),
+                )
+                .await;
+            });
+        }
+    }
+}
+
+impl ProducerState {
+    fn new() -> Self {
+        ProducerState {
+            sys_producer: ptr::null_mut(),
+            sys_track: ptr::null_mut(),
+            track_context: None,
+            audio_source: None,
+            closed: false,
+        }
+    }
+
+    /// Delete the producer, then its track and source. Returns whether it was
+    /// still open.
+    fn close(&mut self) -> bool {
+        if self.closed {
+            return false;
+        }
+        self.closed = true;
+        if !self.sys_producer.is_null() {
+            log::trace!("plain producer delete {:?}", &self.sys_producer);
+            unsafe { sys::plain_producer_delete(self.sys_producer) };
+            self.sys_producer = ptr::null_mut();
+        }
+        if !self.sys_track.is_null() {
+            log::trace!("track delete {:?}", &self.sys_track);
+            unsafe { sys::track_delete(self.sys_track) };
+            self.sys_track = ptr::null_mut();
+        }
+        self.track_context = None;
+        self.audio_source = None;
+        true
+    }
+}
+
+impl Drop for ProducerState {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
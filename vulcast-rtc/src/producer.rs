@@ -0,0 +1,588 @@
+use std::{
+    any::Any,
+    ffi::CString,
+    mem,
+    pin::Pin,
+    ptr,
+    sync::atomic::{AtomicIsize, Ordering},
+    sync::{Arc, Mutex, Weak},
+};
+
+use vulcast_rtc_sys as sys;
+
+use crate::broadcaster::{try_sys, unmarshal_str, BroadcasterError, ProducerSignaller};
+use crate::stats::StatsReport;
+use crate::types::{MediaKind, ProducerId, RtpParameters};
+use crate::video_options::{EncodingParameters, VideoProducerOptions};
+
+/// Implemented by the shared state of producer handles, so that the
+/// broadcaster can produce them again after recreating its send transport.
+pub(crate) trait Reproduce: Send + Sync {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError>;
+    /// The media producer of the handle, or `None` for data producers.
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        None
+    }
+}
+
+/// Weak reference to a producer handle kept by the broadcaster.
+pub(crate) type WeakReproduce = Weak<dyn Reproduce>;
+
+/// Implemented by the shared state of media producer handles, which own a
+/// `Producer` together with whatever its track refers to.
+pub(crate) trait ProducerOwner: Reproduce {
+    fn producer(&self) -> &Producer;
+    /// Release whatever refers to the track before the producer is closed.
+    /// Called on a blocking thread.
+    fn release(&self) {}
+}
+
+/// A media producer sent on the send transport of a broadcaster. Producer
+/// handles such as `VcmCapturer` or `ForeignAudioProducer` dereference to it.
+///
+/// The producer is closed once every clone of its handle is dropped, or
+/// explicitly with `close`. When the broadcaster recreates its transports,
+/// the producer is produced again with a new id, keeping its track, its
+/// encoding parameters and whether it is paused.
+#[derive(Clone)]
+pub struct ProducerHandle {
+    shared: Pin<Arc<dyn ProducerOwner>>,
+}
+
+impl ProducerHandle {
+    pub(crate) fn new(shared: Pin<Arc<dyn ProducerOwner>>) -> Self {
+        ProducerHandle { shared }
+    }
+
+    /// Id of the producer returned by `Signaller::on_produce`. A new id is
+    /// assigned when transports are recreated. Like `kind` and
+    /// `rtp_parameters`, it is kept once the producer is closed, and is only
+    /// None if the producer was never produced.
+    pub fn id(&self) -> Option<ProducerId> {
+        self.shared.producer().id()
+    }
+
+    pub fn kind(&self) -> Option<MediaKind> {
+        self.shared.producer().kind()
+    }
+
+    /// RTP parameters the producer was produced with.
+    pub fn rtp_parameters(&self) -> Option<RtpParameters> {
+        self.shared.producer().rtp_parameters()
+    }
+
+    /// Stats of the RTP streams sent by the producer and of the transport
+    /// they are sent on.
+    pub async fn get_stats(&self) -> Result<StatsReport, BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || shared.producer().get_stats()
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Change the bitrate, framerate or resolution of the encodings of a
+    /// video producer without producing it again, e.g. when viewers report a
+    /// poor network. Encodings are given in the order they were produced
+    /// with, and encodings past the end of the list are left unchanged.
+    pub async fn set_encoding_parameters(
+        &self,
+        encoding_parameters: Vec<EncodingParameters>,
+    ) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || {
+                shared
+                    .producer()
+                    .set_encoding_parameters(serde_json::to_value(&encoding_parameters).unwrap())
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Only send the spatial layers up to the given one, of a video producer
+    /// with simulcast or SVC encodings.
+    pub async fn set_max_spatial_layer(&self, spatial_layer: u8) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || shared.producer().set_max_spatial_layer(spatial_layer)
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Stop sending the track until resumed, and notify the signaller so that
+    /// the server pauses the producer too.
+    pub async fn pause(&self) -> Result<(), BroadcasterError> {
+        self.shared.producer().pause().await
+    }
+
+    pub async fn resume(&self) -> Result<(), BroadcasterError> {
+        self.shared.producer().resume().await
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.producer().is_paused()
+    }
+
+    /// Close the producer and release its track, along with the capture
+    /// device or source it sends, waiting for the signaller to be notified.
+    /// Other methods fail with `BroadcasterError::ProducerClosed` afterwards.
+    pub async fn close(&self) -> Result<(), BroadcasterError> {
+        let shared = self.shared.clone();
+        self.shared
+            .producer()
+            .close_with(move || shared.release())
+            .await
+    }
+}
+
+// Positive while ALSA producers are alive, negative while foreign audio
+// producers are. Counted for the whole process rather than per broadcaster,
+// since the native peer connection factory, which owns the audio device
+// module, is created once and shared by every broadcaster.
+static AUDIO_PRODUCERS: AtomicIsize = AtomicIsize::new(0);
+
+/// Kind of audio sent by a WebRTC audio producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AudioProducerKind {
+    Alsa,
+    Foreign,
+}
+
+/// Held by an audio producer handle while it is alive. The audio device module
+/// of the process delivers recorded audio to every WebRTC audio stream of
+/// every broadcaster, so ALSA and foreign audio producers cannot be alive at
+/// the same time. Plain producers are not affected and hold no guard.
+pub(crate) struct AudioProducerGuard {
+    step: isize,
+}
+impl AudioProducerGuard {
+    pub(crate) fn acquire(kind: AudioProducerKind) -> Result<Self, BroadcasterError> {
+        let step = match kind {
+            AudioProducerKind::Alsa => 1,
+            AudioProducerKind::Foreign => -1,
+        };
+        AUDIO_PRODUCERS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                if count * step >= 0 {
+                    Some(count + step)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| BroadcasterError::ConflictingAudioSources)?;
+        Ok(AudioProducerGuard { step })
+    }
+}
+impl Drop for AudioProducerGuard {
+    fn drop(&mut self) {
+        AUDIO_PRODUCERS.fetch_sub(self.step, Ordering::SeqCst);
+    }
+}
+
+/// Whatever the native track of a producer refers to and must outlive it, e.g.
+/// the frame source it polls.
+pub(crate) type TrackContext = Box<dyn Any + Send + Sync>;
+
+/// A native producer together with the track it sends and the options it was
+/// produced with.
+pub(crate) struct Producer {
+    // shared with blocking tasks closing the producer
+    state: Arc<Mutex<State>>,
+    signaller: ProducerSignaller,
+}
+struct State {
+    sys_producer: *mut sys::mediasoupclient_Producer,
+    sys_track: *mut sys::webrtc_MediaStreamTrackInterface,
+    // dropped after sys_track is deleted
+    track_context: Option<TrackContext>,
+    encodings: Option<CString>,
+    codec_options: CString,
+    codec: Option<CString>,
+    degradation_preference: Option<sys::DegradationPreference>,
+    // applied to every track sent
+    content_hint: Option<sys::ContentHint>,
+    // applied again when produced again
+    encoding_parameters: Option<CString>,
+    max_spatial_layer: Option<u8>,
+    // describe the latest native producer, kept after closing, and set before
+    // any handle exists since handles are only returned once produced
+    producer_id: Option<ProducerId>,
+    kind: Option<MediaKind>,
+    rtp_parameters: Option<RtpParameters>,
+    paused: bool,
+    closed: bool,
+}
+unsafe impl Send for State {}
+
+impl Producer {
+    pub(crate) fn new(signaller: ProducerSignaller) -> Self {
+        Producer {
+            state: Arc::new(Mutex::new(State {
+                sys_producer: ptr::null_mut(),
+                sys_track: ptr::null_mut(),
+                track_context: None,
+                encodings: None,
+                codec_options: CString::default(),
+                codec: None,
+                degradation_preference: None,
+                content_hint: None,
+                encoding_parameters: None,
+                max_spatial_layer: None,
+                producer_id: None,
+                kind: None,
+                rtp_parameters: None,
+                paused: false,
+                closed: false,
+            })),
+            signaller,
+        }
+    }
+
+    /// Produce the given track on the send transport, with the given video
+    /// options for video tracks. Takes ownership of the track reference, even
+    /// if producing fails.
+    pub(crate) fn produce(
+        &self,
+        sys_broadcaster: *mut sys::Broadcaster,
+        sys_track: *mut sys::webrtc_MediaStreamTrackInterface,
+        codec_options: serde_json::Value,
+        video_options: Option<&VideoProducerOptions>,
+    ) -> Result<(), BroadcasterError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.sys_track = sys_track;
+            state.codec_options = CString::new(codec_options.to_string()).unwrap();
+            if let Some(options) = video_options {
+                state.encodings = options
+                    .encodings_json()
+                    .map(|encodings| CString::new(encodings.to_string()).unwrap());
+                state.codec = options
+                    .codec
+                    .map(|codec| CString::new(codec.mime_type()).unwrap());
+                state.degradation_preference = options
+                    .degradation_preference
+                    .map(|preference| preference.to_sys());
+                state.content_hint = options.content_hint.map(|hint| hint.to_sys());
+            }
+            if let Some(content_hint) = state.content_hint {
+                unsafe { sys::track_set_content_hint(sys_track, content_hint) };
+            }
+        }
+        self.reproduce(sys_broadcaster)
+    }
+
+    /// Send the given track instead of the current one, keeping the same
+    /// producer. Takes ownership of the track reference and its context, even
+    /// if replacing fails.
+    pub(crate) fn replace_track(
+        &self,
+        sys_track: *mut sys::webrtc_MediaStreamTrackInterface,
+        track_context: Option<TrackContext>,
+    ) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(content_hint) = state.content_hint {
+            unsafe { sys::track_set_content_hint(sys_track, content_hint) };
+        }
+        let result = if state.closed {
+            Err(BroadcasterError::ProducerClosed)
+        } else {
+            unsafe {
+                try_sys(|error| sys::producer_replace_track(state.sys_producer, sys_track, error))
+            }
+        };
+        let (sys_track, _track_context) = match result {
+            Ok(_) => (
+                mem::replace(&mut state.sys_track, sys_track),
+                mem::replace(&mut state.track_context, track_context),
+            ),
+            Err(_) => (sys_track, track_context),
+        };
+        log::trace!("track delete {:?}", &sys_track);
+        unsafe { sys::track_delete(sys_track) };
+        result.map(|_| ())
+    }
+
+    pub(crate) fn id(&self) -> Option<ProducerId> {
+        self.state.lock().unwrap().producer_id.clone()
+    }
+
+    pub(crate) fn kind(&self) -> Option<MediaKind> {
+        self.state.lock().unwrap().kind.clone()
+    }
+
+    pub(crate) fn rtp_parameters(&self) -> Option<RtpParameters> {
+        self.state.lock().unwrap().rtp_parameters.clone()
+    }
+
+    pub(crate) fn get_stats(&self) -> Result<StatsReport, BroadcasterError> {
+        let state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(BroadcasterError::ProducerClosed);
+        }
+        let stats = unsafe {
+            unmarshal_str(try_sys(|error| {
+                sys::producer_marshal_stats(state.sys_producer, error)
+            })?)
+        };
+        StatsReport::from_json(serde_json::from_str(&stats)?).map_err(BroadcasterError::from)
+    }
+
+    /// Update the encodings of the producer with the given JSON array of
+    /// `EncodingParameters`, in the order the encodings were produced with.
+    pub(crate) fn set_encoding_parameters(
+        &self,
+        encoding_parameters: serde_json::Value,
+    ) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(BroadcasterError::ProducerClosed);
+        }
+        let encoding_parameters = CString::new(encoding_parameters.to_string()).unwrap();
+        unsafe {
+            try_sys(|error| {
+                sys::producer_set_encoding_parameters(
+                    state.sys_producer,
+                    encoding_parameters.as_ptr(),
+                    error,
+                )
+            })?;
+        }
+        state.encoding_parameters = Some(encoding_parameters);
+        Ok(())
+    }
+
+    pub(crate) fn set_max_spatial_layer(&self, spatial_layer: u8) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(BroadcasterError::ProducerClosed);
+        }
+        unsafe {
+            try_sys(|error| {
+                sys::producer_set_max_spatial_layer(state.sys_producer, spatial_layer, error)
+            })?;
+        }
+        state.max_spatial_layer = Some(spatial_layer);
+        Ok(())
+    }
+
+    /// Stop sending the track until resumed. The producer stays paused when
+    /// it is produced again.
+    pub(crate) async fn pause(&self) -> Result<(), BroadcasterError> {
+        self.set_paused(true).await
+    }
+
+    pub(crate) async fn resume(&self) -> Result<(), BroadcasterError> {
+        self.set_paused(false).await
+    }
+
+    async fn set_paused(&self, paused: bool) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        let producer_id = tokio::task::spawn_blocking({
+            let state = self.state.clone();
+            move || {
+                let mut state = state.lock().unwrap();
+                if state.closed {
+                    return Err(BroadcasterError::ProducerClosed);
+                }
+                if state.paused == paused {
+                    return Ok(None);
+                }
+                state.paused = paused;
+                unsafe {
+                    if paused {
+                        sys::producer_pause(state.sys_producer);
+                    } else {
+                        sys::producer_resume(state.sys_producer);
+                    }
+                }
+                Ok(state.producer_id.clone())
+            }
+        })
+        .await
+        .unwrap()?;
+        match producer_id {
+            Some(producer_id) if paused => self.signaller.paused(producer_id),
+            Some(producer_id) => self.signaller.resumed(producer_id),
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Close the producer and release its track, waiting for the signaller to
+    /// be notified. The producer is not produced again afterwards. `release`
+    /// is run first on the same blocking thread, e.g. to release native
+    /// objects referring to the track.
+    pub(crate) async fn close_with(
+        &self,
+        release: impl FnOnce() + Send + 'static,
+    ) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        let producer_id = tokio::task::spawn_blocking({
+            let state = self.state.clone();
+            move || {
+                release();
+                state.lock().unwrap().close()
+            }
+        })
+        .await
+        .unwrap();
+        match producer_id {
+            Some(producer_id) => self.signaller.close(producer_id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        let state = self.state.lock().unwrap();
+        if let (false, Some(producer_id)) = (state.closed, &state.producer_id) {
+            self.signaller.closed(producer_id.clone());
+        }
+    }
+}
+
+impl Reproduce for Producer {
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        Some(self)
+    }
+
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Ok(());
+        }
+        let sys_producer = unsafe {
+            try_sys(|error| {
+                sys::producer_new(
+                    sys_broadcaster,
+                    state.sys_track,
+                    state
+                        .encodings
+                        .as_ref()
+                        .map_or(ptr::null(), |encodings| encodings.as_ptr()),
+                    state.codec_options.as_ptr(),
+                    state
+                        .codec
+                        .as_ref()
+                        .map_or(ptr::null(), |codec| codec.as_ptr()),
+                    error,
+                )
+            })?
+        };
+        log::trace!("producer new {:?}", sys_producer);
+        let (kind, rtp_parameters) = match unsafe { describe_sys_producer(sys_producer) } {
+            Ok(description) => description,
+            Err(e) => {
+                log::trace!("producer delete {:?}", sys_producer);
+                unsafe { sys::producer_delete(sys_producer) };
+                return Err(e);
+            }
+        };
+        if !state.sys_producer.is_null() {
+            log::trace!("producer delete {:?}", &state.sys_producer);
+            unsafe { sys::producer_delete(state.sys_producer) };
+        }
+        state.sys_producer = sys_producer;
+        let producer_id =
+            unsafe { ProducerId::from(unmarshal_str(sys::producer_marshal_id(sys_producer))) };
+        if let Some(preference) = state.degradation_preference {
+            let result = unsafe {
+                try_sys(|error| {
+                    sys::producer_set_degradation_preference(sys_producer, preference, error)
+                })
+            };
+            if let Err(e) = result {
+                log::warn!("failed to set degradation preference: {}", e);
+            }
+        }
+        if let Some(encoding_parameters) = &state.encoding_parameters {
+            let result = unsafe {
+                try_sys(|error| {
+                    sys::producer_set_encoding_parameters(
+                        sys_producer,
+                        encoding_parameters.as_ptr(),
+                        error,
+                    )
+                })
+            };
+            if let Err(e) = result {
+                log::warn!("failed to set encoding parameters again: {}", e);
+            }
+        }
+        if let Some(spatial_layer) = state.max_spatial_layer {
+            let result = unsafe {
+                try_sys(|error| {
+                    sys::producer_set_max_spatial_layer(sys_producer, spatial_layer, error)
+                })
+            };
+            if let Err(e) = result {
+                log::warn!("failed to set max spatial layer again: {}", e);
+            }
+        }
+        if state.paused {
+            unsafe { sys::producer_pause(sys_producer) };
+            self.signaller.paused(producer_id.clone());
+        }
+        state.producer_id = Some(producer_id);
+        state.kind = Some(kind);
+        state.rtp_parameters = Some(rtp_parameters);
+        Ok(())
+    }
+}
+
+/// Kind and RTP parameters of a native producer.
+unsafe fn describe_sys_producer(
+    sys_producer: *mut sys::mediasoupclient_Producer,
+) -> Result<(MediaKind, RtpParameters), BroadcasterError> {
+    let kind = unmarshal_str(sys::producer_marshal_kind(sys_producer));
+    let kind = kind
+        .parse()
+        .map_err(|_| BroadcasterError::Native(format!("invalid producer kind: {}", kind)))?;
+    let rtp_parameters = serde_json::from_str(&unmarshal_str(
+        sys::producer_marshal_rtp_parameters(sys_producer),
+    ))?;
+    Ok((kind, rtp_parameters))
+}
+
+impl State {
+    /// Delete the producer and its track, returning the id of the producer
+    /// unless it was already closed.
+    fn close(&mut self) -> Option<ProducerId> {
+        if self.closed {
+            return None;
+        }
+        self.closed = true;
+        if !self.sys_producer.is_null() {
+            log::trace!("producer delete {:?}", &self.sys_producer);
+            unsafe { sys::producer_delete(self.sys_producer) };
+            self.sys_producer = ptr::null_mut();
+        }
+        if !self.sys_track.is_null() {
+            log::trace!("track delete {:?}", &self.sys_track);
+            unsafe { sys::track_delete(self.sys_track) };
+            self.sys_track = ptr::null_mut();
+        }
+        self.track_context = None;
+        self.producer_id.clone()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,221 @@
+//! Typed subset of the WebRTC statistics reported by libmediasoupclient. Stats
+//! not collected by WebRTC are left `None`.
+
+use serde::{Deserialize, Deserializer};
+
+/// Stats of the objects involved in a transport, producer or data channel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsReport {
+    pub outbound_rtp: Vec<OutboundRtpStats>,
+    pub remote_inbound_rtp: Vec<RemoteInboundRtpStats>,
+    pub inbound_rtp: Vec<InboundRtpStats>,
+    pub media_sources: Vec<MediaSourceStats>,
+    pub candidate_pairs: Vec<CandidatePairStats>,
+    pub transports: Vec<TransportStats>,
+    pub data_channels: Vec<DataChannelStats>,
+}
+
+/// Stats of both transports of a broadcaster.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BroadcasterStats {
+    pub send_transport: StatsReport,
+    pub recv_transport: StatsReport,
+}
+
+/// Stats of an RTP stream sent by a producer, one per encoding.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutboundRtpStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub ssrc: Option<u32>,
+    pub kind: Option<String>,
+    pub rid: Option<String>,
+    pub media_source_id: Option<String>,
+    pub packets_sent: Option<u32>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_sent: Option<u64>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub retransmitted_packets_sent: Option<u64>,
+    /// Bitrate the encoder is targeting, in bits per second.
+    pub target_bitrate: Option<f64>,
+    pub frames_encoded: Option<u32>,
+    pub key_frames_encoded: Option<u32>,
+    pub frames_sent: Option<u32>,
+    /// Total time spent encoding, in seconds.
+    pub total_encode_time: Option<f64>,
+    pub frame_width: Option<u32>,
+    pub frame_height: Option<u32>,
+    pub frames_per_second: Option<f64>,
+    /// Why the resolution or framerate is being reduced: "none", "cpu",
+    /// "bandwidth" or "other".
+    pub quality_limitation_reason: Option<String>,
+    pub encoder_implementation: Option<String>,
+    pub nack_count: Option<u32>,
+    pub pli_count: Option<u32>,
+    pub fir_count: Option<u32>,
+}
+
+/// Stats of an RTP stream sent by a producer, as reported back by the server.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RemoteInboundRtpStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub ssrc: Option<u32>,
+    pub kind: Option<String>,
+    /// Id of the `OutboundRtpStats` of the stream.
+    pub local_id: Option<String>,
+    pub packets_lost: Option<i32>,
+    /// Fraction of packets lost since the previous report, in [0, 1].
+    pub fraction_lost: Option<f64>,
+    /// Jitter in seconds.
+    pub jitter: Option<f64>,
+    /// Round trip time in seconds.
+    pub round_trip_time: Option<f64>,
+}
+
+/// Stats of an RTP stream received by a consumer.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InboundRtpStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub ssrc: Option<u32>,
+    pub kind: Option<String>,
+    pub packets_received: Option<u32>,
+    pub packets_lost: Option<i32>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_received: Option<u64>,
+    /// Jitter in seconds.
+    pub jitter: Option<f64>,
+    pub frames_decoded: Option<u32>,
+    pub frames_dropped: Option<u32>,
+    pub frame_width: Option<u32>,
+    pub frame_height: Option<u32>,
+    pub frames_per_second: Option<f64>,
+}
+
+/// Stats of the track sent by a producer, before encoding. Comparing
+/// `frames` with `OutboundRtpStats::frames_encoded` gives the number of frames
+/// dropped by the encoder.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MediaSourceStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub kind: Option<String>,
+    pub track_identifier: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frames: Option<u32>,
+    pub frames_per_second: Option<u32>,
+    pub audio_level: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CandidatePairStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub transport_id: Option<String>,
+    pub state: Option<String>,
+    pub nominated: Option<bool>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_sent: Option<u64>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_received: Option<u64>,
+    /// Round trip time of the latest STUN request, in seconds.
+    pub current_round_trip_time: Option<f64>,
+    /// Estimated bandwidth available for sending, in bits per second.
+    pub available_outgoing_bitrate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TransportStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_sent: Option<u64>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_received: Option<u64>,
+    pub dtls_state: Option<String>,
+    /// Id of the `CandidatePairStats` in use.
+    pub selected_candidate_pair_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DataChannelStats {
+    pub id: String,
+    #[serde(rename = "timestamp")]
+    pub timestamp_us: f64,
+    pub label: Option<String>,
+    pub protocol: Option<String>,
+    /// SCTP stream id of the data channel.
+    pub data_channel_identifier: Option<i32>,
+    pub state: Option<String>,
+    pub messages_sent: Option<u32>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_sent: Option<u64>,
+    pub messages_received: Option<u32>,
+    #[serde(deserialize_with = "number_as_u64")]
+    pub bytes_received: Option<u64>,
+}
+
+impl StatsReport {
+    /// Parse a JSON array of RTCStats, skipping stats of other types.
+    pub(crate) fn from_json(json: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let stats: Vec<serde_json::Value> = serde_json::from_value(json)?;
+        let mut report = StatsReport::default();
+        for stats in stats {
+            let kind = stats
+                .get("type")
+                .and_then(|kind| kind.as_str())
+                .map(str::to_owned);
+            match kind.as_deref() {
+                Some("outbound-rtp") => report.outbound_rtp.push(serde_json::from_value(stats)?),
+                Some("remote-inbound-rtp") => report
+                    .remote_inbound_rtp
+                    .push(serde_json::from_value(stats)?),
+                Some("inbound-rtp") => report.inbound_rtp.push(serde_json::from_value(stats)?),
+                Some("media-source") => report.media_sources.push(serde_json::from_value(stats)?),
+                Some("candidate-pair") => {
+                    report.candidate_pairs.push(serde_json::from_value(stats)?)
+                }
+                Some("transport") => report.transports.push(serde_json::from_value(stats)?),
+                Some("data-channel") => report.data_channels.push(serde_json::from_value(stats)?),
+                _ => {}
+            }
+        }
+        Ok(report)
+    }
+
+    /// The stats of the given data channel and of the transport it is sent
+    /// on.
+    pub(crate) fn for_data_channel(self, stream_id: u16) -> Self {
+        StatsReport {
+            candidate_pairs: self.candidate_pairs,
+            transports: self.transports,
+            data_channels: self
+                .data_channels
+                .into_iter()
+                .filter(|stats| stats.data_channel_identifier == Some(stream_id as i32))
+                .collect(),
+            ..StatsReport::default()
+        }
+    }
+}
+
+/// WebRTC reports 64-bit counters as doubles, so that they are not truncated
+/// by JSON parsers.
+fn number_as_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.map(|value| value as u64))
+}
@@ -6,15 +6,18 @@ use std::str::FromStr;
 use derive_more::{From, Into};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, From, Into)]
 pub struct TransportId(String);
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, From, Into)]
 pub struct ProducerId(String);
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, From, Into)]
+pub struct ConsumerId(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, From, Into)]
 pub struct DataProducerId(String);
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, From, Into)]
 pub struct DataConsumerId(String);
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -45,7 +48,13 @@ pub struct WebRtcTransportOptions(serde_json::Value);
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
 pub struct DtlsParameters(serde_json::Value);
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+pub struct IceParameters(serde_json::Value);
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
 pub struct SctpStreamParameters(serde_json::Value);
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+pub struct PlainTransportOptions(serde_json::Value);
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+pub struct SrtpParameters(serde_json::Value);
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
 #[serde(rename_all = "camelCase")]
@@ -53,4 +62,17 @@ pub struct DataConsumerOptions {
     pub id: DataConsumerId,
     pub data_producer_id: DataProducerId,
     pub sctp_stream_parameters: serde_json::Value,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub protocol: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, From, Into)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumerOptions {
+    pub id: ConsumerId,
+    pub producer_id: ProducerId,
+    pub kind: MediaKind,
+    pub rtp_parameters: RtpParameters,
 }
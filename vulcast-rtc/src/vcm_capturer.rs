@@ -1,24 +1,127 @@
 use std::{
+    convert::TryFrom,
+    ffi::{CStr, CString},
+    fmt, mem,
+    ops::Deref,
     pin::Pin,
     ptr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
 };
 
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{sync::broadcast, time::Instant};
 use vulcast_rtc_sys as sys;
 
+use crate::broadcaster::{try_sys, BroadcasterError, BroadcasterEvent, ProducerSignaller};
+use crate::devices::{self, VideoCaptureCapability};
+use crate::foreign_producer::track_new_from_frame_source;
+use crate::frame_source::FrameSource;
+use crate::producer::{Producer, ProducerHandle, ProducerOwner, Reproduce, WeakReproduce};
+use crate::video_options::VideoProducerOptions;
+use crate::video_source::VideoSource;
+
 #[derive(Clone)]
 pub struct VcmCapturer {
-    _shared: Pin<Arc<Shared>>,
+    shared: Pin<Arc<Shared>>,
+    handle: ProducerHandle,
 }
 struct Shared {
-    state: Mutex<State>,
+    producer: Producer,
+    capability: VideoCaptureCapability,
+    unique_id: String,
+    // held on blocking threads while the track sent by the producer is
+    // switched, which is the only time device_state is written
+    switching: Mutex<()>,
+    // only locked briefly, since the monitoring task reads it from the runtime
+    device_state: Mutex<DeviceState>,
+    // polled by the fallback track, which is released with the producer
+    fallback: Option<Arc<dyn FrameSource>>,
+}
+enum DeviceState {
+    /// The frames of the device track are being counted.
+    Capturing(FrameMonitor),
+    /// The device stalled and the fallback source is being sent.
+    Lost,
+    /// The device has no failover, or its source was replaced.
+    Unmonitored,
 }
-unsafe impl Send for Shared {}
-unsafe impl Sync for Shared {}
-struct State {
-    sys_producer: *mut sys::mediasoupclient_Producer,
+
+/// Video capture device to open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VcmDevice {
+    Index(u32),
+    /// Unique id or name of the device, as listed by
+    /// `devices::list_video_devices`. Unlike indices, unique ids do not change
+    /// when devices are reconnected in a different order.
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct VcmOptions {
+    /// Device to capture from, or the first device which can be opened if
+    /// `None`.
+    pub device: Option<VcmDevice>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub video_type: VideoType,
+    /// Capture with the capability of the device closest to the requested
+    /// one, rather than the requested one.
+    pub best_match: bool,
+    /// How to handle the device stalling or being unplugged, or None to keep
+    /// sending whatever the device delivers.
+    pub failover: Option<VcmFailover>,
+    /// Options of the producer sending the captured video.
+    pub producer: VideoProducerOptions,
+}
+
+impl VcmOptions {
+    pub(crate) fn capability(&self) -> VideoCaptureCapability {
+        VideoCaptureCapability {
+            width: self.width,
+            height: self.height,
+            max_fps: self.fps,
+            video_type: self.video_type,
+            interlaced: false,
+        }
+    }
+}
+
+/// When the capture device stops delivering frames for `stall_timeout`, it is
+/// considered lost and the fallback source is sent on the same producer
+/// instead. The device is reopened once it is available again.
+#[derive(Clone)]
+pub struct VcmFailover {
+    pub stall_timeout: Duration,
+    /// Interval at which a lost device is looked for.
+    pub retry_interval: Duration,
+    /// Source sent while the device is lost, at the capability of the device.
+    /// Black frames are sent if None.
+    pub fallback: Option<Arc<dyn FrameSource>>,
+}
+impl Default for VcmFailover {
+    fn default() -> Self {
+        VcmFailover {
+            stall_timeout: Duration::from_secs(2),
+            retry_interval: Duration::from_secs(1),
+            fallback: None,
+        }
+    }
+}
+impl fmt::Debug for VcmFailover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VcmFailover")
+            .field("stall_timeout", &self.stall_timeout)
+            .field("retry_interval", &self.retry_interval)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "i32")]
 pub enum VideoType {
     Unknown = 0,
     I420,
@@ -32,41 +135,335 @@ pub enum VideoType {
     MJPEG,
     BGRA,
 }
+impl TryFrom<i32> for VideoType {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => VideoType::Unknown,
+            1 => VideoType::I420,
+            2 => VideoType::IYUV,
+            3 => VideoType::RGB24,
+            4 => VideoType::ARGB,
+            5 => VideoType::RGB565,
+            6 => VideoType::YUY2,
+            7 => VideoType::YV12,
+            8 => VideoType::UYVY,
+            9 => VideoType::MJPEG,
+            10 => VideoType::BGRA,
+            _ => return Err(format!("unknown video type {}", value)),
+        })
+    }
+}
 impl VcmCapturer {
-    pub fn new(
+    pub(crate) fn new(
         sys_broadcaster: *mut sys::Broadcaster,
-        device_idx: Option<i32>,
-        width: u32,
-        height: u32,
-        fps: u32,
-        video_type: VideoType,
-    ) -> Self {
+        signaller: ProducerSignaller,
+        options: VcmOptions,
+        event_tx: broadcast::Sender<BroadcasterEvent>,
+    ) -> Result<Self, BroadcasterError> {
+        let mut capability = options.capability();
+        let (sys_track, unique_id) = unsafe {
+            track_new_from_vcm_capturer(
+                options.device.as_ref(),
+                &mut capability,
+                options.best_match,
+            )?
+        };
+        let device_state = match options.failover {
+            Some(_) => DeviceState::Capturing(unsafe { FrameMonitor::new(sys_track) }),
+            None => DeviceState::Unmonitored,
+        };
         let shared = Arc::pin(Shared {
-            state: Mutex::new(State {
-                sys_producer: ptr::null_mut(),
+            producer: Producer::new(signaller),
+            capability,
+            unique_id,
+            switching: Mutex::new(()),
+            device_state: Mutex::new(device_state),
+            fallback: options.failover.as_ref().map(|failover| {
+                failover
+                    .fallback
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(BlackFrameSource))
             }),
         });
-        unsafe {
-            let sys_producer = sys::producer_new_from_vcm_capturer(
-                sys_broadcaster,
-                device_idx.unwrap_or(-1),
-                width,
-                height,
-                fps,
-                video_type as i32,
-            );
-            let mut state = shared.state.lock().unwrap();
-            state.sys_producer = sys_producer;
+        shared.producer.produce(
+            sys_broadcaster,
+            sys_track,
+            json!({}),
+            Some(&options.producer),
+        )?;
+        if let Some(failover) = options.failover {
+            let shared = Arc::<Shared>::downgrade(&Pin::into_inner(shared.clone()));
+            tokio::spawn(monitor_device(shared, failover, event_tx));
+        }
+        let handle = ProducerHandle::new(shared.clone());
+        Ok(VcmCapturer { shared, handle })
+    }
+
+    /// Unique id of the device the capturer was produced with.
+    pub fn unique_id(&self) -> &str {
+        &self.shared.unique_id
+    }
+
+    /// The capability the device the capturer was produced with is capturing
+    /// with.
+    pub fn capability(&self) -> VideoCaptureCapability {
+        self.shared.capability
+    }
+
+    /// Send the given source instead of the capture device, keeping the same
+    /// producer. Failover stops once the source is replaced.
+    pub async fn replace_source(&self, source: VideoSource) -> Result<(), BroadcasterError> {
+        // spawn on blocking thread
+        tokio::task::spawn_blocking({
+            let shared = self.shared.clone();
+            move || {
+                let _switching = shared.switching.lock().unwrap();
+                let (sys_track, track_context) = source.track_new()?;
+                shared.producer.replace_track(sys_track, track_context)?;
+                shared.set_device_state(DeviceState::Unmonitored);
+                Ok(())
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    pub(crate) fn downgrade_reproduce(&self) -> WeakReproduce {
+        Arc::<Shared>::downgrade(&Pin::into_inner(self.shared.clone()))
+    }
+}
+
+impl Deref for VcmCapturer {
+    type Target = ProducerHandle;
+    fn deref(&self) -> &ProducerHandle {
+        &self.handle
+    }
+}
+
+impl Reproduce for Shared {
+    fn reproduce(&self, sys_broadcaster: *mut sys::Broadcaster) -> Result<(), BroadcasterError> {
+        self.producer.reproduce(sys_broadcaster)
+    }
+    #[cfg(feature = "metrics")]
+    fn media_producer(&self) -> Option<&Producer> {
+        Some(&self.producer)
+    }
+}
+
+impl ProducerOwner for Shared {
+    fn producer(&self) -> &Producer {
+        &self.producer
+    }
+    fn release(&self) {
+        // the monitor holds a reference to the device track
+        let _switching = self.switching.lock().unwrap();
+        self.set_device_state(DeviceState::Unmonitored);
+    }
+}
+
+impl Shared {
+    /// Set the device state while holding `switching`, releasing the previous
+    /// one after the lock, since deleting a monitor blocks.
+    fn set_device_state(&self, state: DeviceState) {
+        let previous = mem::replace(&mut *self.device_state.lock().unwrap(), state);
+        drop(previous);
+    }
+
+    /// Stop monitoring the device track and send the fallback source instead.
+    fn device_lost(&self) {
+        let _switching = self.switching.lock().unwrap();
+        if !matches!(
+            *self.device_state.lock().unwrap(),
+            DeviceState::Capturing(_)
+        ) {
+            return;
+        }
+        self.set_device_state(DeviceState::Lost);
+        let fallback = self.fallback.as_ref().unwrap();
+        let result = unsafe {
+            track_new_from_frame_source(
+                fallback,
+                self.capability.width,
+                self.capability.height,
+                self.capability.max_fps,
+            )
+        }
+        .and_then(|sys_track| self.producer.replace_track(sys_track, None));
+        if let Err(e) = result {
+            log::warn!("failed to switch to fallback source: {}", e);
         }
-        VcmCapturer { _shared: shared }
     }
+
+    /// Reopen the device if it is connected, and send it instead of the
+    /// fallback source.
+    fn restore_device(&self) -> Result<(), BroadcasterError> {
+        let connected = devices::list_video_devices()?
+            .iter()
+            .any(|device| device.unique_id == self.unique_id);
+        if !connected {
+            return Err(BroadcasterError::CaptureDeviceNotFound(
+                self.unique_id.clone(),
+            ));
+        }
+        let _switching = self.switching.lock().unwrap();
+        if !matches!(*self.device_state.lock().unwrap(), DeviceState::Lost) {
+            return Ok(());
+        }
+        let mut capability = self.capability;
+        let (sys_track, _) = unsafe {
+            track_new_from_vcm_capturer(
+                Some(&VcmDevice::Name(self.unique_id.clone())),
+                &mut capability,
+                false,
+            )?
+        };
+        let monitor = unsafe { FrameMonitor::new(sys_track) };
+        self.producer.replace_track(sys_track, None)?;
+        self.set_device_state(DeviceState::Capturing(monitor));
+        Ok(())
+    }
+}
+
+/// Watch the frames delivered by the device, switching to the fallback source
+/// when it stalls and back when it can be reopened.
+async fn monitor_device(
+    shared: Weak<Shared>,
+    failover: VcmFailover,
+    event_tx: broadcast::Sender<BroadcasterEvent>,
+) {
+    let mut last_frame_count = 0;
+    let mut last_frame = Instant::now();
+    loop {
+        let lost = match shared.upgrade() {
+            Some(shared) => match *shared.device_state.lock().unwrap() {
+                DeviceState::Capturing(_) => false,
+                DeviceState::Lost => true,
+                DeviceState::Unmonitored => return,
+            },
+            None => return,
+        };
+        if !lost {
+            tokio::time::sleep(failover.stall_timeout / 4).await;
+            let shared = match shared.upgrade() {
+                Some(shared) => shared,
+                None => return,
+            };
+            let frame_count = match &*shared.device_state.lock().unwrap() {
+                DeviceState::Capturing(monitor) => monitor.frame_count(),
+                _ => continue,
+            };
+            if frame_count != last_frame_count {
+                last_frame_count = frame_count;
+                last_frame = Instant::now();
+            } else if last_frame.elapsed() >= failover.stall_timeout {
+                log::warn!("capture device {} stalled", shared.unique_id);
+                let unique_id = shared.unique_id.clone();
+                tokio::task::spawn_blocking(move || shared.device_lost())
+                    .await
+                    .unwrap();
+                let _ = event_tx.send(BroadcasterEvent::CaptureDeviceLost { unique_id });
+            }
+        } else {
+            tokio::time::sleep(failover.retry_interval).await;
+            let shared = match shared.upgrade() {
+                Some(shared) => shared,
+                None => return,
+            };
+            let unique_id = shared.unique_id.clone();
+            let result = tokio::task::spawn_blocking(move || shared.restore_device())
+                .await
+                .unwrap();
+            match result {
+                Ok(()) => {
+                    log::info!("capture device {} restored", unique_id);
+                    last_frame_count = 0;
+                    last_frame = Instant::now();
+                    let _ = event_tx.send(BroadcasterEvent::CaptureDeviceRestored { unique_id });
+                }
+                Err(e) => log::debug!("capture device {} not restored: {}", unique_id, e),
+            }
+        }
+    }
+}
+
+/// Open a capture device, returning its track and unique id. The capability
+/// actually used is written back.
+pub(crate) unsafe fn track_new_from_vcm_capturer(
+    device: Option<&VcmDevice>,
+    capability: &mut VideoCaptureCapability,
+    best_match: bool,
+) -> Result<(*mut sys::webrtc_MediaStreamTrackInterface, String), BroadcasterError> {
+    let (device_name, device_idx) = match device {
+        Some(VcmDevice::Index(idx)) => (None, *idx as i32),
+        Some(VcmDevice::Name(name)) => {
+            // no device has a name or unique id containing NUL
+            let name = CString::new(name.as_str())
+                .map_err(|_| BroadcasterError::CaptureDeviceNotFound(name.clone()))?;
+            (Some(name), -1)
+        }
+        None => (None, -1),
+    };
+    let mut sys_capability = sys::VideoCapability {
+        width: capability.width,
+        height: capability.height,
+        max_fps: capability.max_fps,
+        video_type: capability.video_type as i32,
+        interlaced: capability.interlaced,
+    };
+    let mut unique_id_ptr = ptr::null_mut();
+    let sys_track = try_sys(|error| {
+        sys::track_new_from_vcm_capturer(
+            device_name
+                .as_ref()
+                .map_or(ptr::null(), |name| name.as_ptr()),
+            device_idx,
+            &mut sys_capability,
+            best_match,
+            &mut unique_id_ptr,
+            error,
+        )
+    })?;
+    let unique_id = CStr::from_ptr(unique_id_ptr).to_string_lossy().into_owned();
+    sys::cpp_unmarshal_str(unique_id_ptr);
+    *capability = VideoCaptureCapability {
+        width: sys_capability.width,
+        height: sys_capability.height,
+        max_fps: sys_capability.max_fps,
+        video_type: VideoType::try_from(sys_capability.video_type).unwrap_or(VideoType::Unknown),
+        interlaced: sys_capability.interlaced,
+    };
+    Ok((sys_track, unique_id))
 }
 
-impl Drop for State {
+/// Counts the frames of a video track.
+struct FrameMonitor {
+    sys_monitor: *mut sys::FrameMonitor,
+}
+unsafe impl Send for FrameMonitor {}
+unsafe impl Sync for FrameMonitor {}
+impl FrameMonitor {
+    unsafe fn new(sys_track: *mut sys::webrtc_MediaStreamTrackInterface) -> Self {
+        FrameMonitor {
+            sys_monitor: sys::frame_monitor_new(sys_track),
+        }
+    }
+    fn frame_count(&self) -> u64 {
+        unsafe { sys::frame_monitor_frame_count(self.sys_monitor) }
+    }
+}
+impl Drop for FrameMonitor {
     fn drop(&mut self) {
-        log::trace!("producer delete {:?}", &self.sys_producer);
-        unsafe {
-            sys::producer_delete(self.sys_producer);
+        unsafe { sys::frame_monitor_delete(self.sys_monitor) };
+    }
+}
+
+struct BlackFrameSource;
+impl FrameSource for BlackFrameSource {
+    fn next_frame(&self, _width: u32, _height: u32, _timestamp: i64, data: &mut [u8]) {
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[0, 0, 0, 255]);
         }
     }
 }
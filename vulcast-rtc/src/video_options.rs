@@ -0,0 +1,136 @@
+use serde::Serialize;
+use vulcast_rtc_sys as sys;
+
+/// Options of a video producer, independent of its source.
+#[derive(Debug, Clone, Default)]
+pub struct VideoProducerOptions {
+    /// Encodings to send, lowest quality first. Several encodings with
+    /// distinct `rid`s enable simulcast, and a single encoding with a
+    /// `scalability_mode` enables SVC. A single default encoding is sent if
+    /// empty.
+    pub encodings: Vec<RtpEncoding>,
+    /// Codec to send with, or the first video codec supported by the router
+    /// if `None`.
+    pub codec: Option<VideoCodecPreference>,
+    /// What to sacrifice first when bandwidth or CPU is limited. WebRTC
+    /// picks based on the content hint if `None`.
+    pub degradation_preference: Option<DegradationPreference>,
+    pub content_hint: Option<ContentHint>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DegradationPreference {
+    /// Degrade both framerate and resolution.
+    Balanced,
+    /// Lower the resolution to keep the framerate, e.g. for games.
+    MaintainFramerate,
+    /// Lower the framerate to keep the resolution.
+    MaintainResolution,
+}
+
+impl DegradationPreference {
+    pub(crate) fn to_sys(self) -> sys::DegradationPreference {
+        match self {
+            DegradationPreference::Balanced => sys::DegradationPreference_DEGRADATION_BALANCED,
+            DegradationPreference::MaintainFramerate => {
+                sys::DegradationPreference_DEGRADATION_MAINTAIN_FRAMERATE
+            }
+            DegradationPreference::MaintainResolution => {
+                sys::DegradationPreference_DEGRADATION_MAINTAIN_RESOLUTION
+            }
+        }
+    }
+}
+
+/// Kind of content of a video track, which tunes the encoder for it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ContentHint {
+    /// Fast motion such as games, where smoothness matters more than detail.
+    Motion,
+    /// Mostly static content where detail matters, such as screen captures.
+    Detail,
+    /// Text, which should stay legible even at a low framerate.
+    Text,
+}
+
+impl ContentHint {
+    pub(crate) fn to_sys(self) -> sys::ContentHint {
+        match self {
+            ContentHint::Motion => sys::ContentHint_CONTENT_HINT_MOTION,
+            ContentHint::Detail => sys::ContentHint_CONTENT_HINT_DETAIL,
+            ContentHint::Text => sys::ContentHint_CONTENT_HINT_TEXT,
+        }
+    }
+}
+
+/// Video codec to send with. The router must support it, otherwise producing
+/// fails with `BroadcasterError::CodecNotSupported`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VideoCodecPreference {
+    VP8,
+    VP9,
+    H264,
+    AV1,
+}
+
+impl VideoCodecPreference {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            VideoCodecPreference::VP8 => "video/VP8",
+            VideoCodecPreference::VP9 => "video/VP9",
+            VideoCodecPreference::H264 => "video/H264",
+            VideoCodecPreference::AV1 => "video/AV1",
+        }
+    }
+}
+
+/// Parameters of a single encoding of a video producer, following the
+/// mediasoup-client `RtpEncodingParameters`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RtpEncoding {
+    /// Simulcast stream id, e.g. "r0".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rid: Option<String>,
+    /// Maximum bitrate in bits per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bitrate: Option<u32>,
+    /// Factor to scale down the resolution of the track by, e.g. 2.0 to send
+    /// half the width and height.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_resolution_down_by: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_framerate: Option<f64>,
+    /// SVC scalability mode, e.g. "L1T3" for three temporal layers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scalability_mode: Option<String>,
+}
+
+impl VideoProducerOptions {
+    /// Encodings as a JSON array, or `None` to send a single default
+    /// encoding.
+    pub(crate) fn encodings_json(&self) -> Option<serde_json::Value> {
+        if self.encodings.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_value(&self.encodings).unwrap())
+        }
+    }
+}
+
+/// Changes to a single encoding of a live video producer. Fields left `None`
+/// keep their current value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodingParameters {
+    /// Maximum bitrate in bits per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bitrate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_framerate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_resolution_down_by: Option<f64>,
+    /// Whether the encoding is sent at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
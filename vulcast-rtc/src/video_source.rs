@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use vulcast_rtc_sys as sys;
+
+use crate::broadcaster::BroadcasterError;
+use crate::foreign_producer::track_new_from_frame_source;
+use crate::frame_source::FrameSource;
+use crate::producer::TrackContext;
+use crate::vcm_capturer::{track_new_from_vcm_capturer, VcmOptions};
+
+/// Source of a video producer, which can replace the source of a live
+/// producer without changing its `ProducerId`.
+#[derive(Clone)]
+pub enum VideoSource {
+    /// Frames polled from a frame source at the given frame rate.
+    FrameSource {
+        frame_source: Arc<dyn FrameSource>,
+        width: u32,
+        height: u32,
+        fps: u32,
+    },
+    /// Frames captured from a video device. Failover and producer options are
+    /// ignored.
+    Vcm(VcmOptions),
+}
+
+impl VideoSource {
+    /// Frame rate the source is expected to deliver frames at.
+    pub(crate) fn fps(&self) -> u32 {
+        match self {
+            VideoSource::FrameSource { fps, .. } => *fps,
+            VideoSource::Vcm(options) => options.fps,
+        }
+    }
+
+    pub(crate) fn track_new(
+        &self,
+    ) -> Result<
+        (
+            *mut sys::webrtc_MediaStreamTrackInterface,
+            Option<TrackContext>,
+        ),
+        BroadcasterError,
+    > {
+        match self {
+            VideoSource::FrameSource {
+                frame_source,
+                width,
+                height,
+                fps,
+            } => {
+                // boxed so that the track can refer to it after it is moved
+                let frame_source = Box::new(frame_source.clone());
+                let sys_track =
+                    unsafe { track_new_from_frame_source(&frame_source, *width, *height, *fps)? };
+                Ok((sys_track, Some(frame_source as TrackContext)))
+            }
+            VideoSource::Vcm(options) => {
+                let mut capability = options.capability();
+                let (sys_track, _) = unsafe {
+                    track_new_from_vcm_capturer(
+                        options.device.as_ref(),
+                        &mut capability,
+                        options.best_match,
+                    )?
+                };
+                Ok((sys_track, None))
+            }
+        }
+    }
+}